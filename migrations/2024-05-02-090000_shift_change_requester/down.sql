@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE Shift_Changes DROP COLUMN IF EXISTS created_at;
+ALTER TABLE Shift_Changes DROP COLUMN IF EXISTS requested_by;
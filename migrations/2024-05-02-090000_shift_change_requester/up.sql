@@ -0,0 +1,5 @@
+-- Track who filed each shift change and when
+ALTER TABLE Shift_Changes ADD COLUMN requested_by INT REFERENCES Employees(id);
+ALTER TABLE Shift_Changes ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT NOW();
+
+UPDATE Shift_Changes sc SET requested_by = s.employee_id FROM Schedules s WHERE s.id = sc.scheduler_id;
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS shift_changes_open_scheduler_id;
+ALTER TABLE Shift_Changes ADD CONSTRAINT shift_changes_scheduler_id_key UNIQUE (scheduler_id);
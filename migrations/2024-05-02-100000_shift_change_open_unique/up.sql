@@ -0,0 +1,3 @@
+-- Only one open (pending) change request per schedule entry, closed ones are kept as history
+ALTER TABLE Shift_Changes DROP CONSTRAINT IF EXISTS shift_changes_scheduler_id_key;
+CREATE UNIQUE INDEX shift_changes_open_scheduler_id ON Shift_Changes(scheduler_id) WHERE status = 'pending';
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE Shift_Changes DROP COLUMN IF EXISTS approved_by_policy;
+ALTER TABLE Shift_Changes DROP COLUMN IF EXISTS counterpart_consent;
+ALTER TABLE Shift_Changes DROP COLUMN IF EXISTS swap_scheduler_id;
+DROP TABLE IF EXISTS Approval_Policies;
@@ -0,0 +1,17 @@
+-- Policies under which a shift change is approved without a manager
+CREATE TABLE IF NOT EXISTS Approval_Policies (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    name TEXT NOT NULL,
+    same_shift_type BOOLEAN NOT NULL DEFAULT TRUE,
+    require_consent BOOLEAN NOT NULL DEFAULT TRUE,
+    require_no_violations BOOLEAN NOT NULL DEFAULT TRUE,
+    min_hours_ahead INT,
+    active BOOLEAN NOT NULL DEFAULT TRUE
+);
+
+ALTER TABLE Shift_Changes ADD COLUMN swap_scheduler_id INT REFERENCES Schedules(id);
+ALTER TABLE Shift_Changes ADD COLUMN counterpart_consent BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE Shift_Changes ADD COLUMN approved_by_policy INT REFERENCES Approval_Policies(id);
+
+-- Shipped disabled, managers switch it on once they agree with it
+INSERT INTO Approval_Policies(name, same_shift_type, require_consent, require_no_violations, min_hours_ahead, active) VALUES ('Trivial swap', TRUE, TRUE, TRUE, 48, FALSE);
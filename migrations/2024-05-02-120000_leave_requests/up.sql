@@ -0,0 +1,17 @@
+CREATE TABLE IF NOT EXISTS Leave_Requests (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    employee_id INT NOT NULL,
+    leave_type TEXT NOT NULL,
+    start_date DATE NOT NULL,
+    end_date DATE NOT NULL,
+    reason TEXT,
+    status TEXT NOT NULL DEFAULT 'pending',
+    manager_comment TEXT,
+    reviewed_by INT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY(employee_id) REFERENCES Employees(id),
+    FOREIGN KEY(reviewed_by) REFERENCES Employees(id),
+    CHECK (start_date <= end_date)
+);
+
+CREATE INDEX leave_requests_employee_dates ON Leave_Requests(employee_id, start_date, end_date);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS Leave_Balance_History;
+DROP TABLE IF EXISTS Leave_Entitlements;
@@ -0,0 +1,27 @@
+-- Leave entitlement and current balance of an employee for one leave type
+CREATE TABLE IF NOT EXISTS Leave_Entitlements (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    employee_id INT NOT NULL,
+    leave_type TEXT NOT NULL,
+    accrual_per_month DOUBLE PRECISION NOT NULL DEFAULT 0,
+    carry_over_limit DOUBLE PRECISION,
+    balance DOUBLE PRECISION NOT NULL DEFAULT 0,
+    FOREIGN KEY(employee_id) REFERENCES Employees(id),
+    UNIQUE (employee_id, leave_type)
+);
+
+-- Every change of a balance: accrual, usage, refund, adjustment, expiry
+CREATE TABLE IF NOT EXISTS Leave_Balance_History (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    entitlement_id INT NOT NULL,
+    kind TEXT NOT NULL,
+    change_days DOUBLE PRECISION NOT NULL,
+    balance_after DOUBLE PRECISION NOT NULL,
+    period DATE,
+    note TEXT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY(entitlement_id) REFERENCES Leave_Entitlements(id)
+);
+
+-- Accrual and year-end expiry happen at most once per period
+CREATE UNIQUE INDEX leave_balance_history_period ON Leave_Balance_History(entitlement_id, kind, period) WHERE period IS NOT NULL;
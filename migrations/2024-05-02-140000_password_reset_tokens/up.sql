@@ -0,0 +1,12 @@
+-- Single-use password reset tokens, only a bcrypt hash of the secret part is stored
+CREATE TABLE IF NOT EXISTS Password_Reset_Tokens (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    employee_id INT NOT NULL,
+    token_hash TEXT NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    used_at TIMESTAMP,
+    issued_by INT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY(employee_id) REFERENCES Employees(id),
+    FOREIGN KEY(issued_by) REFERENCES Employees(id)
+);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE Employees DROP COLUMN IF EXISTS anonymized_at;
+ALTER TABLE Employees DROP COLUMN IF EXISTS deactivated_at;
+ALTER TABLE Employees DROP COLUMN IF EXISTS active;
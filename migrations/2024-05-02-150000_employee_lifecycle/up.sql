@@ -0,0 +1,4 @@
+-- Deactivated employees keep their history but are no longer rostered or listed
+ALTER TABLE Employees ADD COLUMN active BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE Employees ADD COLUMN deactivated_at TIMESTAMP;
+ALTER TABLE Employees ADD COLUMN anonymized_at TIMESTAMP;
@@ -0,0 +1,15 @@
+-- Login sessions backing the refresh tokens, only a bcrypt hash of the secret part is stored
+CREATE TABLE IF NOT EXISTS Sessions (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    employee_id INT NOT NULL,
+    refresh_token_hash TEXT NOT NULL,
+    -- hash of the refresh token replaced by the last rotation, used to spot a replayed token
+    previous_token_hash TEXT,
+    expires_at TIMESTAMP NOT NULL,
+    revoked_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY(employee_id) REFERENCES Employees(id)
+);
+
+CREATE INDEX IF NOT EXISTS sessions_open_idx ON Sessions(employee_id) WHERE revoked_at IS NULL;
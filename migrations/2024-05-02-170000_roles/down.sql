@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE Employees DROP CONSTRAINT IF EXISTS employees_role_check;
+UPDATE Employees SET role = 'SOC' WHERE role = 'Employee';
@@ -0,0 +1,6 @@
+-- Roles are now a fixed set, see src/permissions.rs. "SOC" was the only employee role so far
+UPDATE Employees SET role = 'Employee' WHERE role = 'SOC';
+
+-- NOT VALID: rows with any other legacy value are left alone and simply get no permission
+ALTER TABLE Employees ADD CONSTRAINT employees_role_check
+    CHECK (role IN ('Admin', 'Manager', 'TeamLead', 'Employee', 'Auditor')) NOT VALID;
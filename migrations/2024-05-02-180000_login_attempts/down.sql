@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS Lockout_Events;
+DROP TABLE IF EXISTS Login_Attempts;
@@ -0,0 +1,28 @@
+-- Every login attempt, failures drive the progressive delay and the lockouts
+CREATE TABLE IF NOT EXISTS Login_Attempts (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    -- lower-cased email as typed, it may not belong to any employee
+    email TEXT NOT NULL,
+    ip TEXT,
+    success BOOLEAN NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS login_attempts_email_idx ON Login_Attempts(email, created_at);
+CREATE INDEX IF NOT EXISTS login_attempts_ip_idx ON Login_Attempts(ip, created_at);
+
+-- Audit trail of lockouts and unlocks
+CREATE TABLE IF NOT EXISTS Lockout_Events (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    kind TEXT NOT NULL,
+    email TEXT,
+    employee_id INT,
+    ip TEXT,
+    -- who unlocked the account, NULL for automatic events
+    actor_id INT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY(employee_id) REFERENCES Employees(id),
+    FOREIGN KEY(actor_id) REFERENCES Employees(id)
+);
+
+CREATE INDEX IF NOT EXISTS lockout_events_email_idx ON Lockout_Events(email, created_at);
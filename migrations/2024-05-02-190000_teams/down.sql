@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE Schedules DROP COLUMN IF EXISTS team_id;
+DROP TABLE IF EXISTS Team_Shifts;
+DROP TABLE IF EXISTS Team_Members;
+DROP TABLE IF EXISTS Teams;
@@ -0,0 +1,31 @@
+-- Teams roster their members on their own shifts, see src/models/teams.rs
+CREATE TABLE IF NOT EXISTS Teams (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    manager_id INT,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    FOREIGN KEY(manager_id) REFERENCES Employees(id)
+);
+
+CREATE TABLE IF NOT EXISTS Team_Members (
+    team_id INT NOT NULL,
+    employee_id INT NOT NULL,
+    PRIMARY KEY(team_id, employee_id),
+    FOREIGN KEY(team_id) REFERENCES Teams(id) ON DELETE CASCADE,
+    FOREIGN KEY(employee_id) REFERENCES Employees(id)
+);
+
+-- Which shift fills each slot of the generator (S, C, D, H) for a team,
+-- slots without a row use the default shifts
+CREATE TABLE IF NOT EXISTS Team_Shifts (
+    team_id INT NOT NULL,
+    code TEXT NOT NULL CHECK (code IN ('S', 'C', 'D', 'H')),
+    shift_id INT NOT NULL,
+    PRIMARY KEY(team_id, code),
+    FOREIGN KEY(team_id) REFERENCES Teams(id) ON DELETE CASCADE,
+    FOREIGN KEY(shift_id) REFERENCES Shifts(id)
+);
+
+-- Schedules generated for a team, NULL for the ones made before teams existed
+ALTER TABLE Schedules ADD COLUMN team_id INT REFERENCES Teams(id);
+CREATE INDEX IF NOT EXISTS schedules_team_idx ON Schedules(team_id, data);
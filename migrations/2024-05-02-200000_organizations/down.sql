@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE Teams DROP CONSTRAINT IF EXISTS teams_organization_name_key;
+ALTER TABLE Teams ADD CONSTRAINT teams_name_key UNIQUE(name);
+ALTER TABLE Approval_Policies DROP COLUMN IF EXISTS organization_id;
+ALTER TABLE Leave_Requests DROP COLUMN IF EXISTS organization_id;
+ALTER TABLE Shift_Changes DROP COLUMN IF EXISTS organization_id;
+ALTER TABLE Schedules DROP COLUMN IF EXISTS organization_id;
+ALTER TABLE Teams DROP COLUMN IF EXISTS organization_id;
+ALTER TABLE Shifts DROP COLUMN IF EXISTS organization_id;
+ALTER TABLE Employees DROP COLUMN IF EXISTS organization_id;
+DROP TABLE IF EXISTS Organizations;
@@ -0,0 +1,37 @@
+-- Each customer organization owns its employees, shifts, teams, schedules and requests.
+-- Everything that existed before goes to the default organization (id 1)
+CREATE TABLE IF NOT EXISTS Organizations (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO Organizations(id, name) OVERRIDING SYSTEM VALUE VALUES (1, 'Default') ON CONFLICT DO NOTHING;
+SELECT setval(pg_get_serial_sequence('organizations', 'id'), (SELECT MAX(id) FROM Organizations));
+
+ALTER TABLE Employees ADD COLUMN organization_id INT NOT NULL DEFAULT 1 REFERENCES Organizations(id);
+ALTER TABLE Shifts ADD COLUMN organization_id INT NOT NULL DEFAULT 1 REFERENCES Organizations(id);
+ALTER TABLE Teams ADD COLUMN organization_id INT NOT NULL DEFAULT 1 REFERENCES Organizations(id);
+ALTER TABLE Schedules ADD COLUMN organization_id INT NOT NULL DEFAULT 1 REFERENCES Organizations(id);
+ALTER TABLE Shift_Changes ADD COLUMN organization_id INT NOT NULL DEFAULT 1 REFERENCES Organizations(id);
+ALTER TABLE Leave_Requests ADD COLUMN organization_id INT NOT NULL DEFAULT 1 REFERENCES Organizations(id);
+ALTER TABLE Approval_Policies ADD COLUMN organization_id INT NOT NULL DEFAULT 1 REFERENCES Organizations(id);
+
+-- new rows always name their organization
+ALTER TABLE Employees ALTER COLUMN organization_id DROP DEFAULT;
+ALTER TABLE Shifts ALTER COLUMN organization_id DROP DEFAULT;
+ALTER TABLE Teams ALTER COLUMN organization_id DROP DEFAULT;
+ALTER TABLE Schedules ALTER COLUMN organization_id DROP DEFAULT;
+ALTER TABLE Shift_Changes ALTER COLUMN organization_id DROP DEFAULT;
+ALTER TABLE Leave_Requests ALTER COLUMN organization_id DROP DEFAULT;
+ALTER TABLE Approval_Policies ALTER COLUMN organization_id DROP DEFAULT;
+
+CREATE INDEX IF NOT EXISTS employees_organization_idx ON Employees(organization_id);
+CREATE INDEX IF NOT EXISTS shifts_organization_idx ON Shifts(organization_id);
+CREATE INDEX IF NOT EXISTS schedules_organization_idx ON Schedules(organization_id, data);
+CREATE INDEX IF NOT EXISTS shift_changes_organization_idx ON Shift_Changes(organization_id);
+CREATE INDEX IF NOT EXISTS leave_requests_organization_idx ON Leave_Requests(organization_id);
+
+-- team names only need to be unique inside an organization
+ALTER TABLE Teams DROP CONSTRAINT IF EXISTS teams_name_key;
+ALTER TABLE Teams ADD CONSTRAINT teams_organization_name_key UNIQUE(organization_id, name);
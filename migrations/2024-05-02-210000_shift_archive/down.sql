@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS shifts_organization_name_idx;
+ALTER TABLE Shifts DROP COLUMN IF EXISTS archived_at;
@@ -0,0 +1,5 @@
+-- Shifts still referenced by schedules are archived instead of deleted
+ALTER TABLE Shifts ADD COLUMN archived_at TIMESTAMP;
+
+-- a code is used by one live shift per organization, archived ones free it
+CREATE UNIQUE INDEX IF NOT EXISTS shifts_organization_name_idx ON Shifts(organization_id, name) WHERE archived_at IS NULL;
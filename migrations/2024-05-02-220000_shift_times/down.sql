@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE Shifts DROP COLUMN IF EXISTS crosses_midnight;
+ALTER TABLE Shifts DROP COLUMN IF EXISTS duration_minutes;
+ALTER TABLE Shifts ADD COLUMN duration INT;
+ALTER TABLE Shifts DROP CONSTRAINT IF EXISTS shifts_window_check;
+
+-- whole hours, a shift ending at midnight ends at 24
+ALTER TABLE Shifts ALTER COLUMN start_time TYPE INT USING EXTRACT(HOUR FROM start_time)::INT;
+ALTER TABLE Shifts ALTER COLUMN end_time TYPE INT USING CASE WHEN end_time = '00:00' THEN 24 ELSE EXTRACT(HOUR FROM end_time)::INT END;
+UPDATE Shifts SET duration = (end_time - start_time + 24) % 24;
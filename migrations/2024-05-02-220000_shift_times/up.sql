@@ -0,0 +1,16 @@
+-- Shift times become times of day with minute precision, the end is on the next day when it's not after the start
+-- the seeded shifts get the hours documented for the generator's slots
+UPDATE Shifts SET start_time = 6, end_time = 14 WHERE name = 'S' AND start_time = 0 AND end_time = 8;
+UPDATE Shifts SET start_time = 14, end_time = 22 WHERE name = 'C' AND start_time = 8 AND end_time = 16;
+UPDATE Shifts SET start_time = 22, end_time = 6 WHERE name = 'D' AND start_time = 16 AND end_time = 24;
+UPDATE Shifts SET start_time = 8, end_time = 18 WHERE name = 'H' AND start_time = 2 AND end_time = 12;
+
+ALTER TABLE Shifts ALTER COLUMN start_time TYPE TIME USING make_time(start_time % 24, 0, 0);
+ALTER TABLE Shifts ALTER COLUMN end_time TYPE TIME USING make_time(end_time % 24, 0, 0);
+ALTER TABLE Shifts ADD CONSTRAINT shifts_window_check CHECK (start_time <> end_time);
+
+-- derived from the times, a night shift ending at 06:00 lasts until 06:00 of the next day
+ALTER TABLE Shifts DROP COLUMN duration;
+ALTER TABLE Shifts ADD COLUMN duration_minutes INT NOT NULL
+    GENERATED ALWAYS AS (((EXTRACT(EPOCH FROM (end_time - start_time))::INT / 60) % 1440 + 1440) % 1440) STORED;
+ALTER TABLE Shifts ADD COLUMN crosses_midnight BOOLEAN NOT NULL GENERATED ALWAYS AS (end_time <= start_time) STORED;
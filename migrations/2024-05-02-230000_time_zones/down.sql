@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE Employees DROP COLUMN IF EXISTS time_zone;
+ALTER TABLE Teams DROP COLUMN IF EXISTS time_zone;
+ALTER TABLE Organizations DROP COLUMN IF EXISTS time_zone;
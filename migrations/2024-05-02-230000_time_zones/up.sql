@@ -0,0 +1,5 @@
+-- IANA time zones: shift times are wall-clock times of the team's zone, or of the organization's when the team has none.
+-- An employee's zone is only used to show them times
+ALTER TABLE Organizations ADD COLUMN time_zone TEXT NOT NULL DEFAULT 'UTC';
+ALTER TABLE Teams ADD COLUMN time_zone TEXT;
+ALTER TABLE Employees ADD COLUMN time_zone TEXT;
@@ -0,0 +1,10 @@
+-- Breaks inside a shift, counted from its start. Unpaid ones don't count as worked hours
+CREATE TABLE IF NOT EXISTS Shift_Breaks (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    shift_id INT NOT NULL,
+    start_offset_minutes INT NOT NULL CHECK (start_offset_minutes >= 0),
+    length_minutes INT NOT NULL CHECK (length_minutes > 0),
+    paid BOOLEAN NOT NULL DEFAULT FALSE,
+    FOREIGN KEY(shift_id) REFERENCES Shifts(id) ON DELETE CASCADE
+);
+CREATE INDEX IF NOT EXISTS shift_breaks_shift_idx ON Shift_Breaks(shift_id);
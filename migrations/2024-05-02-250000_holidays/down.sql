@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE Teams DROP COLUMN IF EXISTS region;
+DROP TABLE IF EXISTS Holidays;
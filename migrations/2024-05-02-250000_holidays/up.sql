@@ -0,0 +1,15 @@
+-- Public holidays of an organization, or only of the teams of a region when `region` is set
+CREATE TABLE IF NOT EXISTS Holidays (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    organization_id INT NOT NULL,
+    region TEXT,
+    date DATE NOT NULL,
+    name TEXT NOT NULL,
+    -- employees per slot that day, e.g. {"H": 0, "D": 2}, slots left out keep their usual staffing
+    staffing JSON,
+    pay_multiplier DOUBLE PRECISION NOT NULL DEFAULT 1.0 CHECK (pay_multiplier > 0),
+    FOREIGN KEY(organization_id) REFERENCES Organizations(id)
+);
+CREATE UNIQUE INDEX IF NOT EXISTS holidays_organization_region_date_idx ON Holidays(organization_id, COALESCE(region, ''), date);
+
+ALTER TABLE Teams ADD COLUMN region TEXT;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE IF EXISTS Skill_Requirements;
+DROP TABLE IF EXISTS Employee_Skills;
+DROP TABLE IF EXISTS Skills;
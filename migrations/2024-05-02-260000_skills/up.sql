@@ -0,0 +1,29 @@
+-- Skills and certifications an employee can hold, such as "L2" or a tool they are certified on
+CREATE TABLE IF NOT EXISTS Skills (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    organization_id INT NOT NULL,
+    name TEXT NOT NULL,
+    UNIQUE(organization_id, name),
+    FOREIGN KEY(organization_id) REFERENCES Organizations(id)
+);
+
+CREATE TABLE IF NOT EXISTS Employee_Skills (
+    employee_id INT NOT NULL,
+    skill_id INT NOT NULL,
+    PRIMARY KEY(employee_id, skill_id),
+    FOREIGN KEY(employee_id) REFERENCES Employees(id),
+    FOREIGN KEY(skill_id) REFERENCES Skills(id) ON DELETE CASCADE
+);
+
+-- At least min_count employees with the skill on every day of the shift,
+-- or only on `date`, where it replaces the requirement of every day (0 waives it)
+CREATE TABLE IF NOT EXISTS Skill_Requirements (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    shift_id INT NOT NULL,
+    skill_id INT NOT NULL,
+    min_count INT NOT NULL CHECK (min_count >= 0),
+    date DATE,
+    FOREIGN KEY(shift_id) REFERENCES Shifts(id) ON DELETE CASCADE,
+    FOREIGN KEY(skill_id) REFERENCES Skills(id) ON DELETE CASCADE
+);
+CREATE UNIQUE INDEX IF NOT EXISTS skill_requirements_shift_skill_date_idx ON Skill_Requirements(shift_id, skill_id, COALESCE(date, '-infinity'::date));
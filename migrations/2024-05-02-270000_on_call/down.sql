@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE Organizations DROP COLUMN on_call_pay_factor;
+ALTER TABLE Organizations DROP COLUMN on_call_max_per_month;
+ALTER TABLE Organizations DROP COLUMN on_call_with_shift;
+ALTER TABLE Shifts DROP COLUMN category;
@@ -0,0 +1,7 @@
+-- Work shifts fill the slots of the generator, on-call (standby) shifts are rostered on top of them
+ALTER TABLE Shifts ADD COLUMN category TEXT NOT NULL DEFAULT 'work' CHECK (category IN ('work', 'on_call'));
+
+-- How each organization gives out and pays on-call duties
+ALTER TABLE Organizations ADD COLUMN on_call_with_shift BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE Organizations ADD COLUMN on_call_max_per_month INT CHECK (on_call_max_per_month > 0);
+ALTER TABLE Organizations ADD COLUMN on_call_pay_factor DOUBLE PRECISION NOT NULL DEFAULT 0.25 CHECK (on_call_pay_factor >= 0);
@@ -0,0 +1,12 @@
+-- Secret tokens of the iCalendar subscription feeds, one live token per employee.
+-- Only a SHA-256 (hex) of the secret part is stored
+CREATE TABLE IF NOT EXISTS Calendar_Feeds (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    employee_id INT NOT NULL,
+    token_hash TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMP,
+    FOREIGN KEY(employee_id) REFERENCES Employees(id)
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS calendar_feeds_live ON Calendar_Feeds(employee_id) WHERE revoked_at IS NULL;
@@ -0,0 +1,8 @@
+-- Attempts to sign up a new organization, they are throttled per client IP
+CREATE TABLE IF NOT EXISTS Organization_Signups (
+    id INT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    ip TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS organization_signups_ip ON Organization_Signups(ip, created_at);
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use dotenv::dotenv;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::errors::ErrorKind;
+use crate::config::env_or;
+use crate::utils::TokenClaims;
+
+/*
+    Keys and lifetime of the access tokens, read once from the environment:
+        JWT_ALGORITHM           RS256 (default), ES256 or HS256
+        JWT_EXPIRY_MINUTES      access token lifetime (default 15)
+        JWT_REFRESH_TTL_DAYS    lifetime of a login session and its refresh tokens (default 30)
+        JWT_KID                 id of the signing key, written to the token header
+        JWT_PRIVATE_KEY / JWT_PRIVATE_KEY_FILE   signing key (default file src/private_key.pem)
+        JWT_PUBLIC_KEY / JWT_PUBLIC_KEY_FILE     its verification key (default file src/public_key.pem)
+        JWT_SECRET              shared secret, replaces both keys with HS256
+        JWT_PREVIOUS_KEYS       retired keys still accepted, "kid=path,kid=path"
+    The *_KEY variables hold the PEM itself and win over the *_FILE ones.
+    To rotate, move the current key to JWT_PREVIOUS_KEYS and give the new one a new JWT_KID
+ */
+pub struct JwtConfig {
+    pub algorithm: Algorithm,
+    pub expiry_minutes: i64,
+    pub refresh_ttl_days: i64,
+    pub kid: Option<String>,
+    encoding_key: EncodingKey,
+    // verification keys by kid, tokens without a kid are checked against `current_key`
+    decoding_keys: HashMap<String, DecodingKey>,
+    current_key: DecodingKey
+}
+
+// The value of `key`, or else the content of the file named by `key`_FILE (or `default_file`)
+fn read_key(key: &str, default_file: &str) -> Result<Vec<u8>, String> {
+    if let Ok(value) = std::env::var(key) {
+        return Ok(value.into_bytes())
+    }
+    let path = std::env::var(format!("{}_FILE", key)).unwrap_or(default_file.to_string());
+    std::fs::read(&path).map_err(|e| format!("Can't read {} from {}: {}", key, path, e))
+}
+
+fn encoding_key(algorithm: Algorithm, key: &[u8]) -> Result<EncodingKey, String> {
+    match algorithm {
+        Algorithm::RS256 => EncodingKey::from_rsa_pem(key),
+        Algorithm::ES256 => EncodingKey::from_ec_pem(key),
+        Algorithm::HS256 => Ok(EncodingKey::from_secret(key)),
+        _ => return Err(format!("Unsupported JWT algorithm {:?}", algorithm))
+    }.map_err(|e| format!("Invalid JWT signing key: {}", e))
+}
+
+fn decoding_key(algorithm: Algorithm, key: &[u8]) -> Result<DecodingKey, String> {
+    match algorithm {
+        Algorithm::RS256 => DecodingKey::from_rsa_pem(key),
+        Algorithm::ES256 => DecodingKey::from_ec_pem(key),
+        Algorithm::HS256 => Ok(DecodingKey::from_secret(key)),
+        _ => return Err(format!("Unsupported JWT algorithm {:?}", algorithm))
+    }.map_err(|e| format!("Invalid JWT verification key: {}", e))
+}
+
+impl JwtConfig {
+    /*
+        `signing_key` and `verification_key` are the PEM (or the secret with HS256) of the current key,
+        `previous_keys` the (kid, PEM) of retired keys
+     */
+    pub fn new(algorithm: Algorithm, expiry_minutes: i64, refresh_ttl_days: i64, kid: Option<String>, signing_key: &[u8],
+               verification_key: &[u8], previous_keys: &[(String, Vec<u8>)]) -> Result<Self, String> {
+        let current_key = decoding_key(algorithm, verification_key)?;
+        let mut decoding_keys = HashMap::new();
+        for (previous_kid, key) in previous_keys {
+            decoding_keys.insert(previous_kid.clone(), decoding_key(algorithm, key)?);
+        }
+        if let Some(kid) = &kid {
+            decoding_keys.insert(kid.clone(), current_key.clone());
+        }
+        Ok(JwtConfig {
+            algorithm,
+            expiry_minutes,
+            refresh_ttl_days,
+            kid,
+            encoding_key: encoding_key(algorithm, signing_key)?,
+            decoding_keys,
+            current_key,
+        })
+    }
+
+    pub fn from_env() -> Result<Self, String> {
+        dotenv().ok();
+        let algorithm = std::env::var("JWT_ALGORITHM").unwrap_or("RS256".to_string());
+        let algorithm = Algorithm::from_str(&algorithm).map_err(|_| format!("Unknown JWT algorithm {}", algorithm))?;
+        let (signing_key, verification_key) = if algorithm == Algorithm::HS256 {
+            let secret = std::env::var("JWT_SECRET").map_err(|_| "JWT_SECRET must be set with HS256")?;
+            (secret.clone().into_bytes(), secret.into_bytes())
+        } else {
+            (read_key("JWT_PRIVATE_KEY", "src/private_key.pem")?, read_key("JWT_PUBLIC_KEY", "src/public_key.pem")?)
+        };
+
+        let mut previous_keys = Vec::new();
+        for entry in std::env::var("JWT_PREVIOUS_KEYS").unwrap_or_default().split(',').filter(|e| !e.trim().is_empty()) {
+            let (kid, path) = entry.split_once('=').ok_or(format!("Invalid JWT_PREVIOUS_KEYS entry {}", entry))?;
+            let key = std::fs::read(path.trim()).map_err(|e| format!("Can't read JWT key {}: {}", path, e))?;
+            previous_keys.push((kid.trim().to_string(), key));
+        }
+
+        JwtConfig::new(
+            algorithm,
+            env_or("JWT_EXPIRY_MINUTES", 15),
+            env_or("JWT_REFRESH_TTL_DAYS", 30),
+            std::env::var("JWT_KID").ok().filter(|kid| !kid.is_empty()),
+            &signing_key,
+            &verification_key,
+            &previous_keys,
+        )
+    }
+
+    pub fn encode(&self, claims: &TokenClaims) -> Result<String, jsonwebtoken::errors::Error> {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.kid.clone();
+        jsonwebtoken::encode(&header, claims, &self.encoding_key)
+    }
+
+    // The key is picked by the `kid` of the token, a kid we don't know is rejected
+    pub fn decode(&self, token: &str) -> Result<TokenClaims, jsonwebtoken::errors::Error> {
+        let header = jsonwebtoken::decode_header(token)?;
+        let key = match &header.kid {
+            Some(kid) => self.decoding_keys.get(kid).ok_or(ErrorKind::InvalidToken)?,
+            None => &self.current_key
+        };
+        let decoded = jsonwebtoken::decode::<TokenClaims>(token, key, &Validation::new(self.algorithm))?;
+        Ok(decoded.claims)
+    }
+}
+
+static JWT_CONFIG: OnceLock<JwtConfig> = OnceLock::new();
+
+// Loaded on first use, main calls it at startup so a bad configuration stops the server right away
+pub fn jwt_config() -> &'static JwtConfig {
+    JWT_CONFIG.get_or_init(|| JwtConfig::from_env().expect("Invalid JWT configuration"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use jsonwebtoken::Algorithm;
+    use crate::config::jwt::JwtConfig;
+    use crate::utils::TokenClaims;
+
+    fn claims() -> TokenClaims {
+        TokenClaims { sub: 1, role: "Manager".to_string(), org: 1, sid: 1, iat: 0, exp: 4_000_000_000 }
+    }
+
+    fn hs256(kid: &str, secret: &str, previous: &[(String, Vec<u8>)]) -> JwtConfig {
+        JwtConfig::new(Algorithm::HS256, 60, 30, Some(kid.to_string()), secret.as_bytes(), secret.as_bytes(), previous).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_with_kid() {
+        let config = hs256("k1", "secret", &[]);
+        let token = config.encode(&claims()).unwrap();
+        assert_eq!(jsonwebtoken::decode_header(&token).unwrap().kid, Some("k1".to_string()));
+        assert_eq!(config.decode(&token).unwrap().sub, 1);
+    }
+
+    #[test]
+    fn test_rotated_key_still_verifies() {
+        let old = hs256("k1", "old-secret", &[]);
+        let token = old.encode(&claims()).unwrap();
+
+        let rotated = hs256("k2", "new-secret", &[("k1".to_string(), b"old-secret".to_vec())]);
+        assert!(rotated.decode(&token).is_ok());
+        // once the old key is dropped, its tokens are refused
+        assert!(hs256("k2", "new-secret", &[]).decode(&token).is_err());
+    }
+
+    #[test]
+    fn test_wrong_key_is_rejected() {
+        let token = hs256("k1", "secret", &[]).encode(&claims()).unwrap();
+        assert!(hs256("k1", "other", &[]).decode(&token).is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use std::time::Duration;
+use actix_web::HttpRequest;
+use dotenv::dotenv;
+use crate::config::env_or;
+
+/*
+    Brute-force protection of POST /user/login, tuned with environment variables:
+        LOGIN_MAX_FAILURES (default 5) failures of one account within
+        LOGIN_LOCKOUT_MINUTES (default 15) lock it until the oldest of them is that old
+        LOGIN_IP_MAX_FAILURES (default 50) failures from one client IP within
+        LOGIN_IP_WINDOW_MINUTES (default 15) block that IP the same way
+        LOGIN_DELAY_BASE_MS, LOGIN_DELAY_MAX_MS (default 250, 5000) progressive delay,
+            doubled on every failure of the account
+        LOGIN_TRUST_PROXY_HEADERS (default false) take the client IP from X-Forwarded-For / Forwarded,
+            only enable it behind a proxy that sets them
+ */
+#[derive(Debug, Clone)]
+pub struct LoginPolicy {
+    pub max_failures: i64,
+    pub lockout_minutes: i64,
+    pub ip_max_failures: i64,
+    pub ip_window_minutes: i64,
+    pub delay_base_ms: u64,
+    pub delay_max_ms: u64,
+    pub trust_proxy_headers: bool
+}
+
+impl Default for LoginPolicy {
+    fn default() -> Self {
+        LoginPolicy {
+            max_failures: 5,
+            lockout_minutes: 15,
+            ip_max_failures: 50,
+            ip_window_minutes: 15,
+            delay_base_ms: 250,
+            delay_max_ms: 5000,
+            trust_proxy_headers: false,
+        }
+    }
+}
+
+impl LoginPolicy {
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        let default = LoginPolicy::default();
+        LoginPolicy {
+            max_failures: env_or("LOGIN_MAX_FAILURES", default.max_failures),
+            lockout_minutes: env_or("LOGIN_LOCKOUT_MINUTES", default.lockout_minutes),
+            ip_max_failures: env_or("LOGIN_IP_MAX_FAILURES", default.ip_max_failures),
+            ip_window_minutes: env_or("LOGIN_IP_WINDOW_MINUTES", default.ip_window_minutes),
+            delay_base_ms: env_or("LOGIN_DELAY_BASE_MS", default.delay_base_ms),
+            delay_max_ms: env_or("LOGIN_DELAY_MAX_MS", default.delay_max_ms),
+            trust_proxy_headers: env_or("LOGIN_TRUST_PROXY_HEADERS", default.trust_proxy_headers),
+        }
+    }
+
+    // IP the request comes from, the proxy headers are only trusted when configured
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<String> {
+        if self.trust_proxy_headers {
+            // falls back to the peer address, which comes with a port
+            req.connection_info().realip_remote_addr().map(|addr| match addr.parse::<std::net::SocketAddr>() {
+                Ok(socket) => socket.ip().to_string(),
+                Err(_) => addr.to_string()
+            })
+        } else {
+            req.peer_addr().map(|addr| addr.ip().to_string())
+        }
+    }
+
+    // Wait before answering an account with `failures` recent failed attempts
+    pub fn delay(&self, failures: i64) -> Duration {
+        if failures <= 0 {
+            return Duration::ZERO
+        }
+        let factor = 1u64 << (failures - 1).min(16);
+        Duration::from_millis(self.delay_base_ms.saturating_mul(factor).min(self.delay_max_ms))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::config::login::LoginPolicy;
+
+    #[test]
+    fn test_progressive_delay() {
+        let policy = LoginPolicy::default();
+        assert_eq!(policy.delay(0), Duration::ZERO);
+        assert_eq!(policy.delay(1), Duration::from_millis(250));
+        assert_eq!(policy.delay(3), Duration::from_millis(1000));
+        assert_eq!(policy.delay(10), Duration::from_millis(5000));
+        assert_eq!(policy.delay(1000), Duration::from_millis(5000));
+    }
+}
@@ -1 +1,10 @@
-pub mod postgres;
\ No newline at end of file
+pub mod jwt;
+pub mod login;
+pub mod password;
+pub mod postgres;
+pub mod signup;
+
+// Value of an environment variable, `default` when it is not set or doesn't parse
+pub fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
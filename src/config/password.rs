@@ -0,0 +1,94 @@
+use dotenv::dotenv;
+use crate::config::env_or;
+
+/*
+    Password strength policy applied when a password is changed or reset.
+    Every rule can be tuned with an environment variable:
+        PASSWORD_MIN_LENGTH (default 8)
+        PASSWORD_REQUIRE_UPPERCASE, PASSWORD_REQUIRE_LOWERCASE,
+        PASSWORD_REQUIRE_DIGIT, PASSWORD_REQUIRE_SYMBOL (default true, false, true, false)
+    Reset tokens live PASSWORD_RESET_TTL_MINUTES (default 60)
+ */
+#[derive(Debug, Clone)]
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub require_uppercase: bool,
+    pub require_lowercase: bool,
+    pub require_digit: bool,
+    pub require_symbol: bool,
+    pub reset_ttl_minutes: i64
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        PasswordPolicy {
+            min_length: 8,
+            require_uppercase: true,
+            require_lowercase: false,
+            require_digit: true,
+            require_symbol: false,
+            reset_ttl_minutes: 60,
+        }
+    }
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        let default = PasswordPolicy::default();
+        PasswordPolicy {
+            min_length: env_or("PASSWORD_MIN_LENGTH", default.min_length),
+            require_uppercase: env_or("PASSWORD_REQUIRE_UPPERCASE", default.require_uppercase),
+            require_lowercase: env_or("PASSWORD_REQUIRE_LOWERCASE", default.require_lowercase),
+            require_digit: env_or("PASSWORD_REQUIRE_DIGIT", default.require_digit),
+            require_symbol: env_or("PASSWORD_REQUIRE_SYMBOL", default.require_symbol),
+            reset_ttl_minutes: env_or("PASSWORD_RESET_TTL_MINUTES", default.reset_ttl_minutes),
+        }
+    }
+
+    pub fn validate(&self, password: &str) -> Result<(), String> {
+        let mut missing = Vec::new();
+        if password.chars().count() < self.min_length {
+            missing.push(format!("at least {} characters", self.min_length));
+        }
+        if self.require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+            missing.push("an uppercase letter".to_string());
+        }
+        if self.require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+            missing.push("a lowercase letter".to_string());
+        }
+        if self.require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+            missing.push("a digit".to_string());
+        }
+        if self.require_symbol && !password.chars().any(|c| !c.is_alphanumeric()) {
+            missing.push("a symbol".to_string());
+        }
+        if missing.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Password must contain {}", missing.join(", ")))
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::config::password::PasswordPolicy;
+
+    #[test]
+    fn test_default_policy() {
+        let policy = PasswordPolicy::default();
+        assert!(policy.validate("Secret123").is_ok());
+        assert!(policy.validate("123").is_err());
+        assert!(policy.validate("secret123").is_err());
+        assert!(policy.validate("SecretPassword").is_err());
+    }
+
+    #[test]
+    fn test_error_lists_every_missing_rule() {
+        let policy = PasswordPolicy { require_symbol: true, ..PasswordPolicy::default() };
+        let err = policy.validate("abc").unwrap_err();
+        assert_eq!(err, "Password must contain at least 8 characters, an uppercase letter, a digit, a symbol");
+    }
+}
@@ -10,8 +10,7 @@ pub fn establish_connection_pool() -> DbPool {
     let database_url = std::env::var("DATABASE_URL")
         .expect("DATABASE_URL must be set");
     let manager = ConnectionManager::<PgConnection>::new(database_url);
-    let pool = r2d2::Pool::builder()
+    r2d2::Pool::builder()
         .build(manager)
-        .expect("Failed to create database connection pool");
-    pool
+        .expect("Failed to create database connection pool")
 }
\ No newline at end of file
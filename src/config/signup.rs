@@ -0,0 +1,38 @@
+use dotenv::dotenv;
+use crate::config::env_or;
+
+/*
+    Public sign-up of new organizations (POST /organization/), tuned with environment variables:
+        ORGANIZATION_SIGNUP_ENABLED (default false) the endpoint refuses every request while off
+        ORGANIZATION_SIGNUP_IP_MAX (default 3) sign-up attempts from one client IP within
+        ORGANIZATION_SIGNUP_IP_WINDOW_MINUTES (default 60) before it is turned away
+    The client IP is found as for logins, see config::login
+ */
+#[derive(Debug, Clone)]
+pub struct SignupPolicy {
+    pub enabled: bool,
+    pub ip_max_attempts: i64,
+    pub ip_window_minutes: i64
+}
+
+impl Default for SignupPolicy {
+    fn default() -> Self {
+        SignupPolicy {
+            enabled: false,
+            ip_max_attempts: 3,
+            ip_window_minutes: 60,
+        }
+    }
+}
+
+impl SignupPolicy {
+    pub fn from_env() -> Self {
+        dotenv().ok();
+        let default = SignupPolicy::default();
+        SignupPolicy {
+            enabled: env_or("ORGANIZATION_SIGNUP_ENABLED", default.enabled),
+            ip_max_attempts: env_or("ORGANIZATION_SIGNUP_IP_MAX", default.ip_max_attempts),
+            ip_window_minutes: env_or("ORGANIZATION_SIGNUP_IP_WINDOW_MINUTES", default.ip_window_minutes),
+        }
+    }
+}
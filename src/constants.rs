@@ -1,6 +1,6 @@
 
 // Messages
-// pub const MESSAGE_OK: &str = "ok";
+pub const MESSAGE_OK: &str = "ok";
 // pub const MESSAGE_CAN_NOT_FETCH_DATA: &str = "Can not fetch data";
 // pub const MESSAGE_CAN_NOT_INSERT_DATA: &str = "Can not insert data";
 // pub const MESSAGE_CAN_NOT_UPDATE_DATA: &str = "Can not update data";
@@ -8,11 +8,16 @@
 // pub const MESSAGE_SIGNUP_SUCCESS: &str = "Signup successfully";
 // pub const MESSAGE_SIGNUP_FAILED: &str = "Error while signing up, please try again";
 // pub const MESSAGE_LOGIN_SUCCESS: &str = "Login successfully";
-// pub const MESSAGE_LOGIN_FAILED: &str = "Wrong username or password, please try again";
+pub const MESSAGE_LOGIN_FAILED: &str = "Wrong username or password, please try again";
+pub const MESSAGE_ACCOUNT_LOCKED: &str = "Too many failed login attempts, please try again later";
+pub const MESSAGE_TOO_MANY_SIGNUPS: &str = "Too many sign-up attempts, please try again later";
+pub const MESSAGE_PASSWORD_CHANGED: &str = "Password changed successfully";
+pub const MESSAGE_INVALID_RESET_TOKEN: &str = "Invalid or expired reset token";
+pub const MESSAGE_INVALID_FEED_TOKEN: &str = "Invalid or revoked calendar feed token";
 // pub const MESSAGE_USER_NOT_FOUND: &str = "User not found, please signup";
-// pub const MESSAGE_LOGOUT_SUCCESS: &str = "Logout successfully";
+pub const MESSAGE_LOGOUT_SUCCESS: &str = "Logout successfully";
 // pub const MESSAGE_PROCESS_TOKEN_ERROR: &str = "Error while processing token";
-// pub const MESSAGE_INVALID_TOKEN: &str = "Invalid token, please login again";
+pub const MESSAGE_INVALID_TOKEN: &str = "Invalid token, please login again";
 // pub const MESSAGE_INTERNAL_SERVER_ERROR: &str = "Internal Server Error";
 
 // Bad request messages
@@ -29,10 +34,13 @@
 // pub const IGNORE_ROUTES: [&str; 3] = ["/api/ping", "/api/auth/signup", "/api/auth/login"];
 
 // Default number of items per page
-// pub const DEFAULT_PER_PAGE: i64 = 10;
+pub const DEFAULT_PER_PAGE: i64 = 10;
 
 // Default page number
-// pub const DEFAULT_PAGE_NUM: i64 = 1;
+pub const DEFAULT_PAGE_NUM: i64 = 1;
+
+// Organization owning the data from before organizations existed, /user/seed fills it
+pub const DEFAULT_ORGANIZATION: i32 = 1;
 
 // pub const EMPTY_STR: &str = "";
 
@@ -44,4 +52,19 @@
 pub const DATABASE_INSERT_ERROR:&str = "Error Insert record to database";
 pub const DATABASE_UPDATE_ERROR:&str = "Error Update record";
 pub const DATABASE_INSERT_SUCCESS: &str = "Success insert record to database";
-// pub const DATABASE_UPDATE_SUCCESS: &str = "Success update record";
+pub const DATABASE_UPDATE_SUCCESS: &str = "Success update record";
+
+//Schedule generation
+pub const MAX_GENERATE_ATTEMPTS: i32 = 1000;
+// Minimum rest between the end of a shift and the start of the next one of the same employee
+pub const MIN_REST_HOURS: i64 = 8;
+
+//Shift change status
+pub const SHIFT_CHANGE_PENDING: &str = "pending";
+pub const SHIFT_CHANGE_APPROVED: &str = "Ok";
+
+//Leave request status
+pub const LEAVE_PENDING: &str = "pending";
+pub const LEAVE_APPROVED: &str = "approved";
+pub const LEAVE_REJECTED: &str = "rejected";
+pub const LEAVE_CANCELLED: &str = "cancelled";
@@ -1,7 +1,7 @@
-use crate::response::ResponseBody;
+use crate::response::{ErrorBody, ResponseBody};
 use actix_web::{
     error,
-    http::{header::ContentType, StatusCode},
+    http::{header::{self, ContentType}, StatusCode},
     HttpResponse,
 };
 use derive_more::{Display, Error};
@@ -9,11 +9,28 @@ use derive_more::{Display, Error};
 
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 
+/*
+    Stable codes of authentication (401) and authorization (403) failures,
+    clients can branch on them, the message is only meant for humans
+ */
+pub const AUTH_MISSING_TOKEN: &str = "missing_token";
+pub const AUTH_INVALID_HEADER: &str = "invalid_authorization_header";
+pub const AUTH_INVALID_TOKEN: &str = "invalid_token";
+pub const AUTH_TOKEN_EXPIRED: &str = "token_expired";
+pub const AUTH_SESSION_REVOKED: &str = "session_revoked";
+pub const AUTH_FORBIDDEN: &str = "insufficient_permission";
+
 #[allow(unused)]
 #[derive(Debug, Display, Error)]
 pub enum ServiceError {
     #[display(fmt = "{error_message}")]
-    Unauthorized { error_message: String },
+    Unauthorized { code: &'static str, error_message: String },
+
+    #[display(fmt = "{error_message}")]
+    Forbidden { code: &'static str, error_message: String },
+
+    #[display(fmt = "{error_message}")]
+    TooManyRequests { error_message: String },
 
     #[display(fmt = "{error_message}")]
     InternalServerError { error_message: String },
@@ -25,19 +42,72 @@ pub enum ServiceError {
     NotFound { error_message: String },
 }
 
+impl ServiceError {
+    pub fn unauthorized(code: &'static str, error_message: &str) -> Self {
+        ServiceError::Unauthorized { code, error_message: error_message.to_string() }
+    }
+
+    pub fn forbidden(error_message: &str) -> Self {
+        ServiceError::Forbidden { code: AUTH_FORBIDDEN, error_message: error_message.to_string() }
+    }
+
+    // RFC 6750 challenge, a request without credentials gets no error attribute
+    fn www_authenticate(&self) -> Option<String> {
+        let error = match self {
+            ServiceError::Unauthorized { code, .. } if *code == AUTH_MISSING_TOKEN => return Some("Bearer".to_string()),
+            ServiceError::Unauthorized { code, .. } if *code == AUTH_INVALID_HEADER => "invalid_request",
+            ServiceError::Unauthorized { .. } => "invalid_token",
+            ServiceError::Forbidden { .. } => "insufficient_scope",
+            _ => return None
+        };
+        Some(format!("Bearer error=\"{}\", error_description=\"{}\"", error, self.to_string().replace('"', "'")))
+    }
+}
+
 #[allow(unused)]
 impl error::ResponseError for ServiceError {
     fn status_code(&self) -> StatusCode {
         match *self {
             ServiceError::Unauthorized { .. } => StatusCode::UNAUTHORIZED,
+            ServiceError::Forbidden { .. } => StatusCode::FORBIDDEN,
+            ServiceError::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
             ServiceError::InternalServerError { .. } => StatusCode::INTERNAL_SERVER_ERROR,
             ServiceError::BadRequest { .. } => StatusCode::BAD_REQUEST,
             ServiceError::NotFound { .. } => StatusCode::NOT_FOUND,
         }
     }
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code())
-            .insert_header(ContentType::json())
-            .json(ResponseBody::new(&self.to_string(), String::from("")))
+        let mut response = HttpResponse::build(self.status_code());
+        response.insert_header(ContentType::json());
+        if let Some(challenge) = self.www_authenticate() {
+            response.insert_header((header::WWW_AUTHENTICATE, challenge));
+        }
+        match self {
+            ServiceError::Unauthorized { code, .. } | ServiceError::Forbidden { code, .. } =>
+                response.json(ErrorBody::new(code, &self.to_string())),
+            _ => response.json(ResponseBody::new(&self.to_string(), String::from("")))
+        }
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{http::{header, StatusCode}, ResponseError};
+    use crate::error::{AUTH_INVALID_TOKEN, AUTH_MISSING_TOKEN, ServiceError};
+
+    #[test]
+    fn test_auth_errors_carry_challenge() {
+        let missing = ServiceError::unauthorized(AUTH_MISSING_TOKEN, "Authorization header is missing").error_response();
+        assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(missing.headers().get(header::WWW_AUTHENTICATE).unwrap(), "Bearer");
+
+        let invalid = ServiceError::unauthorized(AUTH_INVALID_TOKEN, "bad signature").error_response();
+        assert_eq!(invalid.headers().get(header::WWW_AUTHENTICATE).unwrap(),
+                   "Bearer error=\"invalid_token\", error_description=\"bad signature\"");
+
+        let forbidden = ServiceError::forbidden("Missing permission ReviewLeave").error_response();
+        assert_eq!(forbidden.status(), StatusCode::FORBIDDEN);
+        assert!(forbidden.headers().get(header::WWW_AUTHENTICATE).unwrap().to_str().unwrap().contains("insufficient_scope"));
+    }
+}
@@ -2,6 +2,7 @@ use actix_cors::Cors;
 #[allow(unused)]
 use actix_web::{App, get, HttpServer, web};
 use actix_web::middleware::Logger;
+use crate::config::jwt::jwt_config;
 use crate::config::postgres::establish_connection_pool;
 
 
@@ -15,6 +16,7 @@ mod response;
 mod route;
 mod utils;
 mod middleware;
+mod permissions;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()>{
@@ -24,6 +26,8 @@ async fn main() -> std::io::Result<()>{
 
     std::env::set_var("RUST_LOG", "debug");
     env_logger::init();
+    // Fail fast on missing or invalid JWT keys
+    jwt_config();
     // Create postgres connection pool
     let pool = establish_connection_pool();
     HttpServer::new(
@@ -33,12 +37,22 @@ async fn main() -> std::io::Result<()>{
             App::new()
                 .wrap(cors)
                 .app_data(web::Data::new(pool.clone()))
-                .wrap(Logger::default())
+                // the default format with the secrets of calendar feeds masked in the request line
+                .wrap(Logger::new(r#"%a "%{request_line}xi" %s %b "%{Referer}i" "%{User-Agent}i" %T"#)
+                    .custom_request_replace("request_line", |req| format!("{} {} {:?}",
+                        req.method(), route::calendar::redact_feed_token(&req.uri().to_string()), req.version())))
                 .service(
                     web::scope("/v1")
                         .configure(route::employee::config)
                         .configure(route::schedule::config)
                         .configure(route::shift_change::config)
+                        .configure(route::leave_request::config)
+                        .configure(route::shift::config)
+                        .configure(route::team::config)
+                        .configure(route::organization::config)
+                        .configure(route::holiday::config)
+                        .configure(route::skill::config)
+                        .configure(route::calendar::config)
                         .service(health_check)
                 )
         }
@@ -1,13 +1,18 @@
-use actix_web::{error::ErrorUnauthorized, Error, HttpMessage};
+use actix_web::{error::ErrorInternalServerError, http::header::{self, HeaderValue}, web, Error, HttpMessage};
 use std::future::{ready, Ready};
+use std::rc::Rc;
 
 use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
 use futures_util::future::LocalBoxFuture;
+use jsonwebtoken::errors::ErrorKind;
+use crate::config::postgres::DbPool;
+use crate::error::{AUTH_INVALID_HEADER, AUTH_INVALID_TOKEN, AUTH_MISSING_TOKEN, AUTH_SESSION_REVOKED, AUTH_TOKEN_EXPIRED, ServiceError};
+use crate::models::sessions::Session;
 
 pub struct JWTAuth;
 impl<S, B> Transform<S, ServiceRequest> for JWTAuth
     where
-        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
         S::Future: 'static,
         B: 'static,
 {
@@ -19,18 +24,18 @@ impl<S, B> Transform<S, ServiceRequest> for JWTAuth
 
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(JWTAuthHiMiddleware {
-            service
+            service: Rc::new(service)
         }))
     }
 }
 
 pub struct JWTAuthHiMiddleware<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S, B> Service<ServiceRequest> for JWTAuthHiMiddleware<S>
     where
-        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+        S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
         S::Future: 'static,
         B: 'static,
 {
@@ -41,32 +46,82 @@ impl<S, B> Service<ServiceRequest> for JWTAuthHiMiddleware<S>
     forward_ready!(service);
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
-        let authorization = req.headers().get("Authorization");
-        if authorization.is_none() {
-            return Box::pin(async { Err(ErrorUnauthorized("err3")) });
-        }
+        let token = match bearer_token(req.headers().get(header::AUTHORIZATION)) {
+            Ok(token) => token,
+            Err(err) => return Box::pin(async { Err(err.into()) })
+        };
 
-        let authorization = authorization.unwrap().to_str();
-        if authorization.is_err() {
-            return Box::pin(async { Err(ErrorUnauthorized("err4")) });
-        }
+        let claims = match crate::utils::verify_jwt_token(token) {
+            Ok(claims) => claims,
+            Err(err) => {
+                let err = match err.kind() {
+                    ErrorKind::ExpiredSignature => ServiceError::unauthorized(AUTH_TOKEN_EXPIRED, "Token has expired"),
+                    _ => ServiceError::unauthorized(AUTH_INVALID_TOKEN, &format!("Invalid token: {}", err))
+                };
+                return Box::pin(async { Err(err.into()) });
+            }
+        };
+        let pool = req.app_data::<web::Data<DbPool>>().cloned();
+        let service = Rc::clone(&self.service);
 
-        let authorization = authorization.unwrap();
-        let token = &authorization[7..]; // 'Bearer ' + token
+        // a token is only good while its session hasn't been logged out or revoked
+        Box::pin(async move {
+            let pool = pool.ok_or_else(|| ErrorInternalServerError("Database pool is not configured"))?;
+            let session_id = claims.sid;
+            let active = web::block(move || {
+                let mut conn = pool.get()?;
+                Session::is_active(session_id, &mut conn)
+            }).await?.map_err(ErrorInternalServerError)?;
+            if !active {
+                return Err(ServiceError::unauthorized(AUTH_SESSION_REVOKED, crate::constants::MESSAGE_INVALID_TOKEN).into());
+            }
+            req.extensions_mut().insert(claims);
+            let res = service.call(req).await?;
+            Ok(res)
+        })
+    }
+}
 
-        let token_data = crate::utils::verify_jwt_token(token.to_string());
+/*
+    The token of an "Authorization: Bearer <token>" header.
+    The scheme is case-insensitive, anything else (other schemes, no token, extra parts) is refused
+ */
+fn bearer_token(authorization: Option<&HeaderValue>) -> Result<String, ServiceError> {
+    let authorization = authorization
+        .ok_or_else(|| ServiceError::unauthorized(AUTH_MISSING_TOKEN, "Authorization header is missing"))?;
+    let invalid = || ServiceError::unauthorized(AUTH_INVALID_HEADER, "Authorization header must be 'Bearer <token>'");
+    let authorization = authorization.to_str().map_err(|_| invalid())?;
+    match authorization.split_once(' ') {
+        Some((scheme, token)) if scheme.eq_ignore_ascii_case("Bearer") && !token.is_empty() && !token.contains(' ') =>
+            Ok(token.to_string()),
+        _ => Err(invalid())
+    }
+}
 
-        if let Err(err) = token_data {
-            return Box::pin(async { Err(ErrorUnauthorized(err)) });
-        }
-        let claims = token_data.unwrap();
-        req.extensions_mut().insert(claims);
 
-        let fut = self.service.call(req);
+#[cfg(test)]
+mod tests {
+    use actix_web::http::header::HeaderValue;
+    use crate::error::{AUTH_INVALID_HEADER, AUTH_MISSING_TOKEN, ServiceError};
+    use crate::middleware::jwt::bearer_token;
 
-        Box::pin(async move {
-            let res = fut.await?;
-            Ok(res)
-        })
+    fn code(header: Option<&str>) -> &'static str {
+        let header = header.map(|h| HeaderValue::from_str(h).unwrap());
+        match bearer_token(header.as_ref()) {
+            Err(ServiceError::Unauthorized { code, .. }) => code,
+            _ => "ok"
+        }
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        let header = HeaderValue::from_static("Bearer abc.def");
+        assert_eq!(bearer_token(Some(&header)).unwrap(), "abc.def");
+        assert_eq!(code(Some("bearer abc")), "ok");
+        assert_eq!(code(None), AUTH_MISSING_TOKEN);
+        assert_eq!(code(Some("Bear")), AUTH_INVALID_HEADER);
+        assert_eq!(code(Some("Bearer ")), AUTH_INVALID_HEADER);
+        assert_eq!(code(Some("Basic abc")), AUTH_INVALID_HEADER);
+        assert_eq!(code(Some("Bearer a b")), AUTH_INVALID_HEADER);
     }
-}
\ No newline at end of file
+}
@@ -1,2 +1,2 @@
 pub mod jwt;
-pub mod is_manager;
\ No newline at end of file
+pub mod permission;
\ No newline at end of file
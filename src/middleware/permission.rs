@@ -1,12 +1,15 @@
-use actix_web::{error::ErrorUnauthorized, Error, HttpMessage};
+use actix_web::{Error, HttpMessage};
 use std::future::{ready, Ready};
 
 use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
 use futures_util::future::LocalBoxFuture;
+use crate::error::{AUTH_MISSING_TOKEN, ServiceError};
+use crate::permissions::Permission;
 use crate::utils::TokenClaims;
 
-pub struct IsManager;
-impl<S, B> Transform<S, ServiceRequest> for IsManager
+// Must be wrapped inside `jwt::JWTAuth`, which provides the claims
+pub struct RequirePermission(pub Permission);
+impl<S, B> Transform<S, ServiceRequest> for RequirePermission
     where
         S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
         S::Future: 'static,
@@ -14,22 +17,24 @@ impl<S, B> Transform<S, ServiceRequest> for IsManager
 {
     type Response = ServiceResponse<B>;
     type Error = Error;
-    type Transform = IsManagerHiMiddleware<S>;
+    type Transform = RequirePermissionMiddleware<S>;
     type InitError = ();
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(IsManagerHiMiddleware {
-            service
+        ready(Ok(RequirePermissionMiddleware {
+            service,
+            permission: self.0
         }))
     }
 }
 
-pub struct IsManagerHiMiddleware<S> {
+pub struct RequirePermissionMiddleware<S> {
     service: S,
+    permission: Permission,
 }
 
-impl<S, B> Service<ServiceRequest> for IsManagerHiMiddleware<S>
+impl<S, B> Service<ServiceRequest> for RequirePermissionMiddleware<S>
     where
         S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
         S::Future: 'static,
@@ -44,11 +49,13 @@ impl<S, B> Service<ServiceRequest> for IsManagerHiMiddleware<S>
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let claims = req.extensions().get::<TokenClaims>().cloned();
         if claims.is_none() {
-            return Box::pin(async { Err(ErrorUnauthorized("err1")) });
+            let err = ServiceError::unauthorized(AUTH_MISSING_TOKEN, "Authentication is required");
+            return Box::pin(async { Err(err.into()) });
         }
         let claims = claims.unwrap();
-        if claims.role != "Manager" {
-            return Box::pin(async { Err(ErrorUnauthorized("err2")) });
+        if !claims.can(self.permission) {
+            let err = ServiceError::forbidden(&format!("Missing permission {:?}", self.permission));
+            return Box::pin(async { Err(err.into()) });
         }
         let fut = self.service.call(req);
 
@@ -57,4 +64,4 @@ impl<S, B> Service<ServiceRequest> for IsManagerHiMiddleware<S>
             Ok(res)
         })
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,128 @@
+use diesel::{AsChangeset, Identifiable, Insertable, PgConnection, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use crate::constants;
+use crate::error::Error;
+use crate::schema::approval_policies;
+use diesel::prelude::*;
+
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Clone)]
+#[diesel(table_name = approval_policies)]
+pub struct ApprovalPolicy {
+    pub id: i32,
+    pub name: String,
+    pub same_shift_type: bool,
+    pub require_consent: bool,
+    pub require_no_violations: bool,
+    pub min_hours_ahead: Option<i32>,
+    pub active: bool,
+    pub organization_id: i32
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable, AsChangeset)]
+#[diesel(table_name = approval_policies)]
+pub struct ApprovalPolicyDTO {
+    pub name: String,
+    pub same_shift_type: bool,
+    pub require_consent: bool,
+    pub require_no_violations: bool,
+    pub min_hours_ahead: Option<i32>,
+    pub active: bool
+}
+
+// What is known about a change request when the policies are evaluated
+#[derive(Debug, Clone)]
+pub struct ChangeFacts {
+    pub same_shift_type: bool,
+    pub consent: bool,
+    pub violates_rules: bool,
+    pub hours_ahead: i64
+}
+
+impl ApprovalPolicy {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(policy_dto: ApprovalPolicyDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<String, Error> {
+        use crate::schema::approval_policies::dsl::*;
+        diesel::insert_into(approval_policies).values((&policy_dto, organization_id.eq(_organization_id))).execute(conn)?;
+        Ok(constants::DATABASE_INSERT_SUCCESS.to_string())
+    }
+
+    pub fn update(_id: i32, policy_dto: ApprovalPolicyDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<ApprovalPolicy, Error> {
+        use crate::schema::approval_policies::dsl::*;
+        diesel::update(approval_policies.find(_id).filter(organization_id.eq(_organization_id)))
+            .set(&policy_dto)
+            .get_result::<ApprovalPolicy>(conn)
+            .optional()?
+            .ok_or_else(|| "Approval policy not found".into())
+    }
+
+    pub fn find_all(_organization_id: i32, conn: &mut PgConnection) -> Result<Vec<ApprovalPolicy>, Error> {
+        use crate::schema::approval_policies::dsl::*;
+        Ok(approval_policies.filter(organization_id.eq(_organization_id)).order_by(id).load::<ApprovalPolicy>(conn)?)
+    }
+
+    pub fn find_active(_organization_id: i32, conn: &mut PgConnection) -> Result<Vec<ApprovalPolicy>, Error> {
+        use crate::schema::approval_policies::dsl::*;
+        Ok(approval_policies.filter(organization_id.eq(_organization_id)).filter(active.eq(true)).order_by(id).load::<ApprovalPolicy>(conn)?)
+    }
+
+    // Every criterion switched on in the policy has to hold for the change
+    pub fn matches(&self, facts: &ChangeFacts) -> bool {
+        if self.same_shift_type && !facts.same_shift_type {
+            return false;
+        }
+        if self.require_consent && !facts.consent {
+            return false;
+        }
+        if self.require_no_violations && facts.violates_rules {
+            return false;
+        }
+        match self.min_hours_ahead {
+            Some(hours) => facts.hours_ahead > hours as i64,
+            None => true
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::models::approval_policies::{ApprovalPolicy, ChangeFacts};
+
+    fn trivial_swap() -> ApprovalPolicy {
+        ApprovalPolicy {
+            id: 1,
+            name: "Trivial swap".to_string(),
+            same_shift_type: true,
+            require_consent: true,
+            require_no_violations: true,
+            min_hours_ahead: Some(48),
+            active: true,
+            organization_id: 1,
+        }
+    }
+
+    fn facts() -> ChangeFacts {
+        ChangeFacts { same_shift_type: true, consent: true, violates_rules: false, hours_ahead: 72 }
+    }
+
+    #[test]
+    fn test_policy_matches_trivial_swap() {
+        assert!(trivial_swap().matches(&facts()));
+    }
+
+    #[test]
+    fn test_policy_rejects_each_failed_criterion() {
+        let policy = trivial_swap();
+        assert!(!policy.matches(&ChangeFacts { same_shift_type: false, ..facts() }));
+        assert!(!policy.matches(&ChangeFacts { consent: false, ..facts() }));
+        assert!(!policy.matches(&ChangeFacts { violates_rules: true, ..facts() }));
+        assert!(!policy.matches(&ChangeFacts { hours_ahead: 48, ..facts() }));
+    }
+
+    #[test]
+    fn test_disabled_criteria_are_ignored() {
+        let policy = ApprovalPolicy { require_consent: false, min_hours_ahead: None, ..trivial_swap() };
+        assert!(policy.matches(&ChangeFacts { consent: false, hours_ahead: 1, ..facts() }));
+    }
+}
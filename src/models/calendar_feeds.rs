@@ -0,0 +1,243 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use diesel::{Identifiable, Insertable, PgConnection, Queryable, RunQueryDsl};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use ring::constant_time::verify_slices_are_equal;
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use crate::constants;
+use crate::error::Error;
+use crate::models::employee::Employee;
+use crate::schema::calendar_feeds;
+use diesel::prelude::*;
+
+// Days before and after today the feeds cover
+pub const FEED_DAYS_BEHIND: i64 = 31;
+pub const FEED_DAYS_AHEAD: i64 = 92;
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable)]
+#[diesel(table_name = calendar_feeds)]
+pub struct CalendarFeed {
+    pub id: i32,
+    pub employee_id: i32,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub created_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = calendar_feeds)]
+struct NewCalendarFeed {
+    employee_id: i32,
+    token_hash: String
+}
+
+// Returned once to the employee, only its hash is kept
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IssuedFeedToken {
+    pub token: String,
+    pub created_at: NaiveDateTime
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventTime {
+    At(DateTime<Utc>),
+    // all-day events
+    Day(NaiveDate)
+}
+
+#[derive(Debug, Clone)]
+pub struct FeedEvent {
+    pub uid: String,
+    pub start: EventTime,
+    // exclusive, the day after the last one for all-day events
+    pub end: EventTime,
+    pub summary: String,
+    pub description: Option<String>,
+    // shown as free time, e.g. on-call duties
+    pub transparent: bool
+}
+
+/*
+    Feed secrets are long random strings polled by calendar apps without any throttling,
+    a SHA-256 is enough to keep them out of the database and is cheap to check
+ */
+fn secret_hash(secret: &str) -> String {
+    digest(&SHA256, secret.as_bytes()).as_ref().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// TEXT values of RFC 5545 escape backslashes, separators and line breaks
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+// Lines longer than 75 octets go on continuation lines starting with a space
+fn fold_line(line: &str, ical: &mut String) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            ical.push_str("\r\n ");
+            length = 1;
+        }
+        ical.push(c);
+        length += c.len_utf8();
+    }
+    ical.push_str("\r\n");
+}
+
+fn event_time(property: &str, time: &EventTime) -> String {
+    match time {
+        EventTime::At(at) => format!("{}:{}", property, at.format("%Y%m%dT%H%M%SZ")),
+        EventTime::Day(day) => format!("{};VALUE=DATE:{}", property, day.format("%Y%m%d"))
+    }
+}
+
+// An iCalendar (.ics) file with one VEVENT per event
+pub fn write_ical(calendar_name: &str, events: &[FeedEvent], now: DateTime<Utc>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//scheduler_api//Schedules//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+        format!("X-WR-CALNAME:{}", escape_text(calendar_name)),
+    ];
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_string());
+        lines.push(format!("UID:{}", event.uid));
+        lines.push(format!("DTSTAMP:{}", now.format("%Y%m%dT%H%M%SZ")));
+        lines.push(event_time("DTSTART", &event.start));
+        lines.push(event_time("DTEND", &event.end));
+        lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+        if let Some(description) = &event.description {
+            lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        if event.transparent {
+            lines.push("TRANSP:TRANSPARENT".to_string());
+        }
+        lines.push("END:VEVENT".to_string());
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ical = String::new();
+    for line in lines {
+        fold_line(&line, &mut ical);
+    }
+    ical
+}
+
+impl CalendarFeed {
+    /*
+        Issue the feed token of an employee, the previous one stops working.
+        The token is "<id>.<secret>" so it can be looked up without storing the secret
+     */
+    pub fn issue(_employee_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<IssuedFeedToken, Error> {
+        Employee::find_by_id(_employee_id, _organization_id, conn)?;
+        let secret: String = rand::thread_rng().sample_iter(&Alphanumeric).take(32).collect();
+
+        let issued = conn.transaction::<CalendarFeed, Error, _>(|conn| {
+            Self::revoke(_employee_id, conn)?;
+            Ok(diesel::insert_into(calendar_feeds::table)
+                .values(NewCalendarFeed {
+                    employee_id: _employee_id,
+                    token_hash: secret_hash(&secret),
+                })
+                .get_result::<CalendarFeed>(conn)?)
+        })?;
+
+        Ok(IssuedFeedToken {
+            token: format!("{}.{}", issued.id, secret),
+            created_at: issued.created_at,
+        })
+    }
+
+    // Revokes the feed token of an employee, their subscriptions stop updating
+    pub fn revoke(_employee_id: i32, conn: &mut PgConnection) -> Result<usize, Error> {
+        use crate::schema::calendar_feeds::dsl::*;
+        Ok(diesel::update(calendar_feeds.filter(employee_id.eq(_employee_id)).filter(revoked_at.is_null()))
+            .set(revoked_at.eq(Utc::now().naive_utc()))
+            .execute(conn)?)
+    }
+
+    // Owner of a live feed token, deactivated employees lose their feeds
+    pub fn authenticate(token: &str, conn: &mut PgConnection) -> Result<Employee, Error> {
+        use crate::schema::employees;
+        let (feed_id, secret) = match token.split_once('.') {
+            Some((feed_id, secret)) => (feed_id.parse::<i32>().map_err(|_| constants::MESSAGE_INVALID_FEED_TOKEN)?, secret),
+            None => return Err(constants::MESSAGE_INVALID_FEED_TOKEN.into())
+        };
+        let feed = calendar_feeds::table.find(feed_id).first::<CalendarFeed>(conn)
+            .optional()?
+            .filter(|feed| feed.revoked_at.is_none())
+            .filter(|feed| verify_slices_are_equal(secret_hash(secret).as_bytes(), feed.token_hash.as_bytes()).is_ok())
+            .ok_or(constants::MESSAGE_INVALID_FEED_TOKEN)?;
+        let employee = employees::table.find(feed.employee_id).first::<Employee>(conn)?;
+        if !employee.active {
+            return Err(constants::MESSAGE_INVALID_FEED_TOKEN.into())
+        }
+        Ok(employee)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use crate::models::calendar_feeds::{EventTime, FeedEvent, secret_hash, write_ical};
+
+    #[test]
+    fn test_secret_hash() {
+        assert_eq!(secret_hash("abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_ne!(secret_hash("abc"), secret_hash("abd"));
+    }
+
+    #[test]
+    fn test_events_are_written_in_utc() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let night = FeedEvent {
+            uid: "schedule-7@scheduler".to_string(),
+            start: EventTime::At(Utc.with_ymd_and_hms(2024, 5, 2, 20, 0, 0).unwrap()),
+            end: EventTime::At(Utc.with_ymd_and_hms(2024, 5, 3, 4, 0, 0).unwrap()),
+            summary: "Night, D".to_string(),
+            description: Some("cover for Ann; bring keys\nthanks".to_string()),
+            transparent: false,
+        };
+        let leave = FeedEvent {
+            uid: "leave-3@scheduler".to_string(),
+            start: EventTime::Day(NaiveDate::from_ymd_opt(2024, 5, 6).unwrap()),
+            end: EventTime::Day(NaiveDate::from_ymd_opt(2024, 5, 8).unwrap()),
+            summary: "Vacation".to_string(),
+            description: None,
+            transparent: true,
+        };
+        let ical = write_ical("Schedule of Ann", &[night, leave], now);
+        assert!(ical.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ical.ends_with("END:VCALENDAR\r\n"));
+        assert!(ical.contains("\r\nDTSTART:20240502T200000Z\r\nDTEND:20240503T040000Z\r\n"));
+        assert!(ical.contains("\r\nSUMMARY:Night\\, D\r\n"));
+        assert!(ical.contains("\r\nDESCRIPTION:cover for Ann\\; bring keys\\nthanks\r\n"));
+        assert!(ical.contains("\r\nDTSTART;VALUE=DATE:20240506\r\nDTEND;VALUE=DATE:20240508\r\n"));
+        assert_eq!(ical.matches("BEGIN:VEVENT").count(), 2);
+        assert_eq!(ical.matches("TRANSP:TRANSPARENT").count(), 1);
+    }
+
+    #[test]
+    fn test_long_lines_are_folded() {
+        let event = FeedEvent {
+            uid: "schedule-1@scheduler".to_string(),
+            start: EventTime::Day(NaiveDate::from_ymd_opt(2024, 5, 6).unwrap()),
+            end: EventTime::Day(NaiveDate::from_ymd_opt(2024, 5, 7).unwrap()),
+            summary: "é".repeat(60),
+            description: None,
+            transparent: false,
+        };
+        let ical = write_ical("Team", &[event], Utc::now());
+        assert!(ical.split("\r\n").all(|line| line.len() <= 75));
+        let unfolded = ical.replace("\r\n ", "");
+        assert!(unfolded.contains(&format!("SUMMARY:{}\r\n", "é".repeat(60))));
+    }
+}
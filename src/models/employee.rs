@@ -1,13 +1,25 @@
-use bcrypt::{DEFAULT_COST, hash};
-use chrono::Utc;
+use bcrypt::{DEFAULT_COST, hash, verify};
+use chrono::{NaiveDateTime, Utc};
+use chrono_tz::Tz;
 use diesel::{Insertable, PgConnection, prelude::*, Queryable};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value;
 
+use crate::config::login::LoginPolicy;
+use crate::config::password::PasswordPolicy;
 use crate::constants;
 use crate::error::Error;
 use crate::schema::employees;
-use crate::utils::generate_token;
+use crate::models::calendar_feeds::CalendarFeed;
+use crate::models::login_attempts::LoginAttempt;
+use crate::models::sessions::{Session, TokenPair};
+use crate::permissions::{Permission, Role};
+use crate::utils::{parse_time_zone, TokenClaims};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+// bcrypt hash at DEFAULT_COST checked when the email has no account, matches no password in use
+const DUMMY_PASSWORD_HASH: &str = "$2b$12$/V4iere3liN7OidRGvSdSOYEpbAOe/urUvhMB452Kc7gApzmoS2S.";
 
 #[derive(Debug, Serialize, Deserialize, Queryable, PartialEq, Identifiable)]
 #[diesel(table_name = employees)]
@@ -21,6 +33,12 @@ pub struct Employee {
     pub department: Option<String>,
     pub role: String,
     pub availability: Option<Value>,
+    pub active: bool,
+    pub deactivated_at: Option<NaiveDateTime>,
+    pub anonymized_at: Option<NaiveDateTime>,
+    pub organization_id: i32,
+    // zone times are shown in, the one of their team or organization when not set
+    pub time_zone: Option<String>,
 }
 
 #[derive(Insertable, Serialize, Deserialize, Debug)]
@@ -33,6 +51,8 @@ pub struct EmployeeDTO {
     pub department: Option<String>,
     pub role: String,
     pub availability: Option<Value>,
+    #[serde(default)]
+    pub time_zone: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -41,15 +61,65 @@ pub struct LoginDTO {
     pub password : String
 }
 
+/*
+    Partial update, absent fields are left untouched.
+    Nullable fields can be cleared by sending them as null
+ */
+#[derive(AsChangeset, Deserialize, Debug, Default)]
+#[diesel(table_name = employees)]
+pub struct UpdateEmployeeDTO {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub phone_number: Option<Option<String>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub department: Option<Option<String>>,
+    pub role: Option<String>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub availability: Option<Option<Value>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub time_zone: Option<Option<String>>,
+}
+
+// Tells an explicit null (Some(None)) apart from a missing field (None)
+fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+    where
+        T: Deserialize<'de>,
+        D: Deserializer<'de>,
+{
+    Deserialize::deserialize(deserializer).map(Some)
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ChangePasswordDTO {
+    pub current_password: String,
+    pub new_password: String
+}
+
 impl Employee {
-    pub fn new(employee_dto: EmployeeDTO, conn: &mut PgConnection) -> Result<String, Error> {
+    // Accounts created by a user of the organization, any role but Employee needs AssignRoles
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(employee_dto: EmployeeDTO, claims: &TokenClaims, conn: &mut PgConnection) -> Result<String, Error> {
+        Self::check_role_assignment(&employee_dto.role, claims)?;
+        Self::register(employee_dto, claims.org, conn)
+    }
+
+    /*
+        Accounts the server creates itself: seed data and the Admin of a new organization.
+        Emails stay unique across organizations, they are what people log in with
+     */
+    pub fn register(employee_dto: EmployeeDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<String, Error> {
+        Self::check_role(&employee_dto.role)?;
+        if let Some(zone) = &employee_dto.time_zone {
+            parse_time_zone(zone)?;
+        }
         if Self::find_user_by_username(&employee_dto.email, conn).is_err() {
             use crate::schema::employees::dsl::*;
             let new_employee = EmployeeDTO {
                 password: hash(&employee_dto.password, DEFAULT_COST).unwrap(),
                 ..employee_dto
             };
-            diesel::insert_into(employees).values(new_employee).execute(conn).expect(constants::DATABASE_INSERT_ERROR);
+            diesel::insert_into(employees).values((new_employee, organization_id.eq(_organization_id))).execute(conn).expect(constants::DATABASE_INSERT_ERROR);
             Ok(constants::DATABASE_INSERT_SUCCESS.to_string())
         } else {
             Err(format!(
@@ -59,37 +129,278 @@ impl Employee {
         }
     }
 
-    pub fn login(login_dto: LoginDTO, conn : &mut PgConnection) -> Result<String, Error> {
+    // `client_ip` feeds the brute-force protection, see config::login
+    pub fn login(login_dto: LoginDTO, client_ip: Option<&str>, conn : &mut PgConnection) -> Result<TokenPair, Error> {
         use crate::schema::employees::dsl::*;
         let (_email, _password) = (login_dto.email.clone(), login_dto.password.clone());
+        let policy = LoginPolicy::from_env();
+        let attempt_key = _email.to_lowercase();
+        LoginAttempt::check_allowed(&attempt_key, client_ip, &policy, conn)?;
+
         let user = employees.filter(email.eq(_email)).first::<Employee>(conn).optional()?;
+        let user_id = user.as_ref().map(|u| u.id);
+        match Self::check_credentials(user, &_password) {
+            Ok(user) => {
+                LoginAttempt::record_success(&attempt_key, client_ip, conn)?;
+                Session::open(&user, conn)
+            }
+            Err(err) => {
+                LoginAttempt::record_failure(&attempt_key, user_id, client_ip, &policy, conn)?;
+                Err(err)
+            }
+        }
+    }
+
+    /*
+        Unknown user, deactivated user and wrong password get the same answer so accounts can't be probed.
+        The password is always checked, against DUMMY_PASSWORD_HASH without a user, so they also take as long
+     */
+    fn check_credentials(user: Option<Employee>, _password: &str) -> Result<Employee, Error> {
+        let hashed = user.as_ref().map_or(DUMMY_PASSWORD_HASH, |u| u.password.as_str());
+        let valid = verify(_password, hashed).unwrap_or(false);
         match user {
-            None => return Err("User not exist".into()),
-            Some(user) => {
-                let db_password =user.password.clone();
-                if hash(_password, DEFAULT_COST)? == db_password {
-                    return Err("Password do not match".into())
-                }
-                let now = Utc::now();
-                let access_token = generate_token(user.id.clone(), user.role.clone(), now)?;
-                Ok(access_token)
+            Some(user) if user.active && valid => Ok(user),
+            _ => Err(constants::MESSAGE_LOGIN_FAILED.into())
+        }
+    }
+
+
+    // Every other session of the employee is logged out, `session_id` is the one making the change
+    pub fn change_password(claims: &TokenClaims, change_dto: ChangePasswordDTO, conn: &mut PgConnection) -> Result<String, Error> {
+        let (_id, session_id) = (claims.sub, claims.sid);
+        let user = Self::find_by_id(_id, claims.org, conn)?;
+        if !verify(&change_dto.current_password, &user.password).unwrap_or(false) {
+            return Err("Current password is incorrect".into())
+        }
+        if change_dto.new_password == change_dto.current_password {
+            return Err("New password must be different from the current one".into())
+        }
+        PasswordPolicy::from_env().validate(&change_dto.new_password)?;
+        Self::set_password(_id, &change_dto.new_password, conn)?;
+        Session::revoke_all(_id, Some(session_id), conn)?;
+        Ok(constants::MESSAGE_PASSWORD_CHANGED.to_string())
+    }
+
+    // Hash and store a password that already passed the policy
+    pub fn set_password(_id: i32, new_password: &str, conn: &mut PgConnection) -> Result<(), Error> {
+        use crate::schema::employees::dsl::*;
+        diesel::update(employees.find(_id))
+            .set(password.eq(hash(new_password, DEFAULT_COST)?))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    fn check_role(_role: &str) -> Result<(), Error> {
+        match Role::parse(_role) {
+            Some(_) => Ok(()),
+            None => Err(format!("Unknown role '{}'", _role).into())
+        }
+    }
+
+    // Accounts holding AssignRoles are out of reach of callers without it, see TokenClaims::outranked_by
+    pub fn check_manageable(&self, claims: &TokenClaims) -> Result<(), Error> {
+        if claims.outranked_by(&self.role) {
+            return Err("You are not allowed to manage this employee".into())
+        }
+        Ok(())
+    }
+
+    fn check_role_assignment(_role: &str, claims: &TokenClaims) -> Result<(), Error> {
+        Self::check_role(_role)?;
+        if Role::parse(_role) != Some(Role::Employee) && !claims.can(Permission::AssignRoles) {
+            return Err("You are not allowed to assign roles".into())
+        }
+        Ok(())
+    }
+
+    /*
+        ManageEmployees allows changing anyone's email and department, AssignRoles their role,
+        everybody else can only change their own name, phone number, availability and time zone.
+        Accounts holding AssignRoles can't be changed by anyone without it
+     */
+    pub fn update(_id: i32, update_dto: UpdateEmployeeDTO, claims: &TokenClaims, conn: &mut PgConnection) -> Result<Employee, Error> {
+        use crate::schema::employees::dsl::*;
+        let can_manage = claims.can(Permission::ManageEmployees);
+        if _id != claims.sub && !can_manage {
+            return Err("You can only update your own profile".into())
+        }
+        if !can_manage && (update_dto.email.is_some() || update_dto.department.is_some()) {
+            return Err("Only managers can change email or department".into())
+        }
+        if let Some(new_role) = &update_dto.role {
+            if !claims.can(Permission::AssignRoles) {
+                return Err("You are not allowed to change roles".into())
+            }
+            Self::check_role(new_role)?;
+        }
+        if let Some(Some(zone)) = &update_dto.time_zone {
+            parse_time_zone(zone)?;
+        }
+        let employee = Self::find_by_id(_id, claims.org, conn)?;
+        employee.check_manageable(claims)?;
+        if employee.anonymized_at.is_some() {
+            return Err("This employee has been deleted".into())
+        }
+        if let Some(new_email) = &update_dto.email {
+            if new_email != &employee.email && Self::find_user_by_username(new_email, conn).is_ok() {
+                return Err(format!("Employee '{}' is already registered", new_email).into())
             }
         }
+        if update_dto.name.is_none() && update_dto.email.is_none() && update_dto.phone_number.is_none()
+            && update_dto.department.is_none() && update_dto.role.is_none() && update_dto.availability.is_none()
+            && update_dto.time_zone.is_none() {
+            return Err("Nothing to update".into())
+        }
+        Ok(diesel::update(employees.find(_id)).set(&update_dto).get_result::<Employee>(conn)?)
     }
 
+    // Soft deactivation, past schedules stay as they are
+    pub fn set_active(_id: i32, is_active: bool, claims: &TokenClaims, conn: &mut PgConnection) -> Result<Employee, Error> {
+        use crate::schema::employees::dsl::*;
+        let employee = Self::find_by_id(_id, claims.org, conn)?;
+        employee.check_manageable(claims)?;
+        if employee.anonymized_at.is_some() {
+            return Err("This employee has been deleted".into())
+        }
+        let deactivated = if is_active { None } else { Some(Utc::now().naive_utc()) };
+        let employee = diesel::update(employees.find(_id))
+            .set((active.eq(is_active), deactivated_at.eq(deactivated)))
+            .get_result::<Employee>(conn)?;
+        if !is_active {
+            Session::revoke_all(_id, None, conn)?;
+        }
+        Ok(employee)
+    }
+
+    /*
+        Deletion on request (GDPR): the row is kept so schedules stay consistent,
+        but every personal detail and free text written by the employee is wiped
+     */
+    pub fn anonymize(_id: i32, claims: &TokenClaims, conn: &mut PgConnection) -> Result<String, Error> {
+        use crate::schema::{employees, leave_requests, password_reset_tokens, shift_changes};
+        let employee = Self::find_by_id(_id, claims.org, conn)?;
+        employee.check_manageable(claims)?;
+        let now = Utc::now().naive_utc();
+        let unusable_password: String = rand::thread_rng().sample_iter(&Alphanumeric).take(32).collect();
+        conn.transaction::<(), Error, _>(|conn| {
+            diesel::update(employees::table.find(_id))
+                .set((
+                    employees::name.eq(format!("Deleted employee #{}", _id)),
+                    employees::email.eq(format!("deleted-{}@invalid", _id)),
+                    employees::password.eq(hash(unusable_password, DEFAULT_COST)?),
+                    employees::phone_number.eq(None::<String>),
+                    employees::availability.eq(None::<Value>),
+                    employees::active.eq(false),
+                    employees::deactivated_at.eq(employee.deactivated_at.or(Some(now))),
+                    employees::anonymized_at.eq(now),
+                ))
+                .execute(conn)?;
+            diesel::update(leave_requests::table.filter(leave_requests::employee_id.eq(_id)))
+                .set(leave_requests::reason.eq(None::<String>))
+                .execute(conn)?;
+            diesel::update(shift_changes::table.filter(shift_changes::requested_by.eq(_id)))
+                .set(shift_changes::reason.eq(None::<String>))
+                .execute(conn)?;
+            diesel::update(password_reset_tokens::table.filter(password_reset_tokens::employee_id.eq(_id)).filter(password_reset_tokens::used_at.is_null()))
+                .set(password_reset_tokens::used_at.eq(now))
+                .execute(conn)?;
+            Session::revoke_all(_id, None, conn)?;
+            CalendarFeed::revoke(_id, conn)?;
+            Ok(())
+        })?;
+        Ok(format!("Employee #{} anonymized", _id))
+    }
 
     pub fn find_user_by_username(_email: &str, conn: &mut PgConnection) -> Result<Employee, Error> {
         use crate::schema::employees::dsl::*;
         Ok(employees.filter(email.eq(_email)).first::<Employee>(conn)?)
     }
 
-    pub fn find_by_id(_id: i32, conn: &mut PgConnection) -> Result<Employee, Error> {
+    pub fn find_by_id(_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<Employee, Error> {
         use crate::schema::employees::dsl::*;
-        Ok(employees.find(_id).get_result::<Employee>(conn)?)
+        employees.find(_id).filter(organization_id.eq(_organization_id)).get_result::<Employee>(conn)
+            .optional()?
+            .ok_or_else(|| "Employee not found".into())
     }
 
-    pub fn find_by_role(_role: &str, conn: &mut PgConnection) -> Result<Vec<Employee>, Error>{
+    // Zone times are shown in to an employee, `fallback` when they have none
+    pub fn viewer_time_zone(_id: i32, _organization_id: i32, fallback: Tz, conn: &mut PgConnection) -> Result<Tz, Error> {
+        match Self::find_by_id(_id, _organization_id, conn)?.time_zone {
+            Some(zone) => Ok(parse_time_zone(&zone)?),
+            None => Ok(fallback)
+        }
+    }
+
+    pub fn find_by_role(_role: Role, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<Employee>, Error>{
         use crate::schema::employees::dsl::*;
-        Ok(employees.filter(role.eq(_role)).get_results::<Employee>(conn)?)
+        Ok(employees.filter(organization_id.eq(_organization_id)).filter(role.eq(_role.name())).filter(active.eq(true)).get_results::<Employee>(conn)?)
     }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bcrypt::{DEFAULT_COST, hash, verify};
+    use crate::constants;
+    use crate::models::employee::{DUMMY_PASSWORD_HASH, Employee};
+    use crate::utils::TokenClaims;
+
+    fn employee(password: &str) -> Employee {
+        Employee {
+            id: 1,
+            name: "SOC1".to_string(),
+            email: "SOC1@vsec.com.vn".to_string(),
+            password: hash(password, 4).unwrap(),
+            phone_number: None,
+            department: None,
+            role: "Employee".to_string(),
+            availability: None,
+            active: true,
+            deactivated_at: None,
+            anonymized_at: None,
+            organization_id: 1,
+            time_zone: None,
+        }
+    }
+
+    #[test]
+    fn test_correct_password_is_accepted() {
+        let rs = Employee::check_credentials(Some(employee("s3cret")), "s3cret");
+        assert_eq!(rs.unwrap().id, 1);
+    }
+
+    #[test]
+    fn test_wrong_password_is_rejected() {
+        let rs = Employee::check_credentials(Some(employee("s3cret")), "123");
+        assert_eq!(rs.unwrap_err().to_string(), constants::MESSAGE_LOGIN_FAILED);
+    }
+
+    #[test]
+    fn test_deactivated_user_is_rejected() {
+        let user = Employee { active: false, ..employee("s3cret") };
+        let rs = Employee::check_credentials(Some(user), "s3cret");
+        assert_eq!(rs.unwrap_err().to_string(), constants::MESSAGE_LOGIN_FAILED);
+    }
+
+    #[test]
+    fn test_unknown_user_gets_same_error() {
+        let rs = Employee::check_credentials(None, "s3cret");
+        assert_eq!(rs.unwrap_err().to_string(), constants::MESSAGE_LOGIN_FAILED);
+    }
+
+    #[test]
+    fn test_dummy_hash_costs_as_much_as_a_real_one() {
+        assert!(DUMMY_PASSWORD_HASH.starts_with(&format!("$2b${}$", DEFAULT_COST)));
+        assert!(!verify("s3cret", DUMMY_PASSWORD_HASH).unwrap());
+    }
+
+    #[test]
+    fn test_only_role_assigners_create_privileged_accounts() {
+        let claims = |role: &str| TokenClaims { sub: 1, role: role.to_string(), org: 1, sid: 1, iat: 0, exp: 0 };
+        assert!(Employee::check_role_assignment("Employee", &claims("Manager")).is_ok());
+        assert!(Employee::check_role_assignment("Admin", &claims("Manager")).is_err());
+        assert!(Employee::check_role_assignment("Manager", &claims("Manager")).is_err());
+        assert!(Employee::check_role_assignment("Manager", &claims("Admin")).is_ok());
+        assert!(Employee::check_role_assignment("Janitor", &claims("Admin")).is_err());
+    }
+}
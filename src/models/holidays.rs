@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use chrono::{Datelike, Duration, NaiveDate};
+use diesel::{AsChangeset, Identifiable, Insertable, PgConnection, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::error::Error;
+use crate::models::teams::SHIFT_SLOTS;
+use crate::schema::holidays;
+use diesel::prelude::*;
+
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Clone)]
+#[diesel(table_name = holidays)]
+pub struct Holiday {
+    pub id: i32,
+    pub organization_id: i32,
+    // none for the whole organization, otherwise only teams of that region
+    pub region: Option<String>,
+    pub date: NaiveDate,
+    pub name: String,
+    // employees per slot, e.g. {"H": 0, "D": 2}
+    pub staffing: Option<Value>,
+    pub pay_multiplier: f64
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable, AsChangeset)]
+#[diesel(table_name = holidays, treat_none_as_null = true)]
+pub struct HolidayDTO {
+    #[serde(default)]
+    pub region: Option<String>,
+    pub date: NaiveDate,
+    pub name: String,
+    #[serde(default)]
+    pub staffing: Option<Value>,
+    #[serde(default = "default_pay_multiplier")]
+    pub pay_multiplier: f64
+}
+
+fn default_pay_multiplier() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HolidayFilter {
+    pub year: Option<i32>,
+    pub region: Option<String>
+}
+
+// Where imported holidays go and how they are paid
+#[derive(Debug, Deserialize)]
+pub struct HolidayImportFilter {
+    pub region: Option<String>,
+    pub pay_multiplier: Option<f64>
+}
+
+#[derive(Debug, Serialize)]
+pub struct HolidayImport {
+    pub imported: usize,
+    // dates that already had a holiday
+    pub skipped: usize
+}
+
+impl HolidayDTO {
+    fn validate(self) -> Result<HolidayDTO, Error> {
+        let name = self.name.trim().to_string();
+        if name.is_empty() {
+            return Err("Holiday name is required".into())
+        }
+        let region = self.region.map(|region| region.trim().to_string()).filter(|region| !region.is_empty());
+        if !(self.pay_multiplier.is_finite() && self.pay_multiplier > 0.0) {
+            return Err("Pay multiplier must be positive".into())
+        }
+        if let Some(staffing) = &self.staffing {
+            let slots = staffing.as_object().ok_or("Staffing must map slots to a number of employees")?;
+            for (slot, count) in slots {
+                if !SHIFT_SLOTS.contains(&slot.as_str()) {
+                    return Err(format!("Unknown shift slot '{}', expected S, C, D or H", slot).into())
+                }
+                if !count.as_i64().is_some_and(|count| (0..=i32::MAX as i64).contains(&count)) {
+                    return Err(format!("Staffing of slot {} must be a number of employees", slot).into())
+                }
+            }
+        }
+        Ok(HolidayDTO { name, region, ..self })
+    }
+}
+
+impl Holiday {
+    pub fn applies_to(&self, region: Option<&str>) -> bool {
+        self.region.is_none() || self.region.as_deref() == region
+    }
+
+    // Slot -> employees that day, for the slots the holiday overrides
+    pub fn staffing_by_slot(&self) -> HashMap<String, i32> {
+        self.staffing.as_ref()
+            .and_then(|staffing| staffing.as_object())
+            .map(|slots| slots.iter()
+                .filter_map(|(slot, count)| count.as_i64().map(|count| (slot.clone(), count as i32)))
+                .collect())
+            .unwrap_or_default()
+    }
+
+    pub fn new(holiday_dto: HolidayDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<Holiday, Error> {
+        let holiday_dto = holiday_dto.validate()?;
+        Self::check_unique(&holiday_dto, None, _organization_id, conn)?;
+        Ok(diesel::insert_into(holidays::table)
+            .values((&holiday_dto, holidays::organization_id.eq(_organization_id)))
+            .get_result::<Holiday>(conn)?)
+    }
+
+    pub fn update(_id: i32, holiday_dto: HolidayDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<Holiday, Error> {
+        Self::find_by_id(_id, _organization_id, conn)?;
+        let holiday_dto = holiday_dto.validate()?;
+        Self::check_unique(&holiday_dto, Some(_id), _organization_id, conn)?;
+        Ok(diesel::update(holidays::table.find(_id)).set(&holiday_dto).get_result::<Holiday>(conn)?)
+    }
+
+    pub fn delete(_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<String, Error> {
+        let holiday = Self::find_by_id(_id, _organization_id, conn)?;
+        diesel::delete(holidays::table.find(_id)).execute(conn)?;
+        Ok(format!("Holiday '{}' deleted", holiday.name))
+    }
+
+    fn check_unique(holiday_dto: &HolidayDTO, except: Option<i32>, _organization_id: i32, conn: &mut PgConnection) -> Result<(), Error> {
+        let mut query = holidays::table
+            .filter(holidays::organization_id.eq(_organization_id))
+            .filter(holidays::date.eq(holiday_dto.date))
+            .into_boxed();
+        query = match &holiday_dto.region {
+            Some(_region) => query.filter(holidays::region.eq(_region)),
+            None => query.filter(holidays::region.is_null())
+        };
+        if let Some(except) = except {
+            query = query.filter(holidays::id.ne(except));
+        }
+        if query.count().get_result::<i64>(conn)? > 0 {
+            return Err(format!("There is already a holiday on {}", holiday_dto.date).into())
+        }
+        Ok(())
+    }
+
+    pub fn find_by_id(_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<Holiday, Error> {
+        holidays::table.find(_id).filter(holidays::organization_id.eq(_organization_id)).first::<Holiday>(conn)
+            .optional()?
+            .ok_or_else(|| "Holiday not found".into())
+    }
+
+    // With a region, the holidays of the whole organization are listed too
+    pub fn find_all(filter: HolidayFilter, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<Holiday>, Error> {
+        let mut query = holidays::table.filter(holidays::organization_id.eq(_organization_id)).into_boxed();
+        if let Some(year) = filter.year {
+            let first = NaiveDate::from_ymd_opt(year, 1, 1).ok_or("Invalid year")?;
+            let last = NaiveDate::from_ymd_opt(year, 12, 31).ok_or("Invalid year")?;
+            query = query.filter(holidays::date.between(first, last));
+        }
+        if let Some(_region) = filter.region {
+            query = query.filter(holidays::region.is_null().or(holidays::region.eq(_region)));
+        }
+        Ok(query.order_by(holidays::date).load::<Holiday>(conn)?)
+    }
+
+    // Holidays of every region between two dates, see `applies_to`
+    pub fn find_between(start: NaiveDate, end: NaiveDate, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<Holiday>, Error> {
+        Ok(holidays::table
+            .filter(holidays::organization_id.eq(_organization_id))
+            .filter(holidays::date.between(start, end))
+            .order_by(holidays::date)
+            .load::<Holiday>(conn)?)
+    }
+
+    // The holiday of `region` on a date, a regional one takes over one of the whole organization
+    pub fn on_date<'a>(holidays_in_range: &'a [Holiday], date: NaiveDate, region: Option<&str>) -> Option<&'a Holiday> {
+        holidays_in_range.iter()
+            .filter(|holiday| holiday.date == date && holiday.applies_to(region))
+            .max_by_key(|holiday| holiday.region.is_some())
+    }
+
+    // Day of month -> slot staffing of the holidays of `region` in a month
+    pub fn by_day(holidays_in_month: &[Holiday], region: Option<&str>) -> HashMap<i32, HashMap<String, i32>> {
+        let mut days: HashMap<i32, HashMap<String, i32>> = HashMap::new();
+        for holiday in holidays_in_month.iter().filter(|holiday| holiday.applies_to(region)) {
+            // a regional holiday on the same day as an organization one refines its staffing
+            let staffing = days.entry(holiday.date.day() as i32).or_default();
+            staffing.extend(holiday.staffing_by_slot());
+        }
+        days
+    }
+
+    // Holidays from an iCalendar file, dates that already have one are left as they are
+    pub fn import_ical(calendar: &str, filter: HolidayImportFilter, _organization_id: i32, conn: &mut PgConnection) -> Result<HolidayImport, Error> {
+        let events = parse_ical(calendar)?;
+        let mut result = HolidayImport { imported: 0, skipped: 0 };
+        conn.transaction::<(), Error, _>(|conn| {
+            for (_date, _name) in events {
+                let holiday_dto = HolidayDTO {
+                    region: filter.region.clone(),
+                    date: _date,
+                    name: _name,
+                    staffing: None,
+                    pay_multiplier: filter.pay_multiplier.unwrap_or(default_pay_multiplier()),
+                }.validate()?;
+                if Self::check_unique(&holiday_dto, None, _organization_id, conn).is_err() {
+                    result.skipped += 1;
+                    continue;
+                }
+                diesel::insert_into(holidays::table)
+                    .values((&holiday_dto, holidays::organization_id.eq(_organization_id)))
+                    .execute(conn)?;
+                result.imported += 1;
+            }
+            Ok(())
+        })?;
+        Ok(result)
+    }
+}
+
+/*
+    Days and names of the VEVENTs of an iCalendar file (RFC 5545). An event spanning several days
+    gives one holiday per day, DTEND being exclusive. Times are ignored, only the date counts
+ */
+pub fn parse_ical(calendar: &str) -> Result<Vec<(NaiveDate, String)>, Error> {
+    // folded lines continue with a space or a tab
+    let mut lines: Vec<String> = Vec::new();
+    for line in calendar.lines() {
+        match line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')) {
+            Some(rest) if !lines.is_empty() => lines.last_mut().unwrap().push_str(rest),
+            _ => lines.push(line.to_string())
+        }
+    }
+
+    let mut events = Vec::new();
+    let mut current: Option<(Option<NaiveDate>, Option<NaiveDate>, Option<String>)> = None;
+    for line in lines {
+        let (key, value) = match line.split_once(':') {
+            Some((key, value)) => (key.split(';').next().unwrap_or("").to_ascii_uppercase(), value.trim()),
+            None => continue
+        };
+        match (key.as_str(), current.as_mut()) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => current = Some((None, None, None)),
+            ("DTSTART", Some(event)) => event.0 = Some(ical_date(value)?),
+            ("DTEND", Some(event)) => event.1 = Some(ical_date(value)?),
+            ("SUMMARY", Some(event)) => event.2 = Some(ical_text(value)),
+            ("END", Some(_)) if value.eq_ignore_ascii_case("VEVENT") => {
+                let (start, end, summary) = current.take().unwrap();
+                let start = start.ok_or("VEVENT without DTSTART")?;
+                let name = summary.unwrap_or_else(|| "Holiday".to_string());
+                let end = end.filter(|end| *end > start).unwrap_or(start + Duration::days(1));
+                let mut day = start;
+                while day < end {
+                    events.push((day, name.clone()));
+                    day += Duration::days(1);
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(events)
+}
+
+// "20241225" or "20241225T000000Z"
+fn ical_date(value: &str) -> Result<NaiveDate, Error> {
+    value.get(..8)
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+        .ok_or_else(|| format!("Invalid date '{}' in calendar", value).into())
+}
+
+fn ical_text(value: &str) -> String {
+    value.replace("\\n", " ").replace("\\N", " ").replace("\\,", ",").replace("\\;", ";").replace("\\\\", "\\")
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use serde_json::json;
+    use crate::models::holidays::{HolidayDTO, parse_ical};
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_parse_ical() {
+        let calendar = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nDTSTART;VALUE=DATE:20241225\r\nDTEND;VALUE=DATE:20241227\r\n\
+            SUMMARY:Christmas\\, Boxing\r\n  Day\r\nEND:VEVENT\r\nBEGIN:VEVENT\r\nDTSTART:20250101T000000Z\r\nSUMMARY:New Year\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n";
+        let events = parse_ical(calendar).unwrap();
+        assert_eq!(events, vec![
+            (date(2024, 12, 25), "Christmas, Boxing Day".to_string()),
+            (date(2024, 12, 26), "Christmas, Boxing Day".to_string()),
+            (date(2025, 1, 1), "New Year".to_string()),
+        ]);
+        assert!(parse_ical("BEGIN:VEVENT\nDTSTART:2024\nEND:VEVENT").is_err());
+    }
+
+    #[test]
+    fn test_validate_staffing() {
+        let holiday = |staffing| HolidayDTO { region: Some(" ".to_string()), date: date(2024, 12, 25), name: " Christmas ".to_string(), staffing, pay_multiplier: 2.0 };
+        let valid = holiday(Some(json!({"H": 0, "D": 2}))).validate().unwrap();
+        assert_eq!(valid.name, "Christmas");
+        assert_eq!(valid.region, None);
+        assert!(holiday(Some(json!({"X": 1}))).validate().is_err());
+        assert!(holiday(Some(json!({"D": -1}))).validate().is_err());
+        assert!(holiday(Some(json!([1]))).validate().is_err());
+    }
+}
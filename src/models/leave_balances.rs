@@ -0,0 +1,350 @@
+use chrono::{NaiveDate, NaiveDateTime};
+use diesel::{Identifiable, Insertable, PgConnection, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use crate::constants;
+use crate::error::Error;
+use crate::models::employee::Employee;
+use crate::models::leave_requests::{leave_code, leave_days, LeaveRequest};
+use crate::permissions::{department_scope, Permission};
+use crate::schema::{leave_balance_history, leave_entitlements};
+use crate::utils::TokenClaims;
+use diesel::prelude::*;
+
+// Kinds of balance changes recorded in the history
+pub const BALANCE_ACCRUAL: &str = "accrual";
+pub const BALANCE_USAGE: &str = "usage";
+pub const BALANCE_REFUND: &str = "refund";
+pub const BALANCE_ADJUSTMENT: &str = "adjustment";
+pub const BALANCE_EXPIRY: &str = "expiry";
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Clone)]
+#[diesel(table_name = leave_entitlements)]
+pub struct LeaveEntitlement {
+    pub id: i32,
+    pub employee_id: i32,
+    pub leave_type: String,
+    pub accrual_per_month: f64,
+    pub carry_over_limit: Option<f64>,
+    pub balance: f64
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = leave_entitlements)]
+pub struct LeaveEntitlementDTO {
+    pub employee_id: i32,
+    pub leave_type: String,
+    pub accrual_per_month: f64,
+    pub carry_over_limit: Option<f64>
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable)]
+#[diesel(table_name = leave_balance_history)]
+pub struct LeaveBalanceHistory {
+    pub id: i32,
+    pub entitlement_id: i32,
+    pub kind: String,
+    pub change_days: f64,
+    pub balance_after: f64,
+    pub period: Option<NaiveDate>,
+    pub note: Option<String>,
+    pub created_at: NaiveDateTime
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = leave_balance_history)]
+struct NewLeaveBalanceHistory<'a> {
+    entitlement_id: i32,
+    kind: &'a str,
+    change_days: f64,
+    balance_after: f64,
+    period: Option<NaiveDate>,
+    note: Option<String>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdjustmentDTO {
+    pub employee_id: i32,
+    pub leave_type: String,
+    pub days: f64,
+    pub note: Option<String>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PeriodDTO {
+    pub month: Option<i32>,
+    pub year: i32
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BalanceReport {
+    pub employee_id: i32,
+    pub employee_name: String,
+    pub leave_type: String,
+    pub balance: f64,
+    pub pending: f64,
+    pub available: f64
+}
+
+impl LeaveEntitlement {
+    // Create the entitlement or change its accrual rule, the balance is left untouched
+    pub fn upsert(entitlement_dto: LeaveEntitlementDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<LeaveEntitlement, Error> {
+        use crate::schema::leave_entitlements::dsl::*;
+        Employee::find_by_id(entitlement_dto.employee_id, _organization_id, conn)?;
+        if leave_code(&entitlement_dto.leave_type).is_none() {
+            return Err(format!("Unknown leave type '{}'", entitlement_dto.leave_type).into())
+        }
+        if entitlement_dto.accrual_per_month < 0.0 || entitlement_dto.carry_over_limit.is_some_and(|limit| limit < 0.0) {
+            return Err("Accrual and carry-over limit must not be negative".into())
+        }
+        Ok(diesel::insert_into(leave_entitlements)
+            .values(&entitlement_dto)
+            .on_conflict((employee_id, leave_type))
+            .do_update()
+            .set((accrual_per_month.eq(entitlement_dto.accrual_per_month), carry_over_limit.eq(entitlement_dto.carry_over_limit)))
+            .get_result::<LeaveEntitlement>(conn)?)
+    }
+
+    pub fn find(_employee_id: i32, _leave_type: &str, conn: &mut PgConnection) -> Result<Option<LeaveEntitlement>, Error> {
+        use crate::schema::leave_entitlements::dsl::*;
+        Ok(leave_entitlements
+            .filter(employee_id.eq(_employee_id))
+            .filter(leave_type.eq(_leave_type))
+            .first::<LeaveEntitlement>(conn)
+            .optional()?)
+    }
+
+    // Same as `find` with the row locked until the caller's transaction ends, so concurrent uses of a balance queue up
+    fn find_for_update(_employee_id: i32, _leave_type: &str, conn: &mut PgConnection) -> Result<Option<LeaveEntitlement>, Error> {
+        use crate::schema::leave_entitlements::dsl::*;
+        Ok(leave_entitlements
+            .filter(employee_id.eq(_employee_id))
+            .filter(leave_type.eq(_leave_type))
+            .for_update()
+            .first::<LeaveEntitlement>(conn)
+            .optional()?)
+    }
+
+    // Change the balance and record why, callers run this inside their own transaction
+    fn apply(entitlement_id: i32, kind: &str, days: f64, period: Option<NaiveDate>, note: Option<String>, conn: &mut PgConnection) -> QueryResult<LeaveEntitlement> {
+        use crate::schema::leave_entitlements::dsl::*;
+        let entitlement = diesel::update(leave_entitlements.find(entitlement_id))
+            .set(balance.eq(balance + days))
+            .get_result::<LeaveEntitlement>(conn)?;
+        diesel::insert_into(leave_balance_history::table)
+            .values(NewLeaveBalanceHistory {
+                entitlement_id,
+                kind,
+                change_days: days,
+                balance_after: entitlement.balance,
+                period,
+                note,
+            })
+            .execute(conn)?;
+        Ok(entitlement)
+    }
+
+    /*
+        Leave types without an entitlement are not balance tracked (e.g. sick leave),
+        for the others the days requested must fit in the balance minus what is already pending.
+        Callers file the request in the same transaction, the balance stays locked until it ends
+     */
+    pub fn check_available(_employee_id: i32, _leave_type: &str, days: f64, conn: &mut PgConnection) -> Result<(), Error> {
+        let entitlement = match Self::find_for_update(_employee_id, _leave_type, conn)? {
+            Some(entitlement) => entitlement,
+            None => return Ok(())
+        };
+        let available = entitlement.balance - Self::pending_days(_employee_id, _leave_type, conn)?;
+        if days > available {
+            return Err(format!("Not enough {} balance: {} days requested, {} available", _leave_type, days, available).into())
+        }
+        Ok(())
+    }
+
+    fn pending_days(_employee_id: i32, _leave_type: &str, conn: &mut PgConnection) -> Result<f64, Error> {
+        use crate::schema::leave_requests::dsl::*;
+        let pending = leave_requests
+            .filter(employee_id.eq(_employee_id))
+            .filter(leave_type.eq(_leave_type))
+            .filter(status.eq(constants::LEAVE_PENDING))
+            .load::<LeaveRequest>(conn)?;
+        Ok(pending.iter().fold(0.0, |days, leave| days + leave_days(leave.start_date, leave.end_date)))
+    }
+
+    // Deduct an approved leave from the balance, fails if it no longer fits. Run inside the caller's transaction
+    pub fn use_leave(leave: &LeaveRequest, conn: &mut PgConnection) -> Result<(), Error> {
+        let entitlement = match Self::find_for_update(leave.employee_id, &leave.leave_type, conn)? {
+            Some(entitlement) => entitlement,
+            None => return Ok(())
+        };
+        let days = leave_days(leave.start_date, leave.end_date);
+        if days > entitlement.balance {
+            return Err(format!("Not enough {} balance: {} days requested, {} left", leave.leave_type, days, entitlement.balance).into())
+        }
+        Self::apply(entitlement.id, BALANCE_USAGE, -days, None, Some(format!("Leave request #{}", leave.id)), conn)?;
+        Ok(())
+    }
+
+    // Give back the days of an approved leave that got cancelled
+    pub fn refund_leave(leave: &LeaveRequest, conn: &mut PgConnection) -> Result<(), Error> {
+        if let Some(entitlement) = Self::find(leave.employee_id, &leave.leave_type, conn)? {
+            let days = leave_days(leave.start_date, leave.end_date);
+            Self::apply(entitlement.id, BALANCE_REFUND, days, None, Some(format!("Leave request #{}", leave.id)), conn)?;
+        }
+        Ok(())
+    }
+
+    pub fn adjust(adjustment_dto: AdjustmentDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<LeaveEntitlement, Error> {
+        Employee::find_by_id(adjustment_dto.employee_id, _organization_id, conn)?;
+        let entitlement = Self::find(adjustment_dto.employee_id, &adjustment_dto.leave_type, conn)?
+            .ok_or("No entitlement for this employee and leave type")?;
+        Ok(conn.transaction(|conn| {
+            Self::apply(entitlement.id, BALANCE_ADJUSTMENT, adjustment_dto.days, None, adjustment_dto.note, conn)
+        })?)
+    }
+
+    /*
+        Credit the monthly accrual to every employee who worked at least one shift in the month.
+        Each month is only accrued once per entitlement
+     */
+    pub fn accrue(month: i32, year: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<String, Error> {
+        use crate::schema::{employees, leave_entitlements, schedules};
+        let start_date = NaiveDate::from_ymd_opt(year, month as u32, 1).ok_or("Invalid Month")?;
+        let end_date = match start_date.checked_add_months(chrono::Months::new(1)).and_then(|d| d.pred_opt()) {
+            Some(date) => date,
+            None => return Err("Invalid Month".into())
+        };
+        let entitlements = leave_entitlements::table
+            .inner_join(employees::table)
+            .filter(employees::organization_id.eq(_organization_id))
+            .filter(leave_entitlements::accrual_per_month.gt(0.0))
+            .select(leave_entitlements::all_columns)
+            .load::<LeaveEntitlement>(conn)?;
+        let worked = schedules::table
+            .filter(schedules::organization_id.eq(_organization_id))
+            .filter(schedules::data.between(start_date, end_date))
+            .select(schedules::employee_id)
+            .distinct()
+            .load::<i32>(conn)?;
+
+        let mut accrued = 0;
+        for entitlement in entitlements.iter().filter(|e| worked.contains(&e.employee_id)) {
+            if Self::has_period(entitlement.id, BALANCE_ACCRUAL, start_date, conn)? {
+                continue;
+            }
+            conn.transaction(|conn| {
+                Self::apply(entitlement.id, BALANCE_ACCRUAL, entitlement.accrual_per_month, Some(start_date), None, conn)
+            })?;
+            accrued += 1;
+        }
+        Ok(format!("Accrued {} entitlements for {}/{}", accrued, month, year))
+    }
+
+    /*
+        Year end: balances above the carry-over limit are cut down to it,
+        the excess is recorded as expired
+     */
+    pub fn carry_over(year: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<String, Error> {
+        use crate::schema::{employees, leave_entitlements};
+        let period = NaiveDate::from_ymd_opt(year, 12, 31).ok_or("Invalid Year")?;
+        let entitlements = leave_entitlements::table
+            .inner_join(employees::table)
+            .filter(employees::organization_id.eq(_organization_id))
+            .filter(leave_entitlements::carry_over_limit.is_not_null())
+            .select(leave_entitlements::all_columns)
+            .load::<LeaveEntitlement>(conn)?;
+
+        let mut expired = 0;
+        for entitlement in entitlements {
+            let excess = expiring_days(entitlement.balance, entitlement.carry_over_limit);
+            if excess <= 0.0 || Self::has_period(entitlement.id, BALANCE_EXPIRY, period, conn)? {
+                continue;
+            }
+            conn.transaction(|conn| {
+                Self::apply(entitlement.id, BALANCE_EXPIRY, -excess, Some(period), Some(format!("Carry-over limit for {}", year)), conn)
+            })?;
+            expired += 1;
+        }
+        Ok(format!("Carried over {} with {} balances capped", year, expired))
+    }
+
+    fn has_period(_entitlement_id: i32, _kind: &str, _period: NaiveDate, conn: &mut PgConnection) -> Result<bool, Error> {
+        use crate::schema::leave_balance_history::dsl::*;
+        let count = leave_balance_history
+            .filter(entitlement_id.eq(_entitlement_id))
+            .filter(kind.eq(_kind))
+            .filter(period.eq(_period))
+            .count()
+            .get_result::<i64>(conn)?;
+        Ok(count > 0)
+    }
+
+    /*
+        Remaining balances, for the caller only or, for managers,
+        for their department (everyone if they have none)
+     */
+    pub fn report(claims: &TokenClaims, conn: &mut PgConnection) -> Result<Vec<BalanceReport>, Error> {
+        use crate::schema::{employees, leave_entitlements};
+        let mut query = leave_entitlements::table
+            .inner_join(employees::table)
+            .filter(employees::organization_id.eq(claims.org))
+            .select((leave_entitlements::all_columns, employees::name))
+            .into_boxed();
+        query = match department_scope(claims, conn)? {
+            Some(Some(dep)) => query.filter(employees::department.eq(dep)),
+            Some(None) => query,
+            None => query.filter(leave_entitlements::employee_id.eq(claims.sub))
+        };
+        let rows = query
+            .order_by((leave_entitlements::employee_id, leave_entitlements::leave_type))
+            .load::<(LeaveEntitlement, String)>(conn)?;
+
+        let mut rs = Vec::new();
+        for (entitlement, employee_name) in rows {
+            let pending = Self::pending_days(entitlement.employee_id, &entitlement.leave_type, conn)?;
+            rs.push(BalanceReport {
+                employee_id: entitlement.employee_id,
+                employee_name,
+                leave_type: entitlement.leave_type,
+                balance: entitlement.balance,
+                pending,
+                available: entitlement.balance - pending,
+            });
+        }
+        Ok(rs)
+    }
+
+    pub fn history(_employee_id: i32, claims: &TokenClaims, conn: &mut PgConnection) -> Result<Vec<LeaveBalanceHistory>, Error> {
+        use crate::schema::{leave_balance_history, leave_entitlements};
+        if _employee_id != claims.sub && !claims.can(Permission::ViewTeam) {
+            return Err("You can only see your own balance history".into())
+        }
+        Employee::find_by_id(_employee_id, claims.org, conn)?;
+        Ok(leave_balance_history::table
+            .inner_join(leave_entitlements::table)
+            .filter(leave_entitlements::employee_id.eq(_employee_id))
+            .select(leave_balance_history::all_columns)
+            .order_by(leave_balance_history::created_at.desc())
+            .load::<LeaveBalanceHistory>(conn)?)
+    }
+}
+
+// Days above the carry-over limit that expire at year end
+pub fn expiring_days(balance: f64, carry_over_limit: Option<f64>) -> f64 {
+    match carry_over_limit {
+        Some(limit) if balance > limit => balance - limit,
+        _ => 0.0
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::models::leave_balances::expiring_days;
+
+    #[test]
+    fn test_expiring_days() {
+        assert_eq!(expiring_days(12.5, Some(5.0)), 7.5);
+        assert_eq!(expiring_days(3.0, Some(5.0)), 0.0);
+        assert_eq!(expiring_days(30.0, None), 0.0);
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use diesel::{Identifiable, Insertable, PgConnection, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use crate::constants;
+use crate::error::Error;
+use crate::models::employee::Employee;
+use crate::models::leave_balances::LeaveEntitlement;
+use crate::models::teams::Team;
+use crate::permissions::{department_scope, Permission};
+use crate::response::Page;
+use crate::schema::leave_requests;
+use crate::utils::TokenClaims;
+use diesel::prelude::*;
+
+/*
+    Supported leave types and the code they get in the monthly view and the CSV export.
+    Codes must not clash with shift names (S, C, D, H) or "N"
+ */
+pub const LEAVE_TYPES: [(&str, &str); 3] = [
+    ("vacation", "V"),
+    ("sick", "SL"),
+    ("training", "T"),
+];
+
+pub fn leave_code(leave_type: &str) -> Option<&'static str> {
+    LEAVE_TYPES.iter().find(|(name, _)| *name == leave_type).map(|(_, code)| *code)
+}
+
+// Calendar days covered by a leave, both ends included
+pub fn leave_days(start: NaiveDate, end: NaiveDate) -> f64 {
+    ((end - start).num_days() + 1) as f64
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Clone)]
+#[diesel(table_name = leave_requests)]
+pub struct LeaveRequest {
+    pub id: i32,
+    pub employee_id: i32,
+    pub leave_type: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub reason: Option<String>,
+    pub status: String,
+    pub manager_comment: Option<String>,
+    pub reviewed_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub organization_id: i32
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = leave_requests)]
+pub struct LeaveRequestDTO {
+    // managers may file leave for someone else, employees always file their own
+    pub employee_id: Option<i32>,
+    pub leave_type: String,
+    pub start_date: NaiveDate,
+    pub end_date: NaiveDate,
+    pub reason: Option<String>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReviewDTO {
+    pub comment: Option<String>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LeaveFilter {
+    pub status: Option<String>,
+    pub employee_id: Option<i32>,
+    pub leave_type: Option<String>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub page_num: Option<i64>,
+    pub page_size: Option<i64>
+}
+
+impl LeaveRequest {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(leave_dto: LeaveRequestDTO, claims: &TokenClaims, conn: &mut PgConnection) -> Result<String, Error> {
+        use crate::schema::leave_requests::dsl::*;
+        let owner = leave_dto.employee_id.unwrap_or(claims.sub);
+        if owner != claims.sub && !claims.can(Permission::FileForOthers) {
+            return Err("You can only request leave for yourself".into())
+        }
+        Employee::find_by_id(owner, claims.org, conn)?;
+        if leave_code(&leave_dto.leave_type).is_none() {
+            return Err(format!("Unknown leave type '{}'", leave_dto.leave_type).into())
+        }
+        if leave_dto.start_date > leave_dto.end_date {
+            return Err("Start date must not be after end date".into())
+        }
+
+        let overlapping = leave_requests
+            .filter(employee_id.eq(owner))
+            .filter(status.eq_any([constants::LEAVE_PENDING, constants::LEAVE_APPROVED]))
+            .filter(start_date.le(leave_dto.end_date))
+            .filter(end_date.ge(leave_dto.start_date))
+            .count()
+            .get_result::<i64>(conn)?;
+        if overlapping > 0 {
+            return Err("Leave overlaps an existing request".into())
+        }
+
+        let new_leave = LeaveRequestDTO {
+            employee_id: Some(owner),
+            ..leave_dto
+        };
+        conn.transaction::<(), Error, _>(|conn| {
+            LeaveEntitlement::check_available(owner, &new_leave.leave_type, leave_days(new_leave.start_date, new_leave.end_date), conn)?;
+            diesel::insert_into(leave_requests).values((&new_leave, organization_id.eq(claims.org))).execute(conn)?;
+            Ok(())
+        })?;
+        Ok(constants::DATABASE_INSERT_SUCCESS.to_string())
+    }
+
+    pub fn find_by_id(_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<LeaveRequest, Error> {
+        use crate::schema::leave_requests::dsl::*;
+        leave_requests.find(_id).filter(organization_id.eq(_organization_id)).first::<LeaveRequest>(conn)
+            .optional()?
+            .ok_or_else(|| "Leave request not found".into())
+    }
+
+    /*
+        Approve or reject a pending request with an optional comment for the employee.
+        Reviewers only reach the people they manage, never themselves
+     */
+    pub fn review(_id: i32, approve: bool, review_dto: ReviewDTO, claims: &TokenClaims, conn: &mut PgConnection) -> Result<LeaveRequest, Error> {
+        use crate::schema::leave_requests::dsl::*;
+        let leave = Self::find_by_id(_id, claims.org, conn)?;
+        if leave.employee_id == claims.sub {
+            return Err("You can't review your own leave".into())
+        }
+        Team::check_manages_employee(leave.employee_id, claims, conn)?;
+        let new_status = if approve { constants::LEAVE_APPROVED } else { constants::LEAVE_REJECTED };
+        conn.transaction(|conn| {
+            // the update only hits a pending request, so two concurrent approvals can't both use the balance
+            let leave = diesel::update(leave_requests.find(_id).filter(status.eq(constants::LEAVE_PENDING)))
+                .set((status.eq(new_status), manager_comment.eq(review_dto.comment), reviewed_by.eq(claims.sub)))
+                .get_result::<LeaveRequest>(conn)
+                .optional()?
+                .ok_or("This leave request is no longer pending")?;
+            if approve {
+                LeaveEntitlement::use_leave(&leave, conn)?;
+            }
+            Ok(leave)
+        })
+    }
+
+    // The owner withdraws a request that is pending or approved
+    pub fn cancel(_id: i32, claims: &TokenClaims, conn: &mut PgConnection) -> Result<LeaveRequest, Error> {
+        use crate::schema::leave_requests::dsl::*;
+        let leave = Self::find_by_id(_id, claims.org, conn)?;
+        if leave.employee_id != claims.sub {
+            return Err("You can only cancel your own leave".into())
+        }
+        if leave.status != constants::LEAVE_PENDING && leave.status != constants::LEAVE_APPROVED {
+            return Err("This leave request can no longer be cancelled".into())
+        }
+        conn.transaction(|conn| {
+            if leave.status == constants::LEAVE_APPROVED {
+                LeaveEntitlement::refund_leave(&leave, conn)?;
+            }
+            Ok(diesel::update(leave_requests.find(_id))
+                .set(status.eq(constants::LEAVE_CANCELLED))
+                .get_result::<LeaveRequest>(conn)?)
+        })
+    }
+
+    /*
+        Employees only see their own leave,
+        managers see the leave of their department (everyone's if they have none)
+     */
+    pub fn find_all(filter: LeaveFilter, claims: &TokenClaims, conn: &mut PgConnection) -> Result<Page<LeaveRequest>, Error> {
+        use crate::schema::{employees, leave_requests};
+
+        let page_num = filter.page_num.unwrap_or(constants::DEFAULT_PAGE_NUM).max(1);
+        let page_size = filter.page_size.unwrap_or(constants::DEFAULT_PER_PAGE).clamp(1, 100);
+        let manager_department = department_scope(claims, conn)?;
+
+        let build_query = || {
+            let mut query = leave_requests::table
+                .filter(leave_requests::organization_id.eq(claims.org))
+                .into_boxed();
+            query = match &manager_department {
+                Some(Some(dep)) => query.filter(leave_requests::employee_id.eq_any(
+                    employees::table.filter(employees::department.eq(dep.clone())).select(employees::id)
+                )),
+                Some(None) => query,
+                None => query.filter(leave_requests::employee_id.eq(claims.sub))
+            };
+            if let Some(_status) = &filter.status {
+                query = query.filter(leave_requests::status.eq(_status.clone()));
+            }
+            if let Some(_employee_id) = filter.employee_id {
+                query = query.filter(leave_requests::employee_id.eq(_employee_id));
+            }
+            if let Some(_leave_type) = &filter.leave_type {
+                query = query.filter(leave_requests::leave_type.eq(_leave_type.clone()));
+            }
+            if let Some(_from) = filter.from {
+                query = query.filter(leave_requests::end_date.ge(_from));
+            }
+            if let Some(_to) = filter.to {
+                query = query.filter(leave_requests::start_date.le(_to));
+            }
+            query
+        };
+
+        let total_elements = build_query().count().get_result::<i64>(conn)?;
+        let data = build_query()
+            .order_by(leave_requests::start_date.desc())
+            .limit(page_size)
+            .offset((page_num - 1) * page_size)
+            .load::<LeaveRequest>(conn)?;
+
+        Ok(Page::new(constants::MESSAGE_OK, data, page_num, page_size, total_elements))
+    }
+
+    pub fn find_approved_between(from: NaiveDate, to: NaiveDate, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<LeaveRequest>, Error> {
+        use crate::schema::leave_requests::dsl::*;
+        Ok(leave_requests
+            .filter(organization_id.eq(_organization_id))
+            .filter(status.eq(constants::LEAVE_APPROVED))
+            .filter(start_date.le(to))
+            .filter(end_date.ge(from))
+            .load::<LeaveRequest>(conn)?)
+    }
+
+    /*
+        Leave code of every (day of month, employee id) covered by `leaves` in the given month,
+        e.g. (3, 7) -> "V" when employee 7 is on vacation on the 3rd
+     */
+    pub fn codes_by_day(leaves: &[LeaveRequest], month: i32, year: i32) -> HashMap<(i32, i32), &'static str> {
+        let mut map = HashMap::new();
+        for leave in leaves {
+            let code = match leave_code(&leave.leave_type) {
+                Some(code) => code,
+                None => continue
+            };
+            let mut day = leave.start_date;
+            while day <= leave.end_date {
+                if day.month() as i32 == month && day.year() == year {
+                    map.insert((day.day() as i32, leave.employee_id), code);
+                }
+                day = match day.succ_opt() {
+                    Some(next) => next,
+                    None => break
+                };
+            }
+        }
+        map
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use crate::models::leave_requests::{leave_code, LeaveRequest};
+
+    fn leave(employee_id: i32, leave_type: &str, start: (i32, u32, u32), end: (i32, u32, u32)) -> LeaveRequest {
+        LeaveRequest {
+            id: 1,
+            employee_id,
+            leave_type: leave_type.to_string(),
+            start_date: NaiveDate::from_ymd_opt(start.0, start.1, start.2).unwrap(),
+            end_date: NaiveDate::from_ymd_opt(end.0, end.1, end.2).unwrap(),
+            reason: None,
+            status: "approved".to_string(),
+            manager_comment: None,
+            reviewed_by: None,
+            created_at: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap(),
+            organization_id: 1,
+        }
+    }
+
+    #[test]
+    fn test_leave_codes() {
+        assert_eq!(leave_code("vacation"), Some("V"));
+        assert_eq!(leave_code("sick"), Some("SL"));
+        assert_eq!(leave_code("unknown"), None);
+    }
+
+    #[test]
+    fn test_codes_by_day_clips_to_month() {
+        let leaves = vec![leave(7, "vacation", (2024, 1, 30), (2024, 2, 2)), leave(8, "sick", (2024, 2, 10), (2024, 2, 10))];
+        let map = LeaveRequest::codes_by_day(&leaves, 2, 2024);
+        assert_eq!(map.len(), 3);
+        assert_eq!(map.get(&(1, 7)), Some(&"V"));
+        assert_eq!(map.get(&(2, 7)), Some(&"V"));
+        assert_eq!(map.get(&(10, 8)), Some(&"SL"));
+        assert_eq!(map.get(&(30, 7)), None);
+    }
+}
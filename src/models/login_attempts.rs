@@ -0,0 +1,186 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{Insertable, PgConnection, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use crate::config::login::LoginPolicy;
+use crate::constants;
+use crate::error::{Error, ServiceError};
+use crate::models::employee::Employee;
+use crate::response::Page;
+use crate::schema::{lockout_events, login_attempts};
+use crate::utils::TokenClaims;
+use diesel::prelude::*;
+
+// Kinds of lockout events
+pub const ACCOUNT_LOCKED: &str = "account_locked";
+pub const ACCOUNT_UNLOCKED: &str = "account_unlocked";
+pub const IP_BLOCKED: &str = "ip_blocked";
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = login_attempts)]
+struct NewLoginAttempt<'a> {
+    email: &'a str,
+    ip: Option<&'a str>,
+    success: bool
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable)]
+#[diesel(table_name = lockout_events)]
+pub struct LockoutEvent {
+    pub id: i32,
+    pub kind: String,
+    pub email: Option<String>,
+    pub employee_id: Option<i32>,
+    pub ip: Option<String>,
+    pub actor_id: Option<i32>,
+    pub created_at: NaiveDateTime
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = lockout_events)]
+struct NewLockoutEvent<'a> {
+    kind: &'a str,
+    email: Option<&'a str>,
+    employee_id: Option<i32>,
+    ip: Option<&'a str>,
+    actor_id: Option<i32>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LockoutEventFilter {
+    pub email: Option<String>,
+    pub kind: Option<String>,
+    pub page_num: Option<i64>,
+    pub page_size: Option<i64>
+}
+
+/*
+    Failed attempts are counted since the latest of: the start of the window,
+    the last successful login and the last unlock by a manager.
+    Emails that belong to nobody are tracked the same way, so a lockout doesn't reveal which accounts exist
+ */
+pub struct LoginAttempt;
+
+impl LoginAttempt {
+    fn account_failures(_email: &str, policy: &LoginPolicy, now: NaiveDateTime, conn: &mut PgConnection) -> Result<i64, Error> {
+        use crate::schema::login_attempts::dsl::*;
+        let last_success = login_attempts
+            .filter(email.eq(_email))
+            .filter(success.eq(true))
+            .select(diesel::dsl::max(created_at))
+            .first::<Option<NaiveDateTime>>(conn)?;
+        let last_unlock = lockout_events::table
+            .filter(lockout_events::email.eq(_email))
+            .filter(lockout_events::kind.eq(ACCOUNT_UNLOCKED))
+            .select(diesel::dsl::max(lockout_events::created_at))
+            .first::<Option<NaiveDateTime>>(conn)?;
+        let since = [last_success, last_unlock].into_iter().flatten()
+            .fold(now - Duration::minutes(policy.lockout_minutes), NaiveDateTime::max);
+
+        Ok(login_attempts
+            .filter(email.eq(_email))
+            .filter(success.eq(false))
+            .filter(created_at.gt(since))
+            .count()
+            .get_result::<i64>(conn)?)
+    }
+
+    fn ip_failures(_ip: &str, policy: &LoginPolicy, now: NaiveDateTime, conn: &mut PgConnection) -> Result<i64, Error> {
+        use crate::schema::login_attempts::dsl::*;
+        Ok(login_attempts
+            .filter(ip.eq(_ip))
+            .filter(success.eq(false))
+            .filter(created_at.gt(now - Duration::minutes(policy.ip_window_minutes)))
+            .count()
+            .get_result::<i64>(conn)?)
+    }
+
+    // How long to hold the answer to a login attempt on this account
+    pub fn delay(_email: &str, policy: &LoginPolicy, conn: &mut PgConnection) -> Result<std::time::Duration, Error> {
+        let failures = Self::account_failures(&_email.to_lowercase(), policy, Utc::now().naive_utc(), conn)?;
+        Ok(policy.delay(failures))
+    }
+
+    // Refuses the attempt while the account or the client IP is locked out
+    pub fn check_allowed(_email: &str, _ip: Option<&str>, policy: &LoginPolicy, conn: &mut PgConnection) -> Result<(), Error> {
+        let now = Utc::now().naive_utc();
+        let ip_blocked = match _ip {
+            Some(_ip) => Self::ip_failures(_ip, policy, now, conn)? >= policy.ip_max_failures,
+            None => false
+        };
+        if ip_blocked || Self::account_failures(_email, policy, now, conn)? >= policy.max_failures {
+            return Err(Box::new(ServiceError::TooManyRequests { error_message: constants::MESSAGE_ACCOUNT_LOCKED.to_string() }))
+        }
+        Ok(())
+    }
+
+    pub fn record_success(_email: &str, _ip: Option<&str>, conn: &mut PgConnection) -> Result<(), Error> {
+        diesel::insert_into(login_attempts::table)
+            .values(NewLoginAttempt { email: _email, ip: _ip, success: true })
+            .execute(conn)?;
+        Ok(())
+    }
+
+    // Records a failure and the lockout it triggers, if any
+    pub fn record_failure(_email: &str, _employee_id: Option<i32>, _ip: Option<&str>, policy: &LoginPolicy, conn: &mut PgConnection) -> Result<(), Error> {
+        diesel::insert_into(login_attempts::table)
+            .values(NewLoginAttempt { email: _email, ip: _ip, success: false })
+            .execute(conn)?;
+        let now = Utc::now().naive_utc();
+        if Self::account_failures(_email, policy, now, conn)? == policy.max_failures {
+            Self::record_event(ACCOUNT_LOCKED, Some(_email), _employee_id, _ip, None, conn)?;
+        }
+        if let Some(_ip) = _ip {
+            if Self::ip_failures(_ip, policy, now, conn)? == policy.ip_max_failures {
+                Self::record_event(IP_BLOCKED, None, None, Some(_ip), None, conn)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn record_event(_kind: &str, _email: Option<&str>, _employee_id: Option<i32>, _ip: Option<&str>, _actor_id: Option<i32>,
+                    conn: &mut PgConnection) -> Result<(), Error> {
+        diesel::insert_into(lockout_events::table)
+            .values(NewLockoutEvent { kind: _kind, email: _email, employee_id: _employee_id, ip: _ip, actor_id: _actor_id })
+            .execute(conn)?;
+        Ok(())
+    }
+
+    // A manager lifts the lockout of an account, earlier failures stop counting
+    pub fn unlock(_employee_id: i32, claims: &TokenClaims, conn: &mut PgConnection) -> Result<String, Error> {
+        let _actor_id = claims.sub;
+        let employee = Employee::find_by_id(_employee_id, claims.org, conn)?;
+        employee.check_manageable(claims)?;
+        let _email = employee.email.to_lowercase();
+        Self::record_event(ACCOUNT_UNLOCKED, Some(&_email), Some(employee.id), None, Some(_actor_id), conn)?;
+        Ok(format!("Account of {} unlocked", employee.email))
+    }
+
+    // Events of the accounts of an organization, blocked IPs belong to no organization and are left out
+    pub fn find_events(filter: LockoutEventFilter, _organization_id: i32, conn: &mut PgConnection) -> Result<Page<LockoutEvent>, Error> {
+        use crate::schema::employees;
+        let page_num = filter.page_num.unwrap_or(constants::DEFAULT_PAGE_NUM).max(1);
+        let page_size = filter.page_size.unwrap_or(constants::DEFAULT_PER_PAGE).clamp(1, 100);
+        let build_query = || {
+            let mut query = lockout_events::table
+                .filter(lockout_events::employee_id.eq_any(
+                    employees::table.filter(employees::organization_id.eq(_organization_id)).select(employees::id.nullable())
+                ))
+                .into_boxed();
+            if let Some(_email) = &filter.email {
+                query = query.filter(lockout_events::email.eq(_email.to_lowercase()));
+            }
+            if let Some(_kind) = &filter.kind {
+                query = query.filter(lockout_events::kind.eq(_kind.clone()));
+            }
+            query
+        };
+
+        let total_elements = build_query().count().get_result::<i64>(conn)?;
+        let data = build_query()
+            .order_by(lockout_events::created_at.desc())
+            .limit(page_size)
+            .offset((page_num - 1) * page_size)
+            .load::<LockoutEvent>(conn)?;
+        Ok(Page::new(constants::MESSAGE_OK, data, page_num, page_size, total_elements))
+    }
+}
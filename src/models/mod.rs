@@ -1,4 +1,16 @@
+pub mod approval_policies;
+pub mod calendar_feeds;
 pub mod employee;
+pub mod holidays;
+pub mod leave_balances;
+pub mod leave_requests;
+pub mod login_attempts;
+pub mod organizations;
+pub mod password_reset_tokens;
 pub mod schedule;
+pub mod sessions;
 pub mod shifts;
-pub mod shift_changes;
\ No newline at end of file
+pub mod shift_breaks;
+pub mod shift_changes;
+pub mod skills;
+pub mod teams;
\ No newline at end of file
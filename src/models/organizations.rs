@@ -0,0 +1,156 @@
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{AsChangeset, Identifiable, PgConnection, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use crate::config::password::PasswordPolicy;
+use crate::config::signup::SignupPolicy;
+use crate::constants;
+use crate::error::{Error, ServiceError};
+use crate::models::employee::{Employee, EmployeeDTO};
+use crate::models::shifts::Shift;
+use crate::models::teams::SHIFT_SLOTS;
+use crate::permissions::Role;
+use crate::schema::{organization_signups, organizations, shifts};
+use crate::utils::parse_time_zone;
+use diesel::prelude::*;
+
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable)]
+#[diesel(table_name = organizations)]
+pub struct Organization {
+    pub id: i32,
+    pub name: String,
+    pub created_at: NaiveDateTime,
+    // zone of the shift times of teams without their own
+    pub time_zone: String,
+    // whoever works a shift can also be on call that day
+    pub on_call_with_shift: bool,
+    pub on_call_max_per_month: Option<i32>,
+    // share of the hours on call that is paid
+    pub on_call_pay_factor: f64
+}
+
+// Sign-up of a new organization together with its first administrator
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewOrganizationDTO {
+    pub name: String,
+    pub admin_name: String,
+    pub admin_email: String,
+    pub admin_password: String,
+    // UTC when not given
+    #[serde(default)]
+    pub time_zone: Option<String>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OrganizationTimeZoneDTO {
+    pub time_zone: String
+}
+
+// How on-call duties are given out and paid, see models::shifts::SHIFT_ON_CALL
+#[derive(Debug, Serialize, Deserialize, AsChangeset)]
+#[diesel(table_name = organizations, treat_none_as_null = true)]
+pub struct OnCallRulesDTO {
+    pub on_call_with_shift: bool,
+    pub on_call_max_per_month: Option<i32>,
+    pub on_call_pay_factor: f64
+}
+
+impl Organization {
+    /*
+        Creates the organization, a copy of the default shifts (S, C, D, H) so it can generate schedules
+        right away, and an Admin account to manage it
+     */
+    pub fn new(organization_dto: NewOrganizationDTO, client_ip: Option<&str>, conn: &mut PgConnection) -> Result<Organization, Error> {
+        Self::check_signup_allowed(client_ip, &SignupPolicy::from_env(), conn)?;
+        if organization_dto.name.trim().is_empty() {
+            return Err("Organization name is required".into())
+        }
+        if organizations::table.filter(organizations::name.eq(&organization_dto.name)).count().get_result::<i64>(conn)? > 0 {
+            return Err(format!("Organization '{}' already exists", organization_dto.name).into())
+        }
+        PasswordPolicy::from_env().validate(&organization_dto.admin_password)?;
+        let zone = organization_dto.time_zone.clone().unwrap_or_else(|| "UTC".to_string());
+        parse_time_zone(&zone)?;
+
+        let default_shifts = shifts::table
+            .filter(shifts::organization_id.eq(constants::DEFAULT_ORGANIZATION))
+            .filter(shifts::name.eq_any(SHIFT_SLOTS))
+            .filter(shifts::archived_at.is_null())
+            .order_by(shifts::id)
+            .load::<Shift>(conn)?;
+        conn.transaction::<Organization, Error, _>(|conn| {
+            let organization = diesel::insert_into(organizations::table)
+                .values((organizations::name.eq(&organization_dto.name), organizations::time_zone.eq(&zone)))
+                .get_result::<Organization>(conn)?;
+            let rows: Vec<_> = default_shifts.iter()
+                .map(|shift| (
+                    shifts::name.eq(shift.name.clone()),
+                    shifts::start_time.eq(shift.start_time),
+                    shifts::end_time.eq(shift.end_time),
+                    shifts::minium_attendences.eq(shift.minium_attendences),
+                    shifts::category.eq(shift.category.clone()),
+                    shifts::organization_id.eq(organization.id),
+                ))
+                .collect();
+            if !rows.is_empty() {
+                diesel::insert_into(shifts::table).values(&rows).execute(conn)?;
+            }
+            Employee::register(EmployeeDTO {
+                name: organization_dto.admin_name.clone(),
+                email: organization_dto.admin_email.clone(),
+                password: organization_dto.admin_password.clone(),
+                phone_number: None,
+                department: None,
+                role: Role::Admin.name().to_string(),
+                availability: None,
+                time_zone: None,
+            }, organization.id, conn)?;
+            Ok(organization)
+        })
+    }
+
+    // Sign-up is off unless configured, and every attempt counts towards the limit of the client IP
+    fn check_signup_allowed(client_ip: Option<&str>, policy: &SignupPolicy, conn: &mut PgConnection) -> Result<(), Error> {
+        if !policy.enabled {
+            return Err(Box::new(ServiceError::forbidden("Organization sign-up is disabled")))
+        }
+        let client_ip = client_ip.ok_or("Unknown client address")?;
+        let since = Utc::now().naive_utc() - Duration::minutes(policy.ip_window_minutes);
+        let attempts = organization_signups::table
+            .filter(organization_signups::ip.eq(client_ip))
+            .filter(organization_signups::created_at.gt(since))
+            .count()
+            .get_result::<i64>(conn)?;
+        if attempts >= policy.ip_max_attempts {
+            return Err(Box::new(ServiceError::TooManyRequests { error_message: constants::MESSAGE_TOO_MANY_SIGNUPS.to_string() }))
+        }
+        diesel::insert_into(organization_signups::table)
+            .values(organization_signups::ip.eq(client_ip))
+            .execute(conn)?;
+        Ok(())
+    }
+
+    // Shift times already scheduled keep their wall-clock times in the new zone
+    pub fn set_time_zone(_id: i32, time_zone_dto: OrganizationTimeZoneDTO, conn: &mut PgConnection) -> Result<Organization, Error> {
+        parse_time_zone(&time_zone_dto.time_zone)?;
+        Ok(diesel::update(organizations::table.find(_id))
+            .set(organizations::time_zone.eq(time_zone_dto.time_zone))
+            .get_result::<Organization>(conn)?)
+    }
+
+    pub fn set_on_call_rules(_id: i32, rules_dto: OnCallRulesDTO, conn: &mut PgConnection) -> Result<Organization, Error> {
+        if rules_dto.on_call_max_per_month.is_some_and(|max| max <= 0) {
+            return Err("The most on-call duties a month must be positive".into())
+        }
+        if !(rules_dto.on_call_pay_factor.is_finite() && rules_dto.on_call_pay_factor >= 0.0) {
+            return Err("On-call pay factor must not be negative".into())
+        }
+        Ok(diesel::update(organizations::table.find(_id)).set(&rules_dto).get_result::<Organization>(conn)?)
+    }
+
+    pub fn find_by_id(_id: i32, conn: &mut PgConnection) -> Result<Organization, Error> {
+        organizations::table.find(_id).first::<Organization>(conn)
+            .optional()?
+            .ok_or_else(|| "Organization not found".into())
+    }
+}
@@ -0,0 +1,155 @@
+use bcrypt::{DEFAULT_COST, hash, verify};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{Identifiable, Insertable, PgConnection, Queryable, RunQueryDsl};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::config::password::PasswordPolicy;
+use crate::constants;
+use crate::error::Error;
+use crate::models::employee::Employee;
+use crate::models::sessions::Session;
+use crate::schema::password_reset_tokens;
+use crate::utils::TokenClaims;
+use diesel::prelude::*;
+
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable)]
+#[diesel(table_name = password_reset_tokens)]
+pub struct PasswordResetToken {
+    pub id: i32,
+    pub employee_id: i32,
+    #[serde(skip_serializing)]
+    pub token_hash: String,
+    pub expires_at: NaiveDateTime,
+    pub used_at: Option<NaiveDateTime>,
+    pub issued_by: Option<i32>,
+    pub created_at: NaiveDateTime
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = password_reset_tokens)]
+struct NewPasswordResetToken {
+    employee_id: i32,
+    token_hash: String,
+    expires_at: NaiveDateTime,
+    issued_by: Option<i32>
+}
+
+// Returned once to whoever issued the token, only its hash is kept
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IssuedResetToken {
+    pub token: String,
+    pub expires_at: NaiveDateTime
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ResetPasswordDTO {
+    pub token: String,
+    pub new_password: String
+}
+
+impl PasswordResetToken {
+    /*
+        Issue a reset token for an employee, any token still outstanding for them is revoked.
+        The token is "<id>.<secret>" so it can be looked up without storing the secret,
+        accounts holding AssignRoles can only be reset by someone holding it too
+     */
+    pub fn issue(_employee_id: i32, claims: &TokenClaims, conn: &mut PgConnection) -> Result<IssuedResetToken, Error> {
+        use crate::schema::password_reset_tokens::dsl::*;
+        Self::check_issuer(&Employee::find_by_id(_employee_id, claims.org, conn)?, claims)?;
+        let policy = PasswordPolicy::from_env();
+        let secret: String = rand::thread_rng().sample_iter(&Alphanumeric).take(32).collect();
+        let now = Utc::now().naive_utc();
+
+        let issued = conn.transaction::<PasswordResetToken, Error, _>(|conn| {
+            diesel::update(password_reset_tokens.filter(employee_id.eq(_employee_id)).filter(used_at.is_null()))
+                .set(used_at.eq(now))
+                .execute(conn)?;
+            Ok(diesel::insert_into(password_reset_tokens)
+                .values(NewPasswordResetToken {
+                    employee_id: _employee_id,
+                    token_hash: hash(&secret, DEFAULT_COST)?,
+                    expires_at: now + Duration::minutes(policy.reset_ttl_minutes),
+                    issued_by: Some(claims.sub),
+                })
+                .get_result::<PasswordResetToken>(conn)?)
+        })?;
+
+        Ok(IssuedResetToken {
+            token: format!("{}.{}", issued.id, secret),
+            expires_at: issued.expires_at,
+        })
+    }
+
+    fn check_issuer(employee: &Employee, claims: &TokenClaims) -> Result<(), Error> {
+        employee.check_manageable(claims)
+    }
+
+    // Set a new password with a valid token, the token can't be used again
+    pub fn redeem(reset_dto: ResetPasswordDTO, conn: &mut PgConnection) -> Result<String, Error> {
+        use crate::schema::password_reset_tokens::dsl::*;
+        let (token_id, secret) = match reset_dto.token.split_once('.') {
+            Some((token_id, secret)) => (token_id.parse::<i32>().map_err(|_| constants::MESSAGE_INVALID_RESET_TOKEN)?, secret),
+            None => return Err(constants::MESSAGE_INVALID_RESET_TOKEN.into())
+        };
+        let now = Utc::now().naive_utc();
+        let token = password_reset_tokens.find(token_id).first::<PasswordResetToken>(conn)
+            .optional()?
+            .filter(|token| token.used_at.is_none() && token.expires_at > now)
+            .filter(|token| verify(secret, &token.token_hash).unwrap_or(false))
+            .ok_or(constants::MESSAGE_INVALID_RESET_TOKEN)?;
+        PasswordPolicy::from_env().validate(&reset_dto.new_password)?;
+
+        conn.transaction::<(), Error, _>(|conn| {
+            // the update only hits an unused token, so two concurrent redeems can't both succeed
+            let updated = diesel::update(password_reset_tokens.find(token.id).filter(used_at.is_null()))
+                .set(used_at.eq(now))
+                .execute(conn)?;
+            if updated == 0 {
+                return Err(constants::MESSAGE_INVALID_RESET_TOKEN.into())
+            }
+            Employee::set_password(token.employee_id, &reset_dto.new_password, conn)?;
+            Session::revoke_all(token.employee_id, None, conn)?;
+            Ok(())
+        })?;
+        Ok(constants::MESSAGE_PASSWORD_CHANGED.to_string())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::models::employee::Employee;
+    use crate::models::password_reset_tokens::PasswordResetToken;
+    use crate::utils::TokenClaims;
+
+    fn employee(id: i32, role: &str) -> Employee {
+        Employee {
+            id,
+            name: role.to_string(),
+            email: format!("{}@vsec.com.vn", role.to_lowercase()),
+            password: String::new(),
+            phone_number: None,
+            department: None,
+            role: role.to_string(),
+            availability: None,
+            active: true,
+            deactivated_at: None,
+            anonymized_at: None,
+            organization_id: 1,
+            time_zone: None,
+        }
+    }
+
+    #[test]
+    fn test_manager_cannot_reset_admin_password() {
+        let manager = TokenClaims { sub: 2, role: "Manager".to_string(), org: 1, sid: 1, iat: 0, exp: 0 };
+        assert!(PasswordResetToken::check_issuer(&employee(1, "Admin"), &manager).is_err());
+        assert!(PasswordResetToken::check_issuer(&employee(3, "Employee"), &manager).is_ok());
+        assert!(PasswordResetToken::check_issuer(&employee(4, "Manager"), &manager).is_ok());
+
+        let admin = TokenClaims { sub: 1, role: "Admin".to_string(), org: 1, sid: 1, iat: 0, exp: 0 };
+        assert!(PasswordResetToken::check_issuer(&employee(5, "Admin"), &admin).is_ok());
+    }
+}
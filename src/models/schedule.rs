@@ -1,14 +1,22 @@
 use crate::utils::verify_valid_schedule;
 use std::collections::HashMap;
-use std::fs::OpenOptions;
-use chrono::{Datelike, NaiveDate};
-use diesel::{ExpressionMethods, Insertable, PgConnection, Queryable, QueryDsl, QueryResult, RunQueryDsl};
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, Utc};
+use chrono_tz::Tz;
+use diesel::{BoolExpressionMethods, ExpressionMethods, Insertable, PgConnection, Queryable, QueryDsl, QueryResult, RunQueryDsl};
 use serde::{Deserialize, Serialize};
 use crate::constants;
+use crate::models::calendar_feeds::{EventTime, FEED_DAYS_AHEAD, FEED_DAYS_BEHIND, FeedEvent, write_ical};
 use crate::models::employee::Employee;
-use crate::models::shifts::Shift;
+use crate::models::holidays::Holiday;
+use crate::models::leave_requests::{LEAVE_TYPES, LeaveRequest};
+use crate::models::shift_breaks::ShiftBreak;
+use crate::models::organizations::Organization;
+use crate::models::shifts::{SHIFT_ON_CALL, Shift, ShiftFilter, ShiftWindow};
+use crate::models::skills::{Skill, SkillRules, SkillViolation};
+use crate::models::teams::{ON_CALL_SLOT, SHIFT_SLOTS, ShiftCatalogue, Team};
+use crate::permissions::{has_permission, Permission};
 use crate::schema::schedules;
-use crate::utils::create_sample_schedule;
+use crate::utils::{create_sample_schedule, last_day_of_month, staffing_of_day, TokenClaims};
 use crate::error::Error;
 
 
@@ -21,7 +29,9 @@ pub struct Schedule {
     pub employee_id: i32,
     pub data: NaiveDate,
     pub shift_id : i32,
-    pub note: Option<String>
+    pub note: Option<String>,
+    pub team_id: Option<i32>,
+    pub organization_id: i32
 }
 
 #[derive(Serialize, Deserialize, Debug, Insertable)]
@@ -30,7 +40,8 @@ pub struct ScheduleDTO {
     pub employee_id: i32,
     pub data: NaiveDate,
     pub shift_id: i32,
-    pub note: Option<String>
+    pub note: Option<String>,
+    pub team_id: Option<i32>
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -42,7 +53,12 @@ pub struct ShiftDetail{
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ShiftDetailName{
     pub key: String,
-    pub value: Vec<String>
+    pub value: Vec<String>,
+    // times of the shift in the viewer's time zone, none for leave
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub starts_at: Option<DateTime<FixedOffset>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ends_at: Option<DateTime<FixedOffset>>
 }
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DayDetail {
@@ -56,25 +72,99 @@ pub struct DayDetailName {
     pub value :Vec<ShiftDetailName>
 }
 
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EmployeeHours {
+    pub employee_id: i32,
+    pub name: String,
+    pub shifts: i64,
+    pub gross_hours: f64,
+    // without unpaid breaks
+    pub net_hours: f64,
+    pub holiday_shifts: i64,
+    pub on_call_duties: i64,
+    // on call, not worked
+    pub on_call_hours: f64,
+    // net hours, those on holidays weighted by their pay multiplier, and the paid share of the hours on call
+    pub paid_hours: f64
+}
+
+// Minutes of the shifts of an employee, see Schedule::worked_minutes
+#[derive(Debug, Default)]
+struct WorkedMinutes {
+    shifts: i64,
+    gross: i64,
+    net: i64,
+    holiday_shifts: i64,
+    on_call_duties: i64,
+    on_call: i64,
+    paid: f64
+}
+
+// A month of schedules as CSV, built in memory and sent as an attachment
+#[derive(Debug)]
+pub struct CsvExport {
+    pub file_name: String,
+    pub content: Vec<u8>
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 pub struct AutoScheduleDTO {
+    // with a team and no employees, every member of the team is rostered
+    #[serde(default)]
     pub employees: Vec<i32>,
     pub month: i32,
     pub year: i32,
-    pub nums_h: i32
+    pub nums_h: i32,
+    #[serde(default)]
+    pub team_id: Option<i32>,
+    // cap on the net hours (without unpaid breaks) of each employee over the month
+    #[serde(default)]
+    pub max_hours: Option<i64>,
+    // on-call shift to roster every day on top of the work shifts, see OnCallPlan
+    #[serde(default)]
+    pub on_call_shift_id: Option<i32>,
+    // employees on call each day, 1 when not given
+    #[serde(default)]
+    pub nums_on_call: Option<i32>
+}
+
+// On-call duties of each day and the rules of the organization for them, see utils::create_sample_schedule
+#[derive(Debug, Clone)]
+pub struct OnCallPlan {
+    pub per_day: i32,
+    pub with_shift: bool,
+    pub max_per_month: Option<i32>
 }
 
 #[allow(dead_code)]
 impl Schedule {
-    pub fn new(schedule_dto: ScheduleDTO, conn: &mut PgConnection) -> Result<String, Error>{
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(schedule_dto: ScheduleDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<String, Error>{
         use crate::schema::schedules::dsl::*;
-        return if Employee::find_by_id(schedule_dto.employee_id, conn).is_err() && Shift::find_by_id(&schedule_dto.shift_id, conn).is_err() {
+        if let Ok(employee) = Employee::find_by_id(schedule_dto.employee_id, _organization_id, conn) {
+            if !employee.active {
+                return Err("Employee is deactivated".into())
+            }
+        }
+        if let Ok(shift) = Shift::find_by_id(&schedule_dto.shift_id, _organization_id, conn) {
+            if shift.archived_at.is_some() {
+                return Err("Shift is archived".into())
+            }
+        }
+        if let Some(_team_id) = schedule_dto.team_id {
+            Team::find_by_id(_team_id, _organization_id, conn)?;
+            if !Team::member_ids(_team_id, conn)?.contains(&schedule_dto.employee_id) {
+                return Err("Employee is not a member of this team".into())
+            }
+        }
+        // both have to belong to the caller's organization
+        return if Employee::find_by_id(schedule_dto.employee_id, _organization_id, conn).is_err() || Shift::find_by_id(&schedule_dto.shift_id, _organization_id, conn).is_err() {
             Err(
                 "Invalid employee id or shift id".into()
             )
         } else {
             let new_schedule = schedule_dto;
-            let rs = diesel::insert_into(schedules).values(new_schedule).execute(conn);
+            let rs = diesel::insert_into(schedules).values((new_schedule, organization_id.eq(_organization_id))).execute(conn);
             if rs.is_err() {
                 return Err(
                     constants::DATABASE_INSERT_ERROR.to_string().into()
@@ -84,36 +174,130 @@ impl Schedule {
         }
     }
 
-    pub fn find_by_id(_id: i32, conn: &mut PgConnection) -> QueryResult<Schedule> {
+    pub fn find_by_id(_id: i32, _organization_id: i32, conn: &mut PgConnection) -> QueryResult<Schedule> {
         use crate::schema::schedules::dsl::*;
-        schedules.find(_id).get_result::<Schedule>(conn)
+        schedules.find(_id).filter(organization_id.eq(_organization_id)).get_result::<Schedule>(conn)
     }
 
-    /* Suppose there already have 4 shift:
-        - S: Morning shift from 6.00 to 14.00 //id = 4
-        - C: Afternoon shift from 2.00 to 22.00 //id = 3
-        - D: Night shift from 22.00 to 6.00 next day *** //id = 2
-        - H: Office hours from 8.00 to 18.00 //id = 1
+    /*
+        Shifts come from the catalogue of the team (see models::teams::SHIFT_SLOTS).
+        With a team, only its members are rostered and days they already work for another team are skipped
      */
-    pub fn from_sample_to_db(auto_schedule_dto: AutoScheduleDTO, conn: &mut PgConnection) -> Result<Vec<DayDetailName>, Error> {
+    #[allow(clippy::needless_return)]
+    pub fn from_sample_to_db(mut auto_schedule_dto: AutoScheduleDTO, claims: &TokenClaims, conn: &mut PgConnection) -> Result<Vec<DayDetailName>, Error> {
         use crate::schema::schedules::dsl::*;
         use crate::schema::employees::dsl::*;
+        let _team_id = auto_schedule_dto.team_id;
+        let org = claims.org;
+        if let Some(_team_id) = _team_id {
+            Team::find_by_id(_team_id, org, conn)?.check_manages(claims)?;
+            let members = Team::member_ids(_team_id, conn)?;
+            if auto_schedule_dto.employees.is_empty() {
+                auto_schedule_dto.employees = members;
+            } else if auto_schedule_dto.employees.iter().any(|e| !members.contains(e)) {
+                return Err("Only members of the team can be rostered".into())
+            }
+        }
+        let catalogue = Team::shift_catalogue(_team_id, org, conn)?;
+        // a slot loses its shift when that shift is archived or deleted
+        if let Some(slot) = SHIFT_SLOTS.iter().find(|slot| catalogue.shift_id(slot).is_none()) {
+            return Err(format!("No shift for slot {}, create one or set it in the team's shifts", slot).into())
+        }
+        let mut windows = Self::slot_windows(&catalogue, org, conn)?;
+        let zone = Team::time_zone(_team_id, org, conn)?;
+        let viewer_zone = Employee::viewer_time_zone(claims.sub, org, zone, conn)?;
+        // deactivated employees are never rostered
+        let active_employees = employees
+            .filter(crate::schema::employees::id.eq_any(&auto_schedule_dto.employees))
+            .filter(crate::schema::employees::organization_id.eq(org))
+            .filter(active.eq(true))
+            .select(crate::schema::employees::id)
+            .load::<i32>(conn)?;
+        auto_schedule_dto.employees.retain(|e| active_employees.contains(e));
+        if auto_schedule_dto.employees.is_empty() {
+            return Err("No active employees to roster".into())
+        }
         let mut sample_schedule: Vec<DayDetail> ;
         let mut return_sample_schedule : Vec<DayDetailName> = vec![];
         let month = auto_schedule_dto.month;
         let year = auto_schedule_dto.year;
 
         let start_date = NaiveDate::from_ymd_opt(year, month as u32, 1).unwrap();
-        let schedules_in_month = schedules.filter(data.between(start_date, NaiveDate::from_ymd_opt(year, month as u32, 20).unwrap())).order_by(data).get_results::<Schedule>(conn)?;
+        let mut query = schedules
+            .filter(crate::schema::schedules::organization_id.eq(org))
+            .filter(data.between(start_date, NaiveDate::from_ymd_opt(year, month as u32, 20).unwrap()))
+            .into_boxed();
+        query = match _team_id {
+            Some(_team_id) => query.filter(team_id.eq(_team_id)),
+            None => query.filter(team_id.is_null())
+        };
+        let schedules_in_month = query.order_by(data).get_results::<Schedule>(conn)?;
         if schedules_in_month.len() >= 20*&auto_schedule_dto.employees.len() {
             return Err("Already generated".into())
         }
+        let end_date = last_day_of_month(month, year).ok_or("Invalid Month")?;
+        let leaves = LeaveRequest::find_approved_between(start_date, end_date, org, conn)?;
+        let mut on_leave = LeaveRequest::codes_by_day(&leaves, month, year);
+        if let Some(_team_id) = _team_id {
+            let elsewhere = schedules
+                .filter(crate::schema::schedules::organization_id.eq(org))
+                .filter(data.between(start_date, end_date))
+                .filter(crate::schema::schedules::employee_id.eq_any(&auto_schedule_dto.employees))
+                .filter(team_id.is_null().or(team_id.ne(_team_id)))
+                .get_results::<Schedule>(conn)?;
+            for schedule in elsewhere {
+                on_leave.entry((schedule.data.day() as i32, schedule.employee_id)).or_insert("busy");
+            }
+        }
+        let region = Team::region(_team_id, org, conn)?;
+        let holidays_in_month = Holiday::find_between(start_date, end_date, org, conn)?;
+        let holidays = Holiday::by_day(&holidays_in_month, region.as_deref());
+        let skills = SkillRules::load(&catalogue, &auto_schedule_dto.employees, month, year, conn)?;
+        let on_call = match auto_schedule_dto.on_call_shift_id {
+            Some(_shift_id) => {
+                let shift = Shift::find_active(&_shift_id, org, conn)?;
+                if shift.category != SHIFT_ON_CALL {
+                    return Err(format!("Shift '{}' is not an on-call shift", shift.name).into())
+                }
+                let per_day = auto_schedule_dto.nums_on_call.unwrap_or(1);
+                if per_day < 1 {
+                    return Err("At least one employee is on call each day".into())
+                }
+                let organization = Organization::find_by_id(org, conn)?;
+                windows.insert(ON_CALL_SLOT.to_string(), shift.window());
+                Some(OnCallPlan {
+                    per_day,
+                    with_shift: organization.on_call_with_shift,
+                    max_per_month: organization.on_call_max_per_month,
+                })
+            }
+            None => None
+        };
         //check if schedule is valid
+        let mut attempts = 0;
         loop {
-            sample_schedule = create_sample_schedule(&auto_schedule_dto).unwrap();
-            if verify_valid_schedule(&sample_schedule) {
+            sample_schedule = create_sample_schedule(&auto_schedule_dto, &on_leave, &windows, zone, &holidays, &skills, on_call.as_ref())?;
+            if verify_valid_schedule(&sample_schedule, &auto_schedule_dto, &windows, zone, &holidays, &skills, on_call.as_ref()) {
                 break;
             }
+            attempts += 1;
+            if attempts >= constants::MAX_GENERATE_ATTEMPTS {
+                // the skills the last attempt lacked, if that is why it failed
+                let names: HashMap<i32, String> = Skill::find_all(org, conn)?.into_iter().map(|skill| (skill.id, skill.name)).collect();
+                let missing: Vec<String> = sample_schedule.iter()
+                    .flat_map(|day| day.value.iter()
+                        .filter(|shift| SHIFT_SLOTS.iter().position(|slot| *slot == shift.key)
+                            .is_some_and(|slot| staffing_of_day(&auto_schedule_dto, holidays.get(&day.day))[slot] > 0))
+                        .flat_map(|shift| skills.shortfalls(day.day, &shift.key, &shift.value)
+                        .into_iter()
+                        .map(|(skill, required, assigned)| format!("day {} {}: {} {} of {}", day.day, shift.key,
+                            names.get(&skill).map_or("?", |skill_name| skill_name.as_str()), assigned, required))))
+                    .collect();
+                if !missing.is_empty() {
+                    return Err(format!("Could not generate a valid schedule, not enough employees with the required skills ({})", missing.join(", ")).into())
+                }
+                return Err("Could not generate a valid schedule, check the employees available this month".into())
+            }
         }
 
         // insert to datebase
@@ -123,12 +307,9 @@ impl Schedule {
             for shift in &day.value {
                 let key_ = shift.key.clone();
 
-                let id_shift = match shift.key.as_str() {
-                    "S" => 4,
-                    "D" => 2,
-                    "C" => 3,
-                    "H" => 1,
-                    _ => return Err(constants::DATABASE_INSERT_ERROR.to_string().into())
+                let id_shift = match if key_ == ON_CALL_SLOT { auto_schedule_dto.on_call_shift_id } else { catalogue.shift_id(&shift.key) } {
+                    Some(id_shift) => id_shift,
+                    None => return Err(constants::DATABASE_INSERT_ERROR.to_string().into())
                 };
                 let mut names : Vec<String> = Vec::new();
                 for uid in &shift.value {
@@ -137,14 +318,18 @@ impl Schedule {
                         data: _date,
                         shift_id: id_shift,
                         note: None,
+                        team_id: _team_id,
                     };
-                    diesel::insert_into(schedules).values(schedule).execute(conn).expect("TODO: panic message");
+                    diesel::insert_into(schedules).values((schedule, crate::schema::schedules::organization_id.eq(org))).execute(conn).expect("TODO: panic message");
                     let emp = employees.find(uid).get_result::<Employee>(conn).expect("Error get employee");
                     names.push(emp.name);
                 }
+                let times = shown_times(&windows, &key_, _date, zone, viewer_zone);
                 let _detail = ShiftDetailName {
                     key: key_,
                     value: names,
+                    starts_at: times.map(|(start, _)| start),
+                    ends_at: times.map(|(_, end)| end),
                 };
                 vec_detail.push(_detail);
             }
@@ -157,134 +342,230 @@ impl Schedule {
         return Ok(return_sample_schedule);
     }
 
-    pub fn export_csv(month: i32, year: i32,  conn: &mut PgConnection) -> Result<String, Error> {
-        use crate::schema::schedules::dsl::*;
+    /*
+        Employees whose schedules the caller may see in the roster and its export, None for all of them:
+            - ViewAllDepartments: everyone
+            - managers (ViewTeam): a team they manage, without a team the members of all of them and themselves
+            - others: a team they are a member of, without a team only themselves
+     */
+    fn roster_scope(_team_id: Option<i32>, claims: &TokenClaims, conn: &mut PgConnection) -> Result<Option<Vec<i32>>, Error> {
+        let team = match _team_id {
+            Some(_team_id) => Some(Team::find_by_id(_team_id, claims.org, conn)?),
+            None => None
+        };
+        if claims.can(Permission::ViewAllDepartments) {
+            return Ok(None)
+        }
+        match team {
+            Some(team) if claims.can(Permission::ViewTeam) => {
+                team.check_manages(claims)?;
+                Ok(None)
+            },
+            Some(team) => match Team::member_ids(team.id, conn)?.contains(&claims.sub) {
+                true => Ok(None),
+                false => Err(format!("You aren't a member of team '{}'", team.name).into())
+            },
+            None if claims.can(Permission::ViewTeam) => {
+                let mut visible = Team::managed_member_ids(claims.sub, claims.org, conn)?;
+                visible.push(claims.sub);
+                Ok(Some(visible))
+            },
+            None => Ok(Some(vec![claims.sub]))
+        }
+    }
+
+    /*
+        With a team, only its schedules and its members (and whoever worked for it that month) are exported.
+        Shift cells carry the times of the shift in the caller's time zone, e.g. "D 23:00-07:00".
+        Holidays of the team's region (of the whole organization without a team) are named in the header.
+        Rows are limited to what the caller may see, see `roster_scope`
+     */
+    pub fn export_csv(month: i32, year: i32, _team_id: Option<i32>, claims: &TokenClaims, conn: &mut PgConnection) -> Result<CsvExport, Error> {
         use crate::schema::employees::dsl::*;
-        let start_date = NaiveDate::from_ymd_opt(year, month as u32, 1).unwrap();
-        let day  = match month {
-            1|3|5|7|8|10|12 => 31,
-            4|6|9|11 => 30,
-            2 => 28,
-            _ => return Err("Invalid Month".into())
+        let _organization_id = claims.org;
+        let end_date = last_day_of_month(month, year).ok_or("Invalid Month")?;
+        let start_date = end_date.with_day(1).unwrap();
+        let day = end_date.day();
+        let visible = Self::roster_scope(_team_id, claims, conn)?;
+        let schedules_in_month = Self::find_between(start_date, end_date, _team_id, _organization_id, conn)?;
+        let members = match _team_id {
+            Some(_team_id) => Some(Team::member_ids(_team_id, conn)?),
+            None => None
         };
-        let schedules_in_month = schedules.filter(data.between(start_date, NaiveDate::from_ymd_opt(year, month as u32, day).unwrap())).order_by(data).get_results::<Schedule>(conn)?;
-        let nums_employees = employees.load::<Employee>(conn)?;
-        let file_name = format!("schedule_{}_{}.csv", month, year);
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(file_name.clone())?;
+        let catalogue = Team::shift_catalogue(_team_id, _organization_id, conn)?;
+        // deactivated employees only keep a row for the months they still worked
+        let nums_employees: Vec<Employee> = employees.filter(organization_id.eq(_organization_id)).load::<Employee>(conn)?
+            .into_iter()
+            .filter(|emp| {
+                let worked = schedules_in_month.iter().any(|s| s.employee_id == emp.id);
+                let listed = members.as_ref().is_none_or(|members| members.contains(&emp.id));
+                (worked || (emp.active && listed)) && visible.as_ref().is_none_or(|visible| visible.contains(&emp.id))
+            })
+            .collect();
+        let leaves = LeaveRequest::find_approved_between(start_date, end_date, _organization_id, conn)?;
+        let on_leave = LeaveRequest::codes_by_day(&leaves, month, year);
+        let windows = Self::shift_windows(_organization_id, conn)?;
+        let viewer_zone = Employee::viewer_time_zone(claims.sub, _organization_id, Team::time_zone(_team_id, _organization_id, conn)?, conn)?;
+        let zones = Self::team_zones(&schedules_in_month, _organization_id, conn)?;
+        let regions = Self::team_regions(&schedules_in_month, _organization_id, conn)?;
+        let holidays = Holiday::find_between(start_date, end_date, _organization_id, conn)?;
+        let on_call_pay = Self::on_call_pay(_organization_id, conn)?;
+        let hours = Self::worked_minutes(&schedules_in_month, &windows, &zones, &holidays, &regions, &on_call_pay);
+        let region = Team::region(_team_id, _organization_id, conn)?;
         let mut title = vec!["Employee Name".to_string()];
         for i in 1..=day {
-            let date = format!("{}/{}/{}", i, &month, &year);
+            let mut date = format!("{}/{}/{}", i, &month, &year);
+            if let Some(holiday) = Holiday::on_date(&holidays, NaiveDate::from_ymd_opt(year, month as u32, i).unwrap(), region.as_deref()) {
+                date = format!("{} ({})", date, holiday.name);
+            }
             title.push(date);
         }
         title.push("Total S".to_string());
         title.push("Total C".to_string());
         title.push("Total D".to_string());
         title.push("Total H".to_string());
+        title.push("Total Leave".to_string());
         title.push("Total N".to_string());
         title.push("Total".to_string());
-        let mut wtr = csv::Writer::from_writer(&file);
+        title.push("Total Hours".to_string());
+        title.push("Holiday Shifts".to_string());
+        title.push("Total On Call".to_string());
+        let mut wtr = csv::Writer::from_writer(vec![]);
         wtr.write_record(&title)?;
         let mut map :HashMap<(i32, i32), String> = HashMap::new();
+        let mut shown: HashMap<(i32, i32), String> = HashMap::new();
+        // on-call duties are added to the cell of the day, after the shift worked if any
+        let mut on_call_shown: HashMap<(i32, i32), String> = HashMap::new();
         for schedule in schedules_in_month {
-            let shift_name :&str = match schedule.shift_id {
-                1 => "H",
-                2 => "D",
-                3 => "C",
-                4 => "S",
-                _ => "N"
-            };
-            map.insert((schedule.data.day() as i32, schedule.employee_id) ,shift_name.to_string());
+            let on_call = on_call_pay.contains_key(&schedule.shift_id);
+            let shift_name :&str = if on_call { ON_CALL_SLOT } else { catalogue.code(schedule.shift_id) };
+            if !on_call {
+                map.insert((schedule.data.day() as i32, schedule.employee_id) ,shift_name.to_string());
+            }
+            if let Some(window) = windows.get(&schedule.shift_id) {
+                let (start, end) = window.instants(schedule.data, zones[&schedule.team_id]);
+                let times = format!("{} {}-{}", shift_name,
+                    start.with_timezone(&viewer_zone).format("%H:%M"), end.with_timezone(&viewer_zone).format("%H:%M"));
+                match on_call {
+                    true => on_call_shown.insert((schedule.data.day() as i32, schedule.employee_id), times),
+                    false => shown.insert((schedule.data.day() as i32, schedule.employee_id), times)
+                };
+            }
         }
 
         for x in nums_employees {
             let mut insert :Vec<&str> = vec![x.name.as_str()];
             for i in 1..=day {
-                match map.get(&(i as i32, x.id)) {
-                    Some(j) => insert.push(j),
-                    None => insert.push("N")
+                match (map.get(&(i as i32, x.id)), on_leave.get(&(i as i32, x.id))) {
+                    (Some(j), _) => insert.push(j),
+                    (None, Some(code)) => insert.push(code),
+                    (None, None) => insert.push("N")
                 };
             }
-            let (mut count_s, mut count_c, mut count_d, mut count_h, mut total, mut count_n) =(0, 0, 0, 0, 0, 0);
+            let (mut count_s, mut count_c, mut count_d, mut count_h, mut total, mut count_leave, mut count_n) =(0, 0, 0, 0, 0, 0, 0);
             for x in &insert {
-                match x {
-                    &"S" => {
+                match *x {
+                    "S" => {
                         count_s += 1;
                         total += 1;
                     },
-                    &"C" => {
+                    "C" => {
                         count_c += 1;
                         total += 1;
                     },
-                    &"D" => {
+                    "D" => {
                         count_d += 1;
                         total += 1;
                     },
-                    &"H" => {
+                    "H" => {
                         count_h += 1;
                         total += 1;
                     },
+                    code if LEAVE_TYPES.iter().any(|(_, leave)| *leave == code) => {
+                        count_leave += 1;
+                    }
                     _ => {
                         count_n += 1;
                     }
                 }
             }
-            let (count_s_str, count_c_str, count_d_str, count_h_str,  total_str , count_n_str) = (
+            let (count_s_str, count_c_str, count_d_str, count_h_str,  total_str , count_leave_str, count_n_str) = (
                     count_s.to_string(),
                     count_c.to_string(),
                     count_d.to_string(),
                     count_h.to_string(),
                     total.to_string(),
+                    count_leave.to_string(),
                     count_n.to_string()
                 );
             insert.push(&*count_s_str);
             insert.push(&*count_c_str);
             insert.push(&*count_d_str);
             insert.push(&*count_h_str);
+            insert.push(&*count_leave_str);
             insert.push(&*count_n_str);
             insert.push(&*total_str);
-            wtr.write_record(insert)?;
-        }
-        wtr.flush()?;
-        let mut rdr = csv::Reader::from_reader(&file);
-        for result in rdr.records() {
-            let record = result?;
-            println!("{:?}", record);
+            // net of unpaid breaks
+            let hours_str = format!("{:.2}", hours.get(&x.id).map_or(0, |worked| worked.net) as f64 / 60.0);
+            insert.push(&*hours_str);
+            let holiday_shifts_str = hours.get(&x.id).map_or(0, |worked| worked.holiday_shifts).to_string();
+            insert.push(&*holiday_shifts_str);
+            let on_call_str = hours.get(&x.id).map_or(0, |worked| worked.on_call_duties).to_string();
+            insert.push(&*on_call_str);
+            let mut record: Vec<String> = insert.iter().map(|cell| cell.to_string()).collect();
+            for (i, cell) in record.iter_mut().enumerate().skip(1).take(day as usize) {
+                if let Some(times) = shown.get(&(i as i32, x.id)) {
+                    *cell = times.clone();
+                }
+                if let Some(on_call) = on_call_shown.get(&(i as i32, x.id)) {
+                    *cell = match shown.contains_key(&(i as i32, x.id)) {
+                        true => format!("{} + {}", cell, on_call),
+                        false => on_call.clone()
+                    };
+                }
+            }
+            wtr.write_record(record)?;
         }
-        Ok(file_name)
+        Ok(CsvExport {
+            file_name: format!("schedule_{}_{}.csv", month, year),
+            content: wtr.into_inner().map_err(|err| err.into_error())?,
+        })
     }
 
-    pub fn get_by_month_year(month: i32, year: i32,  conn: &mut PgConnection) -> Result<Vec<DayDetailName>, Error> {
-        use crate::schema::schedules::dsl::*;
+    // Shift times are shown in the caller's time zone, on-call duties are listed under ON_CALL_SLOT
+    pub fn get_by_month_year(month: i32, year: i32, _team_id: Option<i32>, claims: &TokenClaims, conn: &mut PgConnection) -> Result<Vec<DayDetailName>, Error> {
         use crate::schema::employees::dsl::*;
-        let start_date = NaiveDate::from_ymd_opt(year, month as u32, 1).unwrap();
-        let day  = match month {
-            1|3|5|7|8|10|12 => 31,
-            2 => 29,
-            4|6|9|11 => 30,
-            _ => return Err("Invalid Month".into())
-        };
-        let schedules_in_month = schedules.filter(data.between(start_date, NaiveDate::from_ymd_opt(year, month as u32, day).unwrap())).order_by(data).get_results::<Schedule>(conn)?;
-        let nums_employees = employees.load::<Employee>(conn)?;
+        let _organization_id = claims.org;
+        let end_date = last_day_of_month(month, year).ok_or("Invalid Month")?;
+        let start_date = end_date.with_day(1).unwrap();
+        let day = end_date.day();
+        // only the people the caller may see are listed, see `roster_scope`
+        let visible = Self::roster_scope(_team_id, claims, conn)?;
+        let is_visible = |_employee_id: i32| visible.as_ref().is_none_or(|visible| visible.contains(&_employee_id));
+        let mut schedules_in_month = Self::find_between(start_date, end_date, _team_id, _organization_id, conn)?;
+        schedules_in_month.retain(|schedule| is_visible(schedule.employee_id));
+        let nums_employees = employees.filter(organization_id.eq(_organization_id)).load::<Employee>(conn)?;
+        let mut leaves = LeaveRequest::find_approved_between(start_date, end_date, _organization_id, conn)?;
+        if let Some(_team_id) = _team_id {
+            let members = Team::member_ids(_team_id, conn)?;
+            leaves.retain(|leave| members.contains(&leave.employee_id));
+        }
+        leaves.retain(|leave| is_visible(leave.employee_id));
+        let catalogue = Team::shift_catalogue(_team_id, _organization_id, conn)?;
+        let windows = Self::slot_windows(&catalogue, _organization_id, conn)?;
+        let zone = Team::time_zone(_team_id, _organization_id, conn)?;
+        let viewer_zone = Employee::viewer_time_zone(claims.sub, _organization_id, zone, conn)?;
         let mut map_id_name : HashMap<i32, String> = HashMap::new();
 
         for emp in nums_employees {
             map_id_name.insert(emp.id, emp.name);
         }
+        let on_call_shifts = Self::on_call_pay(_organization_id, conn)?;
         let mut map :HashMap<(i32, String), Vec<String>> = HashMap::new();
         let mut rs : Vec<DayDetailName> = Vec::new();
         for schedule in schedules_in_month {
 
-            let shift_name :&str = match schedule.shift_id {
-                1 => "H",
-                2 => "D",
-                3 => "C",
-                4 => "S",
-                _ => "N"
-            };
+            let shift_name :&str = if on_call_shifts.contains_key(&schedule.shift_id) { ON_CALL_SLOT } else { catalogue.code(schedule.shift_id) };
             let vl = map_id_name.get(&schedule.employee_id).unwrap().clone();
             match map.entry((schedule.data.day() as i32, shift_name.to_string())) {
                 std::collections::hash_map::Entry::Occupied(mut entry) => {
@@ -295,21 +576,24 @@ impl Schedule {
                 }
             }
         }
+        // employees on approved leave are listed under the code of their leave type
+        for ((leave_day, leave_employee), code) in LeaveRequest::codes_by_day(&leaves, month, year) {
+            if let Some(vl) = map_id_name.get(&leave_employee) {
+                map.entry((leave_day, code.to_string())).or_default().push(vl.clone());
+            }
+        }
+        let keys: Vec<&str> = ["S", "C", "D", "H", ON_CALL_SLOT].into_iter().chain(LEAVE_TYPES.iter().map(|(_, code)| *code)).collect();
         for i in 1..=day {
             let mut vec_shift : Vec<ShiftDetailName> = Vec::new();
-            for shift in vec!["S", "C", "D", "H"] {
-                if month == 2 && year == 2024 && i == 29 {
-                    vec_shift.push(
-                        ShiftDetailName {
-                        key: shift.to_string(),
-                        value: vec![],
-                    });
-                    continue;
-                }
+            for shift in &keys {
                 let insert : Vec<String> = map.get(&(i as i32, shift.to_string())).unwrap_or(&vec![]).clone();
+                let times = NaiveDate::from_ymd_opt(year, month as u32, i)
+                    .and_then(|date| shown_times(&windows, shift, date, zone, viewer_zone));
                 let shift_value = ShiftDetailName {
                     key: shift.to_string(),
                     value: insert,
+                    starts_at: times.map(|(start, _)| start),
+                    ends_at: times.map(|(_, end)| end),
                 };
                 vec_shift.push(shift_value)
             }
@@ -321,18 +605,270 @@ impl Schedule {
         }
         Ok(rs)
     }
-    //
-    fn analysis(month: i32, year: i32,  conn: &mut PgConnection) -> Result<String, Error> {
+    // Times and unpaid breaks of the shift behind each slot of the catalogue that has one
+    fn slot_windows(catalogue: &ShiftCatalogue, _organization_id: i32, conn: &mut PgConnection) -> Result<HashMap<String, ShiftWindow>, Error> {
+        let unpaid = ShiftBreak::unpaid_minutes_by_shift(_organization_id, conn)?;
+        let mut windows = HashMap::new();
+        for slot in SHIFT_SLOTS {
+            if let Some(id_shift) = catalogue.shift_id(slot) {
+                let window = Shift::find_by_id(&id_shift, _organization_id, conn)?.window()
+                    .with_unpaid_breaks(unpaid.get(&id_shift).copied().unwrap_or(0));
+                windows.insert(slot.to_string(), window);
+            }
+        }
+        Ok(windows)
+    }
+
+    // Times and unpaid breaks of every shift of an organization, archived ones included
+    fn shift_windows(_organization_id: i32, conn: &mut PgConnection) -> Result<HashMap<i32, ShiftWindow>, Error> {
+        let unpaid = ShiftBreak::unpaid_minutes_by_shift(_organization_id, conn)?;
+        Ok(Shift::find_all(ShiftFilter { include_archived: true }, _organization_id, conn)?
+            .into_iter()
+            .map(|shift| (shift.id, shift.window().with_unpaid_breaks(unpaid.get(&shift.id).copied().unwrap_or(0))))
+            .collect())
+    }
+
+    // Schedules between two dates, of one team or of the whole organization
+    fn find_between(start: NaiveDate, end: NaiveDate, _team_id: Option<i32>, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<Schedule>, Error> {
+        use crate::schema::schedules::dsl::*;
+        let mut query = schedules.filter(organization_id.eq(_organization_id)).filter(data.between(start, end)).into_boxed();
+        if let Some(_team_id) = _team_id {
+            query = query.filter(team_id.eq(_team_id));
+        }
+        Ok(query.order_by(data).get_results::<Schedule>(conn)?)
+    }
+
+    // Zone of the shift times of every team in `schedules`
+    fn team_zones(schedules_in_range: &[Schedule], _organization_id: i32, conn: &mut PgConnection) -> Result<HashMap<Option<i32>, Tz>, Error> {
+        let mut zones = HashMap::new();
+        for schedule in schedules_in_range {
+            if let std::collections::hash_map::Entry::Vacant(entry) = zones.entry(schedule.team_id) {
+                entry.insert(Team::time_zone(schedule.team_id, _organization_id, conn)?);
+            }
+        }
+        Ok(zones)
+    }
+
+    // Region of the holidays of every team in `schedules`
+    fn team_regions(schedules_in_range: &[Schedule], _organization_id: i32, conn: &mut PgConnection) -> Result<HashMap<Option<i32>, Option<String>>, Error> {
+        let mut regions = HashMap::new();
+        for schedule in schedules_in_range {
+            if let std::collections::hash_map::Entry::Vacant(entry) = regions.entry(schedule.team_id) {
+                entry.insert(Team::region(schedule.team_id, _organization_id, conn)?);
+            }
+        }
+        Ok(regions)
+    }
+
+    // On-call shift id -> share of its hours that is paid
+    fn on_call_pay(_organization_id: i32, conn: &mut PgConnection) -> Result<HashMap<i32, f64>, Error> {
+        let factor = Organization::find_by_id(_organization_id, conn)?.on_call_pay_factor;
+        Ok(Shift::find_all(ShiftFilter { include_archived: true }, _organization_id, conn)?
+            .into_iter()
+            .filter(|shift| shift.category == SHIFT_ON_CALL)
+            .map(|shift| (shift.id, factor))
+            .collect())
+    }
+
+    /*
+        Employee id -> minutes of their shifts, net leaves out unpaid breaks.
+        A shift on a holiday of the region of its team is paid its net minutes times the holiday's multiplier,
+        on-call shifts (keys of `on_call_pay`) are counted apart and paid their share
+     */
+    fn worked_minutes(schedules_in_range: &[Schedule], windows: &HashMap<i32, ShiftWindow>, zones: &HashMap<Option<i32>, Tz>,
+                      holidays: &[Holiday], regions: &HashMap<Option<i32>, Option<String>>, on_call_pay: &HashMap<i32, f64>) -> HashMap<i32, WorkedMinutes> {
+        let mut minutes: HashMap<i32, WorkedMinutes> = HashMap::new();
+        for schedule in schedules_in_range {
+            if let Some(window) = windows.get(&schedule.shift_id) {
+                let zone = zones[&schedule.team_id];
+                let (start, end) = window.instants(schedule.data, zone);
+                let entry = minutes.entry(schedule.employee_id).or_default();
+                if let Some(factor) = on_call_pay.get(&schedule.shift_id) {
+                    entry.on_call_duties += 1;
+                    entry.on_call += (end - start).num_minutes();
+                    entry.paid += (end - start).num_minutes() as f64 * factor;
+                    continue;
+                }
+                let net = window.net_duration(schedule.data, zone).num_minutes();
+                let holiday = Holiday::on_date(holidays, schedule.data, regions[&schedule.team_id].as_deref());
+                entry.shifts += 1;
+                entry.gross += (end - start).num_minutes();
+                entry.net += net;
+                entry.paid += net as f64 * holiday.map_or(1.0, |holiday| holiday.pay_multiplier);
+                if holiday.is_some() {
+                    entry.holiday_shifts += 1;
+                }
+            }
+        }
+        minutes
+    }
+
+    // Shifts of a month, of one team or of the whole organization, worked without the skills they require
+    pub fn skill_violations(month: i32, year: i32, _team_id: Option<i32>, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<SkillViolation>, Error> {
+        let end_date = last_day_of_month(month, year).ok_or("Invalid Month")?;
+        let start_date = end_date.with_day(1).unwrap();
+        if let Some(_team_id) = _team_id {
+            Team::find_by_id(_team_id, _organization_id, conn)?;
+        }
+        let schedules_in_month = Self::find_between(start_date, end_date, _team_id, _organization_id, conn)?;
+        Skill::violations(&schedules_in_month, _organization_id, conn)
+    }
+
+    /*
+        Hours worked by each employee in a month, of one team or of the whole organization.
+        Only the people the caller may see are listed, see `roster_scope`
+     */
+    pub fn hours(month: i32, year: i32, _team_id: Option<i32>, claims: &TokenClaims, conn: &mut PgConnection) -> Result<Vec<EmployeeHours>, Error> {
+        use crate::schema::employees::dsl::*;
+        let _organization_id = claims.org;
+        let end_date = last_day_of_month(month, year).ok_or("Invalid Month")?;
+        let start_date = end_date.with_day(1).unwrap();
+        let visible = Self::roster_scope(_team_id, claims, conn)?;
+        let mut schedules_in_month = Self::find_between(start_date, end_date, _team_id, _organization_id, conn)?;
+        schedules_in_month.retain(|schedule| visible.as_ref().is_none_or(|visible| visible.contains(&schedule.employee_id)));
+        let windows = Self::shift_windows(_organization_id, conn)?;
+        let zones = Self::team_zones(&schedules_in_month, _organization_id, conn)?;
+        let regions = Self::team_regions(&schedules_in_month, _organization_id, conn)?;
+        let holidays = Holiday::find_between(start_date, end_date, _organization_id, conn)?;
+        let on_call_pay = Self::on_call_pay(_organization_id, conn)?;
+        let minutes = Self::worked_minutes(&schedules_in_month, &windows, &zones, &holidays, &regions, &on_call_pay);
+        let mut rs: Vec<EmployeeHours> = employees
+            .filter(organization_id.eq(_organization_id))
+            .filter(crate::schema::employees::id.eq_any(minutes.keys().copied().collect::<Vec<i32>>()))
+            .load::<Employee>(conn)?
+            .into_iter()
+            .map(|emp| {
+                let worked = &minutes[&emp.id];
+                EmployeeHours {
+                    employee_id: emp.id,
+                    name: emp.name,
+                    shifts: worked.shifts,
+                    gross_hours: worked.gross as f64 / 60.0,
+                    net_hours: worked.net as f64 / 60.0,
+                    holiday_shifts: worked.holiday_shifts,
+                    on_call_duties: worked.on_call_duties,
+                    on_call_hours: worked.on_call as f64 / 60.0,
+                    paid_hours: worked.paid / 60.0,
+                }
+            })
+            .collect();
+        rs.sort_by_key(|hours| hours.employee_id);
+        Ok(rs)
+    }
+
+    /*
+        iCalendar feed of an employee's shifts and approved leave, or with a team of everyone in it,
+        from FEED_DAYS_BEHIND days ago to FEED_DAYS_AHEAD days ahead. It is built on every request,
+        so approved swaps and leave show up at the calendar's next refresh.
+        Team feeds are open to its members, its manager and whoever sees every department
+     */
+    pub fn ical_feed(employee: &Employee, _team_id: Option<i32>, conn: &mut PgConnection) -> Result<String, Error> {
+        use crate::schema::employees;
+        let _organization_id = employee.organization_id;
+        let today = Utc::now().date_naive();
+        let start_date = today - chrono::Duration::days(FEED_DAYS_BEHIND);
+        let end_date = today + chrono::Duration::days(FEED_DAYS_AHEAD);
+        let (calendar_name, members) = match _team_id {
+            Some(_team_id) => {
+                let team = Team::find_by_id(_team_id, _organization_id, conn)?;
+                let members = Team::member_ids(_team_id, conn)?;
+                let allowed = members.contains(&employee.id) || team.manager_id == Some(employee.id)
+                    || has_permission(&employee.role, Permission::ViewAllDepartments);
+                if !allowed {
+                    return Err(format!("You aren't a member of team '{}'", team.name).into())
+                }
+                (format!("Schedule of team {}", team.name), members)
+            },
+            None => (format!("Schedule of {}", employee.name), vec![employee.id])
+        };
+        let schedules_in_range: Vec<Schedule> = Self::find_between(start_date, end_date, _team_id, _organization_id, conn)?
+            .into_iter()
+            .filter(|schedule| _team_id.is_some() || schedule.employee_id == employee.id)
+            .collect();
+        let leaves: Vec<LeaveRequest> = LeaveRequest::find_approved_between(start_date, end_date, _organization_id, conn)?
+            .into_iter()
+            .filter(|leave| members.contains(&leave.employee_id))
+            .collect();
+        let shifts: HashMap<i32, Shift> = Shift::find_all(ShiftFilter { include_archived: true }, _organization_id, conn)?
+            .into_iter()
+            .map(|shift| (shift.id, shift))
+            .collect();
+        let windows = Self::shift_windows(_organization_id, conn)?;
+        let zones = Self::team_zones(&schedules_in_range, _organization_id, conn)?;
+        let names: HashMap<i32, String> = employees::table
+            .filter(employees::organization_id.eq(_organization_id))
+            .select((employees::id, employees::name))
+            .load::<(i32, String)>(conn)?
+            .into_iter()
+            .collect();
+        // team feeds say whose event it is
+        let summary = |_employee_id: i32, what: String| match _team_id {
+            Some(_) => format!("{}: {}", names.get(&_employee_id).map(String::as_str).unwrap_or("?"), what),
+            None => what
+        };
 
-        Ok("Oke".to_string())
+        let mut events = Vec::new();
+        for schedule in &schedules_in_range {
+            let (shift, window) = match (shifts.get(&schedule.shift_id), windows.get(&schedule.shift_id)) {
+                (Some(shift), Some(window)) => (shift, window),
+                _ => continue
+            };
+            let (start, end) = window.instants(schedule.data, zones[&schedule.team_id]);
+            // on-call duties don't block the calendar
+            let on_call = shift.category == SHIFT_ON_CALL;
+            let what = if on_call { format!("On call ({})", shift.name) } else { shift.name.clone() };
+            events.push(FeedEvent {
+                uid: format!("schedule-{}@scheduler_api", schedule.id),
+                start: EventTime::At(start),
+                end: EventTime::At(end),
+                summary: summary(schedule.employee_id, what),
+                description: schedule.note.clone(),
+                transparent: on_call,
+            });
+        }
+        for leave in &leaves {
+            events.push(FeedEvent {
+                uid: format!("leave-{}@scheduler_api", leave.id),
+                start: EventTime::Day(leave.start_date),
+                end: EventTime::Day(leave.end_date + chrono::Duration::days(1)),
+                summary: summary(leave.employee_id, format!("Leave ({})", leave.leave_type)),
+                description: None,
+                transparent: true,
+            });
+        }
+        Ok(write_ical(&calendar_name, &events, Utc::now()))
     }
 }
 
 
+// Start and end of the shift of `slot` worked on `date`, written in `zone` and shown in `viewer_zone`
+fn shown_times(windows: &HashMap<String, ShiftWindow>, slot: &str, date: NaiveDate, zone: Tz, viewer_zone: Tz)
+               -> Option<(DateTime<FixedOffset>, DateTime<FixedOffset>)> {
+    let (start, end) = windows.get(slot)?.instants(date, zone);
+    Some((start.with_timezone(&viewer_zone).fixed_offset(), end.with_timezone(&viewer_zone).fixed_offset()))
+}
+
 
 #[cfg(test)]
 mod tests {
-    use crate::models::schedule::{AutoScheduleDTO, create_sample_schedule, Schedule};
+    use std::collections::{BTreeMap, HashMap};
+    use chrono::NaiveTime;
+    use chrono_tz::Tz;
+    use crate::models::schedule::{AutoScheduleDTO, OnCallPlan, create_sample_schedule};
+    use crate::models::teams::ON_CALL_SLOT;
+    use crate::models::shifts::ShiftWindow;
+    use crate::models::skills::SkillRules;
+    use crate::utils::verify_valid_schedule;
+
+    // the default shifts, see models::teams::SHIFT_SLOTS
+    fn windows() -> HashMap<String, ShiftWindow> {
+        [("S", 6, 14), ("C", 14, 22), ("D", 22, 6), ("H", 8, 18)].into_iter()
+            .map(|(slot, start, end)| (slot.to_string(), ShiftWindow::new(
+                NaiveTime::from_hms_opt(start, 0, 0).unwrap(),
+                NaiveTime::from_hms_opt(end, 0, 0).unwrap()
+            )))
+            .collect()
+    }
 
     #[test]
     fn it_works() {
@@ -346,12 +882,243 @@ mod tests {
             employees: vec![1,2,3,5,7,8,9,11],
             month: 1,
             year: 2024,
-            nums_h: 2
+            nums_h: 2,
+            team_id: None,
+            max_hours: None,
+            on_call_shift_id: None,
+            nums_on_call: None
         };
-        let rs = create_sample_schedule(&dto);
+        let rs = create_sample_schedule(&dto, &HashMap::new(), &windows(), Tz::UTC, &HashMap::new(), &SkillRules::default(), None);
         assert!(rs.is_ok())
     }
 
+    #[test]
+    fn test_employee_on_leave_is_not_rostered() {
+        let dto = AutoScheduleDTO {
+            employees: vec![1,2,3,5,7,8,9,11],
+            month: 1,
+            year: 2024,
+            nums_h: 2,
+            team_id: None,
+            max_hours: None,
+            on_call_shift_id: None,
+            nums_on_call: None
+        };
+        let mut on_leave = HashMap::new();
+        for day in 1..=10 {
+            on_leave.insert((day, 7), "V");
+        }
+        let rs = create_sample_schedule(&dto, &on_leave, &windows(), Tz::UTC, &HashMap::new(), &SkillRules::default(), None).unwrap();
+        for day in rs.iter().filter(|day| day.day <= 10) {
+            assert!(!day.value.iter().any(|shift| shift.value.contains(&7)));
+        }
+    }
+
+    #[test]
+    fn test_not_enough_employees_because_of_leave() {
+        let dto = AutoScheduleDTO {
+            employees: vec![1,2,3,5,7],
+            month: 1,
+            year: 2024,
+            nums_h: 2,
+            team_id: None,
+            max_hours: None,
+            on_call_shift_id: None,
+            nums_on_call: None
+        };
+        let on_leave = HashMap::from([((15, 7), "SL")]);
+        assert!(create_sample_schedule(&dto, &on_leave, &windows(), Tz::UTC, &HashMap::new(), &SkillRules::default(), None).is_err());
+    }
+
+    #[test]
+    fn test_night_shift_is_not_followed_by_early_shifts() {
+        let dto = AutoScheduleDTO {
+            employees: vec![1,2,3,5,7,8,9,11],
+            month: 3,
+            year: 2024,
+            nums_h: 2,
+            team_id: None,
+            max_hours: None,
+            on_call_shift_id: None,
+            nums_on_call: None
+        };
+        // picking rested employees first gives a valid schedule without retries, DST change on the 31st included
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let rs = create_sample_schedule(&dto, &HashMap::new(), &windows(), berlin, &HashMap::new(), &SkillRules::default(), None).unwrap();
+        assert!(verify_valid_schedule(&rs, &dto, &windows(), berlin, &HashMap::new(), &SkillRules::default(), None));
+        for pair in rs.windows(2) {
+            let night = &pair[0].value.iter().find(|shift| shift.key == "D").unwrap().value;
+            for early in ["S", "H"] {
+                let next = &pair[1].value.iter().find(|shift| shift.key == early).unwrap().value;
+                assert!(!next.iter().any(|e| night.contains(e)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_hour_cap_uses_net_hours() {
+        let mut dto = AutoScheduleDTO {
+            employees: vec![1,2,3,5,7,8,9,11],
+            month: 1,
+            year: 2024,
+            nums_h: 2,
+            team_id: None,
+            max_hours: Some(100),
+            on_call_shift_id: None,
+            nums_on_call: None
+        };
+        // an hour of unpaid break in every shift, 39 net hours a day between 8 employees
+        let windows: HashMap<String, ShiftWindow> = windows().into_iter()
+            .map(|(slot, window)| (slot, window.with_unpaid_breaks(60)))
+            .collect();
+        let rs = create_sample_schedule(&dto, &HashMap::new(), &windows, Tz::UTC, &HashMap::new(), &SkillRules::default(), None).unwrap();
+        assert!(!verify_valid_schedule(&rs, &dto, &windows, Tz::UTC, &HashMap::new(), &SkillRules::default(), None));
+        // 8 * 160 = 1280 covers the 1209 net hours of the month, but not their 1364 gross hours
+        dto.max_hours = Some(160);
+        let rs = create_sample_schedule(&dto, &HashMap::new(), &windows, Tz::UTC, &HashMap::new(), &SkillRules::default(), None).unwrap();
+        assert!(verify_valid_schedule(&rs, &dto, &windows, Tz::UTC, &HashMap::new(), &SkillRules::default(), None));
+    }
+
+    #[test]
+    fn test_holiday_staffing_and_fairness() {
+        let dto = AutoScheduleDTO {
+            employees: vec![1,2,3,5,7,8,9,11],
+            month: 12,
+            year: 2024,
+            nums_h: 2,
+            team_id: None,
+            max_hours: None,
+            on_call_shift_id: None,
+            nums_on_call: None
+        };
+        // no office hours on the holidays, a second night shift on Christmas Eve
+        let holidays: HashMap<i32, HashMap<String, i32>> = [24, 25, 26, 31].into_iter()
+            .map(|day| (day, HashMap::from([("H".to_string(), 0), ("D".to_string(), if day == 24 { 2 } else { 1 })])))
+            .collect();
+        let rs = create_sample_schedule(&dto, &HashMap::new(), &windows(), Tz::UTC, &holidays, &SkillRules::default(), None).unwrap();
+        assert!(verify_valid_schedule(&rs, &dto, &windows(), Tz::UTC, &holidays, &SkillRules::default(), None));
+        let slot = |day: i32, key: &str| rs[day as usize - 1].value.iter().find(|shift| shift.key == key).unwrap().value.len();
+        assert_eq!(slot(25, "H"), 0);
+        assert_eq!(slot(24, "D"), 2);
+        assert_eq!(slot(23, "H"), 2);
+        // 13 holiday shifts between 8 employees
+        let mut holiday_shifts: HashMap<i32, usize> = HashMap::new();
+        for day in rs.iter().filter(|day| holidays.contains_key(&day.day)) {
+            for employee in day.value.iter().flat_map(|shift| &shift.value) {
+                *holiday_shifts.entry(*employee).or_insert(0) += 1;
+            }
+        }
+        assert!(dto.employees.iter().all(|e| (1..=2).contains(holiday_shifts.get(e).unwrap_or(&0))));
+        // a holiday asking for more than what was rostered
+        let more = HashMap::from([(25, HashMap::from([("S".to_string(), 3)]))]);
+        assert!(!verify_valid_schedule(&rs, &dto, &windows(), Tz::UTC, &more, &SkillRules::default(), None));
+    }
+
+    #[test]
+    fn test_night_shift_gets_an_l2_analyst() {
+        let dto = AutoScheduleDTO {
+            employees: vec![1,2,3,5,7,8,9,11],
+            month: 1,
+            year: 2024,
+            nums_h: 2,
+            team_id: None,
+            max_hours: None,
+            on_call_shift_id: None,
+            nums_on_call: None
+        };
+        const L2: i32 = 1;
+        // three analysts for 31 nights, two of them certified on the tool the nights of the 10th need twice
+        let skills = SkillRules {
+            employee_skills: HashMap::from([(2, vec![L2]), (5, vec![L2, 2]), (9, vec![L2, 2])]),
+            every_day: HashMap::from([("D".to_string(), BTreeMap::from([(L2, 1)]))]),
+            on_day: HashMap::from([((10, "D".to_string()), BTreeMap::from([(2, 2)]))]),
+        };
+        let rs = create_sample_schedule(&dto, &HashMap::new(), &windows(), Tz::UTC, &HashMap::new(), &skills, None).unwrap();
+        assert!(verify_valid_schedule(&rs, &dto, &windows(), Tz::UTC, &HashMap::new(), &skills, None));
+        for day in &rs {
+            let night = &day.value.iter().find(|shift| shift.key == "D").unwrap().value;
+            assert!(night.iter().any(|e| [2, 5, 9].contains(e)));
+        }
+        let tenth = &rs[9].value.iter().find(|shift| shift.key == "D").unwrap().value;
+        assert!(tenth.contains(&5) && tenth.contains(&9));
+        // nobody holds the skill
+        let nobody = SkillRules { employee_skills: HashMap::new(), ..skills };
+        let rs = create_sample_schedule(&dto, &HashMap::new(), &windows(), Tz::UTC, &HashMap::new(), &nobody, None).unwrap();
+        assert!(!verify_valid_schedule(&rs, &dto, &windows(), Tz::UTC, &HashMap::new(), &nobody, None));
+    }
+
+    #[test]
+    fn test_closed_slot_gets_no_skill_holders() {
+        let dto = AutoScheduleDTO {
+            employees: vec![1,2,3,5,7,8,9,11],
+            month: 12,
+            year: 2024,
+            nums_h: 2,
+            team_id: None,
+            max_hours: None,
+            on_call_shift_id: None,
+            nums_on_call: None
+        };
+        const L2: i32 = 1;
+        // office hours need an L2 analyst, except on the holidays that close them
+        let skills = SkillRules {
+            employee_skills: HashMap::from([(2, vec![L2]), (5, vec![L2]), (9, vec![L2])]),
+            every_day: HashMap::from([("H".to_string(), BTreeMap::from([(L2, 1)]))]),
+            on_day: HashMap::new(),
+        };
+        let holidays: HashMap<i32, HashMap<String, i32>> = [25, 26].into_iter()
+            .map(|day| (day, HashMap::from([("H".to_string(), 0)])))
+            .collect();
+        let rs = create_sample_schedule(&dto, &HashMap::new(), &windows(), Tz::UTC, &holidays, &skills, None).unwrap();
+        assert!(verify_valid_schedule(&rs, &dto, &windows(), Tz::UTC, &holidays, &skills, None));
+        let office = |day: usize| rs[day - 1].value.iter().find(|shift| shift.key == "H").unwrap().value.clone();
+        assert!(office(25).is_empty() && office(26).is_empty());
+        assert!(office(24).iter().any(|e| [2, 5, 9].contains(e)));
+        // somebody rostered into the closed slot
+        let mut staffed = rs.clone();
+        staffed[24].value.iter_mut().find(|shift| shift.key == "H").unwrap().value.push(2);
+        assert!(!verify_valid_schedule(&staffed, &dto, &windows(), Tz::UTC, &holidays, &skills, None));
+    }
+
+    #[test]
+    fn test_on_call_duties_are_shared_out() {
+        let dto = AutoScheduleDTO {
+            employees: vec![1,2,3,5,7,8,9,11],
+            month: 1,
+            year: 2024,
+            nums_h: 2,
+            team_id: None,
+            max_hours: None,
+            on_call_shift_id: Some(20),
+            nums_on_call: Some(1)
+        };
+        let mut windows = windows();
+        windows.insert(ON_CALL_SLOT.to_string(), ShiftWindow::new(NaiveTime::from_hms_opt(18, 0, 0).unwrap(), NaiveTime::from_hms_opt(8, 0, 0).unwrap()));
+        let plan = OnCallPlan { per_day: 1, with_shift: true, max_per_month: Some(4) };
+        let rs = create_sample_schedule(&dto, &HashMap::new(), &windows, Tz::UTC, &HashMap::new(), &SkillRules::default(), Some(&plan)).unwrap();
+        assert!(verify_valid_schedule(&rs, &dto, &windows, Tz::UTC, &HashMap::new(), &SkillRules::default(), Some(&plan)));
+        let mut duties: HashMap<i32, i32> = HashMap::new();
+        for day in &rs {
+            let on_duty = &day.value.iter().find(|shift| shift.key == ON_CALL_SLOT).unwrap().value;
+            assert_eq!(on_duty.len(), 1);
+            *duties.entry(on_duty[0]).or_insert(0) += 1;
+        }
+        // 31 duties between 8 employees
+        assert!(duties.values().all(|count| (3..=4).contains(count)));
+        // more duties than the cap lets the employees take
+        let capped = OnCallPlan { max_per_month: Some(3), ..plan.clone() };
+        let rs = create_sample_schedule(&dto, &HashMap::new(), &windows, Tz::UTC, &HashMap::new(), &SkillRules::default(), Some(&capped)).unwrap();
+        assert!(!verify_valid_schedule(&rs, &dto, &windows, Tz::UTC, &HashMap::new(), &SkillRules::default(), Some(&capped)));
+        // nobody on call works a shift that day
+        let apart = OnCallPlan { with_shift: false, max_per_month: None, ..plan };
+        let rs = create_sample_schedule(&dto, &HashMap::new(), &windows, Tz::UTC, &HashMap::new(), &SkillRules::default(), Some(&apart)).unwrap();
+        assert!(verify_valid_schedule(&rs, &dto, &windows, Tz::UTC, &HashMap::new(), &SkillRules::default(), Some(&apart)));
+        for day in &rs {
+            let on_duty = &day.value.iter().find(|shift| shift.key == ON_CALL_SLOT).unwrap().value;
+            assert!(!day.value.iter().filter(|shift| shift.key != ON_CALL_SLOT).any(|shift| shift.value.contains(&on_duty[0])));
+        }
+    }
+
     // #[test]
     // fn test_export() {
     //     let month = 1;
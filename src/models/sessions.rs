@@ -0,0 +1,221 @@
+use bcrypt::{DEFAULT_COST, hash, verify};
+use chrono::{Duration, NaiveDateTime, Utc};
+use diesel::{Identifiable, Insertable, PgConnection, Queryable, RunQueryDsl};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::config::jwt::jwt_config;
+use crate::constants;
+use crate::error::Error;
+use crate::models::employee::Employee;
+use crate::schema::sessions;
+use crate::utils::generate_token;
+use diesel::prelude::*;
+
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable)]
+#[diesel(table_name = sessions)]
+pub struct Session {
+    pub id: i32,
+    pub employee_id: i32,
+    #[serde(skip_serializing)]
+    pub refresh_token_hash: String,
+    #[serde(skip_serializing)]
+    pub previous_token_hash: Option<String>,
+    pub expires_at: NaiveDateTime,
+    pub revoked_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub last_used_at: NaiveDateTime
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = sessions)]
+struct NewSession {
+    employee_id: i32,
+    refresh_token_hash: String,
+    expires_at: NaiveDateTime
+}
+
+// Returned on login and on every refresh, the refresh token is "<session id>.<secret>"
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TokenPair {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub refresh_expires_at: NaiveDateTime
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshDTO {
+    pub refresh_token: String
+}
+
+#[derive(Debug, PartialEq)]
+enum RefreshCheck {
+    Valid,
+    // the secret of the previous rotation, someone else may hold the current one
+    Replayed,
+    Invalid
+}
+
+fn new_secret() -> String {
+    rand::thread_rng().sample_iter(&Alphanumeric).take(32).collect()
+}
+
+fn split_token(token: &str) -> Result<(i32, &str), Error> {
+    match token.split_once('.') {
+        Some((session_id, secret)) => Ok((session_id.parse::<i32>().map_err(|_| constants::MESSAGE_INVALID_TOKEN)?, secret)),
+        None => Err(constants::MESSAGE_INVALID_TOKEN.into())
+    }
+}
+
+impl Session {
+    // Open a session for an employee who just logged in
+    pub fn open(employee: &Employee, conn: &mut PgConnection) -> Result<TokenPair, Error> {
+        use crate::schema::sessions::dsl::*;
+        let secret = new_secret();
+        let session = diesel::insert_into(sessions)
+            .values(NewSession {
+                employee_id: employee.id,
+                refresh_token_hash: hash(&secret, DEFAULT_COST)?,
+                expires_at: Utc::now().naive_utc() + Duration::days(jwt_config().refresh_ttl_days),
+            })
+            .get_result::<Session>(conn)?;
+        session.token_pair(employee, secret)
+    }
+
+    fn token_pair(&self, employee: &Employee, secret: String) -> Result<TokenPair, Error> {
+        Ok(TokenPair {
+            access_token: generate_token(employee.id, employee.role.clone(), employee.organization_id, self.id, Utc::now())?,
+            refresh_token: format!("{}.{}", self.id, secret),
+            refresh_expires_at: self.expires_at,
+        })
+    }
+
+    fn check(&self, secret: &str, now: NaiveDateTime) -> RefreshCheck {
+        if self.revoked_at.is_some() || self.expires_at <= now {
+            return RefreshCheck::Invalid
+        }
+        if verify(secret, &self.refresh_token_hash).unwrap_or(false) {
+            return RefreshCheck::Valid
+        }
+        match &self.previous_token_hash {
+            Some(previous) if verify(secret, previous).unwrap_or(false) => RefreshCheck::Replayed,
+            _ => RefreshCheck::Invalid
+        }
+    }
+
+    /*
+        Swap a refresh token for a new access token and a new refresh token, the old one stops working.
+        Presenting an already rotated token revokes the whole session
+     */
+    pub fn refresh(refresh_dto: RefreshDTO, conn: &mut PgConnection) -> Result<TokenPair, Error> {
+        use crate::schema::sessions::dsl::*;
+        let (session_id, secret) = split_token(&refresh_dto.refresh_token)?;
+        let now = Utc::now().naive_utc();
+        let session = sessions.find(session_id).first::<Session>(conn)
+            .optional()?
+            .ok_or(constants::MESSAGE_INVALID_TOKEN)?;
+
+        match session.check(secret, now) {
+            RefreshCheck::Valid => (),
+            RefreshCheck::Replayed => {
+                Self::revoke(session.id, session.employee_id, conn)?;
+                return Err(constants::MESSAGE_INVALID_TOKEN.into())
+            }
+            RefreshCheck::Invalid => return Err(constants::MESSAGE_INVALID_TOKEN.into())
+        }
+        // the session says whose it is, the employee's organization comes with them
+        let employee = crate::schema::employees::table.find(session.employee_id).first::<Employee>(conn)?;
+        if !employee.active {
+            Self::revoke_all(employee.id, None, conn)?;
+            return Err(constants::MESSAGE_INVALID_TOKEN.into())
+        }
+
+        let next_secret = new_secret();
+        // the update only hits the hash we checked, so two concurrent refreshes can't both succeed
+        let rotated = diesel::update(sessions.find(session.id).filter(refresh_token_hash.eq(&session.refresh_token_hash)))
+            .set((
+                refresh_token_hash.eq(hash(&next_secret, DEFAULT_COST)?),
+                previous_token_hash.eq(&session.refresh_token_hash),
+                last_used_at.eq(now),
+            ))
+            .get_result::<Session>(conn)
+            .optional()?
+            .ok_or(constants::MESSAGE_INVALID_TOKEN)?;
+        rotated.token_pair(&employee, next_secret)
+    }
+
+    // Access tokens are only accepted while their session is open
+    pub fn is_active(_id: i32, conn: &mut PgConnection) -> Result<bool, Error> {
+        use crate::schema::sessions::dsl::*;
+        let open = sessions.find(_id)
+            .filter(revoked_at.is_null())
+            .filter(expires_at.gt(Utc::now().naive_utc()))
+            .count()
+            .get_result::<i64>(conn)?;
+        Ok(open > 0)
+    }
+
+    pub fn revoke(_id: i32, _employee_id: i32, conn: &mut PgConnection) -> Result<String, Error> {
+        use crate::schema::sessions::dsl::*;
+        diesel::update(sessions.find(_id).filter(employee_id.eq(_employee_id)).filter(revoked_at.is_null()))
+            .set(revoked_at.eq(Utc::now().naive_utc()))
+            .execute(conn)?;
+        Ok(constants::MESSAGE_LOGOUT_SUCCESS.to_string())
+    }
+
+    // Close every open session of an employee, except `keep` when given
+    pub fn revoke_all(_employee_id: i32, keep: Option<i32>, conn: &mut PgConnection) -> Result<usize, Error> {
+        use crate::schema::sessions::dsl::*;
+        let mut query = diesel::update(sessions)
+            .filter(employee_id.eq(_employee_id))
+            .filter(revoked_at.is_null())
+            .into_boxed();
+        if let Some(kept) = keep {
+            query = query.filter(id.ne(kept));
+        }
+        Ok(query.set(revoked_at.eq(Utc::now().naive_utc())).execute(conn)?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use bcrypt::hash;
+    use chrono::{Duration, NaiveDate};
+    use crate::models::sessions::{RefreshCheck, Session, split_token};
+
+    fn session(current: &str, previous: Option<&str>) -> Session {
+        let created_at = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        Session {
+            id: 1,
+            employee_id: 2,
+            refresh_token_hash: hash(current, 4).unwrap(),
+            previous_token_hash: previous.map(|p| hash(p, 4).unwrap()),
+            expires_at: created_at + Duration::days(30),
+            revoked_at: None,
+            created_at,
+            last_used_at: created_at,
+        }
+    }
+
+    #[test]
+    fn test_split_token() {
+        assert_eq!(split_token("12.abc").unwrap(), (12, "abc"));
+        assert!(split_token("abc").is_err());
+        assert!(split_token("x.abc").is_err());
+    }
+
+    #[test]
+    fn test_refresh_check() {
+        let now = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        let rotated = session("new", Some("old"));
+        assert_eq!(rotated.check("new", now), RefreshCheck::Valid);
+        assert_eq!(rotated.check("old", now), RefreshCheck::Replayed);
+        assert_eq!(rotated.check("guess", now), RefreshCheck::Invalid);
+
+        let revoked = Session { revoked_at: Some(now), ..session("new", None) };
+        assert_eq!(revoked.check("new", now), RefreshCheck::Invalid);
+        assert_eq!(rotated.check("new", now + Duration::days(60)), RefreshCheck::Invalid);
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use diesel::{Insertable, PgConnection, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use crate::error::Error;
+use crate::models::shifts::Shift;
+use crate::schema::shift_breaks;
+use diesel::prelude::*;
+
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Clone)]
+#[diesel(table_name = shift_breaks)]
+pub struct ShiftBreak {
+    pub id: i32,
+    pub shift_id: i32,
+    // minutes after the start of the shift
+    pub start_offset_minutes: i32,
+    pub length_minutes: i32,
+    pub paid: bool
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ShiftBreakDTO {
+    pub start_offset_minutes: i32,
+    pub length_minutes: i32,
+    #[serde(default)]
+    pub paid: bool
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = shift_breaks)]
+struct NewShiftBreak {
+    shift_id: i32,
+    start_offset_minutes: i32,
+    length_minutes: i32,
+    paid: bool
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShiftBreaksDTO {
+    pub breaks: Vec<ShiftBreakDTO>
+}
+
+// Breaks lie inside a shift of `duration_minutes` and don't overlap each other
+pub fn validate_breaks(breaks: &[ShiftBreakDTO], duration_minutes: i32) -> Result<(), Error> {
+    let mut sorted: Vec<&ShiftBreakDTO> = breaks.iter().collect();
+    sorted.sort_by_key(|b| b.start_offset_minutes);
+    let mut previous_end = 0;
+    for b in sorted {
+        if b.start_offset_minutes < 0 || b.length_minutes <= 0 {
+            return Err("Breaks need a non-negative start offset and a positive length".into())
+        }
+        if b.start_offset_minutes + b.length_minutes > duration_minutes {
+            return Err(format!("Break at +{} min doesn't fit in a shift of {} min", b.start_offset_minutes, duration_minutes).into())
+        }
+        if b.start_offset_minutes < previous_end {
+            return Err(format!("Break at +{} min overlaps the previous one", b.start_offset_minutes).into())
+        }
+        previous_end = b.start_offset_minutes + b.length_minutes;
+    }
+    Ok(())
+}
+
+impl ShiftBreak {
+    pub fn find_by_shift(_shift_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<ShiftBreak>, Error> {
+        Shift::find(_shift_id, _organization_id, conn)?;
+        Ok(shift_breaks::table
+            .filter(shift_breaks::shift_id.eq(_shift_id))
+            .order_by(shift_breaks::start_offset_minutes)
+            .load::<ShiftBreak>(conn)?)
+    }
+
+    // Replaces the breaks of a shift
+    pub fn set_for_shift(_shift_id: i32, breaks_dto: ShiftBreaksDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<ShiftBreak>, Error> {
+        let shift = Shift::find(_shift_id, _organization_id, conn)?;
+        if shift.archived_at.is_some() {
+            return Err("Archived shifts can't be changed".into())
+        }
+        validate_breaks(&breaks_dto.breaks, shift.duration_minutes)?;
+
+        conn.transaction::<(), Error, _>(|conn| {
+            diesel::delete(shift_breaks::table.filter(shift_breaks::shift_id.eq(_shift_id))).execute(conn)?;
+            let rows: Vec<NewShiftBreak> = breaks_dto.breaks.iter()
+                .map(|b| NewShiftBreak {
+                    shift_id: _shift_id,
+                    start_offset_minutes: b.start_offset_minutes,
+                    length_minutes: b.length_minutes,
+                    paid: b.paid,
+                })
+                .collect();
+            if !rows.is_empty() {
+                diesel::insert_into(shift_breaks::table).values(&rows).execute(conn)?;
+            }
+            Ok(())
+        })?;
+        Self::find_by_shift(_shift_id, _organization_id, conn)
+    }
+
+    // Latest end of a break of the shift, a new shift window has to keep them inside
+    pub fn latest_end(_shift_id: i32, conn: &mut PgConnection) -> Result<i32, Error> {
+        Ok(shift_breaks::table
+            .filter(shift_breaks::shift_id.eq(_shift_id))
+            .select(shift_breaks::start_offset_minutes + shift_breaks::length_minutes)
+            .load::<i32>(conn)?
+            .into_iter()
+            .max()
+            .unwrap_or(0))
+    }
+
+    // Unpaid break minutes of every shift of an organization that has some
+    pub fn unpaid_minutes_by_shift(_organization_id: i32, conn: &mut PgConnection) -> Result<HashMap<i32, i64>, Error> {
+        use crate::schema::shifts;
+        let rows = shift_breaks::table
+            .inner_join(shifts::table)
+            .filter(shifts::organization_id.eq(_organization_id))
+            .filter(shift_breaks::paid.eq(false))
+            .select((shift_breaks::shift_id, shift_breaks::length_minutes))
+            .load::<(i32, i32)>(conn)?;
+        let mut unpaid = HashMap::new();
+        for (shift, minutes) in rows {
+            *unpaid.entry(shift).or_insert(0) += minutes as i64;
+        }
+        Ok(unpaid)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::models::shift_breaks::{ShiftBreakDTO, validate_breaks};
+
+    fn brk(start_offset_minutes: i32, length_minutes: i32) -> ShiftBreakDTO {
+        ShiftBreakDTO { start_offset_minutes, length_minutes, paid: false }
+    }
+
+    #[test]
+    fn test_breaks_fit_in_the_shift() {
+        assert!(validate_breaks(&[brk(240, 30), brk(120, 15)], 480).is_ok());
+        assert!(validate_breaks(&[brk(460, 30)], 480).is_err());
+        assert!(validate_breaks(&[brk(0, 0)], 480).is_err());
+        assert!(validate_breaks(&[brk(-5, 10)], 480).is_err());
+    }
+
+    #[test]
+    fn test_overlapping_breaks_are_rejected() {
+        assert!(validate_breaks(&[brk(120, 30), brk(140, 15)], 480).is_err());
+        assert!(validate_breaks(&[brk(120, 30), brk(150, 15)], 480).is_ok());
+    }
+}
@@ -1,8 +1,18 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc};
 use diesel::{Identifiable, Insertable, PgConnection, Queryable, RunQueryDsl};
+use diesel::result::{DatabaseErrorKind, Error::DatabaseError};
 use serde::{Deserialize, Serialize};
 use crate::constants;
 use crate::error::Error;
+use crate::models::approval_policies::{ApprovalPolicy, ChangeFacts};
+use crate::models::schedule::Schedule;
+use crate::models::shifts::{SHIFT_ON_CALL, SHIFT_WORK, Shift, ShiftWindow};
+use crate::models::skills::{Skill, SkillViolation};
+use crate::models::teams::Team;
+use crate::permissions::Permission;
+use crate::response::Page;
 use crate::schema::shift_changes;
+use crate::utils::{breaks_rest_rule, TokenClaims};
 use diesel::prelude::*;
 
 
@@ -11,30 +21,374 @@ pub struct ShiftChange {
     pub id: i32,
     pub scheduler_id : i32,
     pub reason: Option<String>,
-    pub status: Option<String>
+    pub status: Option<String>,
+    pub requested_by: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub swap_scheduler_id: Option<i32>,
+    pub counterpart_consent: bool,
+    pub approved_by_policy: Option<i32>,
+    pub organization_id: i32
 }
 
 #[derive(Debug, Serialize, Deserialize, Insertable)]
 #[diesel(table_name = shift_changes)]
 pub struct ShiftChangeDTO {
     pub scheduler_id : i32,
-    pub reason : Option<String>
+    pub reason : Option<String>,
+    // filled from the caller's token, never from the request body
+    #[serde(skip_deserializing)]
+    pub requested_by: Option<i32>,
+    // schedule entry of the colleague to swap with, if any
+    pub swap_scheduler_id: Option<i32>
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShiftChangeFilter {
+    pub status: Option<String>,
+    pub employee_id: Option<i32>,
+    pub requested_by: Option<i32>,
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub page_num: Option<i64>,
+    pub page_size: Option<i64>
 }
 
 impl ShiftChange {
-    pub fn new(shift_change_dto: ShiftChangeDTO, conn: &mut PgConnection) -> Result<String, Error> {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(shift_change_dto: ShiftChangeDTO, claims: &TokenClaims, conn: &mut PgConnection) -> Result<String, Error> {
+        use crate::schema::shift_changes::dsl::*;
+        let schedule = Schedule::find_by_id(shift_change_dto.scheduler_id, claims.org, conn)
+            .optional()?
+            .ok_or("Schedule entry not found")?;
+        Self::check_request_allowed(&schedule, claims, Utc::now().date_naive())?;
+        if let Some(swap_id) = shift_change_dto.swap_scheduler_id {
+            let swap_schedule = Schedule::find_by_id(swap_id, claims.org, conn)
+                .optional()?
+                .ok_or("Schedule entry to swap with not found")?;
+            if swap_schedule.employee_id == schedule.employee_id {
+                return Err("Cannot swap with your own schedule entry".into())
+            }
+            if swap_schedule.data < Utc::now().date_naive() {
+                return Err("Cannot swap with a past schedule entry".into())
+            }
+        }
+
+        let open_request = shift_changes
+            .filter(scheduler_id.eq(schedule.id))
+            .filter(status.eq(constants::SHIFT_CHANGE_PENDING))
+            .first::<ShiftChange>(conn)
+            .optional()?;
+        if open_request.is_some() {
+            return Err("An open change request already exists for this schedule entry".into())
+        }
+
+        let new_shift_change = ShiftChangeDTO {
+            requested_by: Some(claims.sub),
+            ..shift_change_dto
+        };
+        let shift_change = match diesel::insert_into(shift_changes).values((&new_shift_change, organization_id.eq(claims.org))).get_result::<ShiftChange>(conn) {
+            Ok(shift_change) => shift_change,
+            // lost a race against another request for the same entry
+            Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => return Err("An open change request already exists for this schedule entry".into()),
+            Err(_) => return Err(constants::DATABASE_INSERT_ERROR.into())
+        };
+
+        match Self::try_auto_approve(&shift_change, conn)? {
+            Some(policy) => Ok(format!("Shift change approved automatically by policy '{}'", policy.name)),
+            None => Ok(constants::DATABASE_INSERT_SUCCESS.to_string())
+        }
+    }
+
+    // The colleague whose entry is targeted by a swap agrees to it
+    pub fn consent(shift_change_id: i32, claims: &TokenClaims, conn: &mut PgConnection) -> Result<String, Error> {
+        use crate::schema::shift_changes::dsl::*;
+        let shift_change = shift_changes.find(shift_change_id).filter(organization_id.eq(claims.org)).first::<ShiftChange>(conn)
+            .optional()?
+            .ok_or("Shift change not found")?;
+        let swap_schedule = match shift_change.swap_scheduler_id {
+            Some(swap_id) => Schedule::find_by_id(swap_id, claims.org, conn)?,
+            None => return Err("This shift change is not a swap".into())
+        };
+        if swap_schedule.employee_id != claims.sub {
+            return Err("Only the colleague asked to swap can consent".into())
+        }
+        if shift_change.status.as_deref() != Some(constants::SHIFT_CHANGE_PENDING) {
+            return Err("This shift change is no longer pending".into())
+        }
+
+        let shift_change = diesel::update(shift_changes.find(shift_change_id))
+            .set(counterpart_consent.eq(true))
+            .get_result::<ShiftChange>(conn)?;
+        match Self::try_auto_approve(&shift_change, conn)? {
+            Some(policy) => Ok(format!("Shift change approved automatically by policy '{}'", policy.name)),
+            None => Ok(constants::DATABASE_UPDATE_SUCCESS.to_string())
+        }
+    }
+
+    /*
+        Approve the change with the first active policy it matches, if any.
+        Returns the policy that approved it.
+        Only swaps are checked against the rest and skill rules, every other change goes to a manager
+     */
+    fn try_auto_approve(shift_change: &ShiftChange, conn: &mut PgConnection) -> Result<Option<ApprovalPolicy>, Error> {
+        if shift_change.swap_scheduler_id.is_none() {
+            return Ok(None)
+        }
+        let policies = ApprovalPolicy::find_active(shift_change.organization_id, conn)?;
+        if policies.is_empty() {
+            return Ok(None)
+        }
+        let facts = Self::collect_facts(shift_change, conn)?;
+        match policies.into_iter().find(|policy| policy.matches(&facts)) {
+            Some(policy) => {
+                Self::approve(shift_change.id, Some(policy.id), conn)?;
+                Ok(Some(policy))
+            }
+            None => Ok(None)
+        }
+    }
+
+    fn collect_facts(shift_change: &ShiftChange, conn: &mut PgConnection) -> Result<ChangeFacts, Error> {
+        let org = shift_change.organization_id;
+        let schedule = Schedule::find_by_id(shift_change.scheduler_id, org, conn)?;
+        let shift = Shift::find_by_id(&schedule.shift_id, org, conn)?;
+        let swap_schedule = match shift_change.swap_scheduler_id {
+            Some(swap_id) => Some(Schedule::find_by_id(swap_id, org, conn)?),
+            None => None
+        };
+
+        let (starts_at, _) = shift.window().instants(schedule.data, Team::time_zone(schedule.team_id, org, conn)?);
+        let mut hours_ahead = (starts_at - Utc::now()).num_hours();
+        let mut violates_rules = false;
+        if let Some(swap_schedule) = &swap_schedule {
+            let swap_shift = Shift::find_by_id(&swap_schedule.shift_id, org, conn)?;
+            let (swap_starts_at, _) = swap_shift.window().instants(swap_schedule.data, Team::time_zone(swap_schedule.team_id, org, conn)?);
+            hours_ahead = hours_ahead.min((swap_starts_at - Utc::now()).num_hours());
+            violates_rules = Self::breaks_rules_for(swap_schedule.employee_id, &schedule, &shift, swap_schedule.id, conn)?
+                || Self::breaks_rules_for(schedule.employee_id, swap_schedule, &swap_shift, schedule.id, conn)?
+                || Self::swap_loses_skills(&schedule, swap_schedule, conn)?;
+        }
+
+        Ok(ChangeFacts {
+            same_shift_type: swap_schedule.as_ref().is_some_and(|swap| swap.shift_id == schedule.shift_id),
+            consent: swap_schedule.is_none() || shift_change.counterpart_consent,
+            violates_rules,
+            hours_ahead,
+        })
+    }
+
+    /*
+        Would `employee` break the rest rule by taking over `schedule`, once `given_away` is no longer theirs.
+        On-call duties don't take part in the rest rule
+     */
+    fn breaks_rules_for(employee: i32, schedule: &Schedule, shift: &Shift, given_away: i32, conn: &mut PgConnection) -> Result<bool, Error> {
+        use crate::schema::{schedules, shifts};
+        if shift.category == SHIFT_ON_CALL {
+            return Ok(false)
+        }
+        let neighbours = schedules::table
+            .inner_join(shifts::table)
+            .filter(shifts::category.eq(SHIFT_WORK))
+            .filter(schedules::organization_id.eq(schedule.organization_id))
+            .filter(schedules::employee_id.eq(employee))
+            .filter(schedules::id.ne(given_away))
+            .filter(schedules::data.between(schedule.data - Duration::days(2), schedule.data + Duration::days(2)))
+            .select((schedules::data, shifts::start_time, shifts::end_time, schedules::team_id))
+            .load::<(NaiveDate, NaiveTime, NaiveTime, Option<i32>)>(conn)?;
+        let mut windows = Vec::new();
+        for (day, start, end, team) in neighbours {
+            windows.push((day, ShiftWindow::new(start, end), Team::time_zone(team, schedule.organization_id, conn)?));
+        }
+        let zone = Team::time_zone(schedule.team_id, schedule.organization_id, conn)?;
+        Ok(breaks_rest_rule(schedule.data, shift.window(), zone, &windows))
+    }
+
+    // Would the swap leave the two shifts with fewer holders of the skills they require than before
+    fn swap_loses_skills(schedule: &Schedule, swap_schedule: &Schedule, conn: &mut PgConnection) -> Result<bool, Error> {
+        use crate::schema::schedules;
+        let org = schedule.organization_id;
+        let mut worked = schedules::table
+            .filter(schedules::organization_id.eq(org))
+            .filter(schedules::data.eq_any([schedule.data, swap_schedule.data]))
+            .filter(schedules::shift_id.eq_any([schedule.shift_id, swap_schedule.shift_id]))
+            .load::<Schedule>(conn)?;
+        let missing = |violations: Vec<SkillViolation>| violations.iter().map(|v| v.required - v.assigned).sum::<i32>();
+        let before = missing(Skill::violations(&worked, org, conn)?);
+        for entry in worked.iter_mut() {
+            if entry.id == schedule.id {
+                entry.employee_id = swap_schedule.employee_id;
+            } else if entry.id == swap_schedule.id {
+                entry.employee_id = schedule.employee_id;
+            }
+        }
+        Ok(missing(Skill::violations(&worked, org, conn)?) > before)
+    }
+
+    /*
+        Mark the change approved and carry out the swap, if it is one.
+        The update only hits a pending change, so a manager and a policy approving at the same time
+        can't both carry out the swap and undo it
+     */
+    fn approve(shift_change_id: i32, policy_id: Option<i32>, conn: &mut PgConnection) -> Result<ShiftChange, Error> {
         use crate::schema::shift_changes::dsl::*;
+        conn.transaction::<ShiftChange, diesel::result::Error, _>(|conn| {
+            // NotFound when the change is no longer pending, which rolls the transaction back
+            let shift_change = diesel::update(shift_changes.find(shift_change_id).filter(status.eq(constants::SHIFT_CHANGE_PENDING)))
+                .set((status.eq(constants::SHIFT_CHANGE_APPROVED), approved_by_policy.eq(policy_id)))
+                .get_result::<ShiftChange>(conn)?;
+            if let Some(swap_id) = shift_change.swap_scheduler_id {
+                use crate::schema::schedules;
+                let own = schedules::table.find(shift_change.scheduler_id).select(schedules::employee_id).first::<i32>(conn)?;
+                let other = schedules::table.find(swap_id).select(schedules::employee_id).first::<i32>(conn)?;
+                diesel::update(schedules::table.find(shift_change.scheduler_id)).set(schedules::employee_id.eq(other)).execute(conn)?;
+                diesel::update(schedules::table.find(swap_id)).set(schedules::employee_id.eq(own)).execute(conn)?;
+            }
+            Ok(shift_change)
+        }).map_err(|err| match err {
+            diesel::result::Error::NotFound => "This shift change is no longer pending".into(),
+            _ => constants::DATABASE_UPDATE_ERROR.into()
+        })
+    }
 
-        diesel::insert_into(shift_changes).values(&shift_change_dto).execute(conn).expect(constants::DATABASE_INSERT_ERROR);
-        Ok(constants::DATABASE_INSERT_SUCCESS.to_string())
+    /*
+        Only the owner of the schedule entry or a manager can file a change,
+        and only for entries that are not in the past
+     */
+    fn check_request_allowed(schedule: &Schedule, claims: &TokenClaims, today: NaiveDate) -> Result<(), Error> {
+        if schedule.employee_id != claims.sub && !claims.can(Permission::FileForOthers) {
+            return Err("You can only request changes for your own schedule".into())
+        }
+        if schedule.data < today {
+            return Err("Cannot request a change for a past date".into())
+        }
+        Ok(())
     }
 
-    pub fn verify_change(shift_change_id: i32, conn: &mut PgConnection) -> Result<String ,Error> {
+    pub fn verify_change(shift_change_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<String ,Error> {
         use crate::schema::shift_changes::dsl::*;
 
-        let _shift_change = diesel::update(shift_changes.find(shift_change_id)).set(status.eq("Ok")).get_result::<ShiftChange>(conn).expect(constants::DATABASE_UPDATE_ERROR);
+        let shift_change = shift_changes.find(shift_change_id).filter(organization_id.eq(_organization_id)).first::<ShiftChange>(conn)
+            .optional()?
+            .ok_or("Shift change not found")?;
+        if shift_change.status.as_deref() != Some(constants::SHIFT_CHANGE_PENDING) {
+            return Err("This shift change is no longer pending".into())
+        }
+        Self::approve(shift_change.id, None, conn)?;
+
+        Ok(constants::DATABASE_UPDATE_SUCCESS.to_string())
+    }
+
+    /*
+        Employees only see requests on their own schedule entries, filed by themselves
+        or asking them to swap, managers also the requests on the entries of the teams they manage.
+        ViewAllDepartments sees every request
+     */
+    pub fn find_all(filter: ShiftChangeFilter, claims: &TokenClaims, conn: &mut PgConnection) -> Result<Page<ShiftChange>, Error> {
+        use crate::schema::{schedules, shift_changes};
+
+        let page_num = filter.page_num.unwrap_or(constants::DEFAULT_PAGE_NUM).max(1);
+        let page_size = filter.page_size.unwrap_or(constants::DEFAULT_PER_PAGE).clamp(1, 100);
+        // None when the caller sees every request, otherwise whose requests they see besides their own
+        let managed = if claims.can(Permission::ViewAllDepartments) {
+            None
+        } else if claims.can(Permission::ViewTeam) {
+            Some(Team::managed_member_ids(claims.sub, claims.org, conn)?)
+        } else {
+            Some(Vec::new())
+        };
+
+        let swap_requests = shift_changes::table
+            .filter(shift_changes::organization_id.eq(claims.org))
+            .filter(shift_changes::swap_scheduler_id.eq_any(
+                schedules::table.filter(schedules::employee_id.eq(claims.sub)).select(schedules::id.nullable())
+            ))
+            .select(shift_changes::id)
+            .load::<i32>(conn)?;
+
+        let build_query = || {
+            let mut query = shift_changes::table
+                .inner_join(schedules::table)
+                .filter(shift_changes::organization_id.eq(claims.org))
+                .select(shift_changes::all_columns)
+                .into_boxed();
+            if let Some(managed) = &managed {
+                query = query.filter(
+                    schedules::employee_id.eq(claims.sub)
+                        .or(shift_changes::requested_by.eq(claims.sub))
+                        .or(shift_changes::id.eq_any(swap_requests.clone()))
+                        .or(schedules::employee_id.eq_any(managed.clone()))
+                );
+            }
+            if let Some(_status) = &filter.status {
+                query = query.filter(shift_changes::status.eq(_status.clone()));
+            }
+            if let Some(_employee_id) = filter.employee_id {
+                query = query.filter(schedules::employee_id.eq(_employee_id));
+            }
+            if let Some(_requested_by) = filter.requested_by {
+                query = query.filter(shift_changes::requested_by.eq(_requested_by));
+            }
+            if let Some(_from) = filter.from {
+                query = query.filter(schedules::data.ge(_from));
+            }
+            if let Some(_to) = filter.to {
+                query = query.filter(schedules::data.le(_to));
+            }
+            query
+        };
+
+        let total_elements = build_query().count().get_result::<i64>(conn)?;
+        let data = build_query()
+            .order_by(shift_changes::created_at.desc())
+            .limit(page_size)
+            .offset((page_num - 1) * page_size)
+            .load::<ShiftChange>(conn)?;
+
+        Ok(Page::new(constants::MESSAGE_OK, data, page_num, page_size, total_elements))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+    use crate::models::schedule::Schedule;
+    use crate::models::shift_changes::ShiftChange;
+    use crate::utils::TokenClaims;
+
+    fn schedule(employee_id: i32, day: u32) -> Schedule {
+        Schedule {
+            id: 1,
+            employee_id,
+            data: NaiveDate::from_ymd_opt(2024, 5, day).unwrap(),
+            shift_id: 1,
+            note: None,
+            team_id: None,
+            organization_id: 1,
+        }
+    }
+
+    fn claims(sub: i32, role: &str) -> TokenClaims {
+        TokenClaims { sub, role: role.to_string(), org: 1, sid: 0, iat: 0, exp: 0 }
+    }
 
-        // delete schedule when verify change ( service module will be created later to seperate logic)
-        Ok(constants::DATABASE_INSERT_SUCCESS.to_string())
+    #[test]
+    fn test_owner_can_request_change() {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 10).unwrap();
+        assert!(ShiftChange::check_request_allowed(&schedule(7, 10), &claims(7, "Employee"), today).is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_other_employee_cannot_request_change() {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 10).unwrap();
+        assert!(ShiftChange::check_request_allowed(&schedule(7, 12), &claims(8, "Employee"), today).is_err());
+        assert!(ShiftChange::check_request_allowed(&schedule(7, 12), &claims(1, "Manager"), today).is_ok());
+    }
+
+    #[test]
+    fn test_past_date_is_rejected() {
+        let today = NaiveDate::from_ymd_opt(2024, 5, 10).unwrap();
+        assert!(ShiftChange::check_request_allowed(&schedule(7, 9), &claims(7, "Employee"), today).is_err());
+        assert!(ShiftChange::check_request_allowed(&schedule(7, 9), &claims(1, "Manager"), today).is_err());
+    }
+}
@@ -1,45 +1,371 @@
-use diesel::{Identifiable, Insertable, PgConnection, Queryable, QueryResult, RunQueryDsl};
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use diesel::{AsChangeset, Identifiable, Insertable, PgConnection, Queryable, QueryResult, RunQueryDsl};
+use diesel::result::{DatabaseErrorKind, Error::DatabaseError};
 use serde::{Deserialize, Serialize};
-use crate::constants;
+use crate::error::Error;
+use crate::models::leave_requests::LEAVE_TYPES;
+use crate::models::shift_breaks::ShiftBreak;
 use crate::schema::shifts;
 use diesel::prelude::*;
 
 
+// Work shifts fill the slots of the generator (see models::teams::SHIFT_SLOTS),
+// on-call shifts are standby duties rostered on top of them
+pub const SHIFT_WORK: &str = "work";
+pub const SHIFT_ON_CALL: &str = "on_call";
+
 #[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Clone)]
 #[diesel(table_name = shifts)]
 pub struct Shift {
     pub id : i32,
     pub name: String,
-    pub start_time: i32 ,
-    pub end_time: i32,
-    pub duration: Option<i32>,
-    pub minium_attendences: Option<i32>
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub minium_attendences: Option<i32>,
+    pub organization_id: i32,
+    // set once the shift is retired, schedules keep pointing at it
+    pub archived_at: Option<NaiveDateTime>,
+    // both derived by the database from the times
+    pub duration_minutes: i32,
+    pub crosses_midnight: bool,
+    pub category: String
 }
 
-#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[derive(Debug, Serialize, Deserialize, Insertable, AsChangeset)]
 #[diesel(table_name = shifts)]
 pub struct ShiftDTO {
     pub name: String,
-    pub start_time: i32,
-    pub end_time: i32,
-    pub duration: Option<i32>,
-    pub minium_attendences: Option<i32>
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    pub minium_attendences: Option<i32>,
+    #[serde(default = "default_category")]
+    pub category: String
+}
+
+fn default_category() -> String {
+    SHIFT_WORK.to_string()
+}
+
+/*
+    Where a shift sits in the day. When the end is not after the start the shift ends the next day,
+    so D from 22:00 to 06:00 lasts 8 hours
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShiftWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+    // see models::shift_breaks
+    pub unpaid_break_minutes: i64
+}
+
+impl ShiftWindow {
+    pub fn new(start: NaiveTime, end: NaiveTime) -> Self {
+        ShiftWindow { start, end, unpaid_break_minutes: 0 }
+    }
+
+    pub fn with_unpaid_breaks(self, unpaid_break_minutes: i64) -> Self {
+        ShiftWindow { unpaid_break_minutes, ..self }
+    }
+
+    pub fn crosses_midnight(&self) -> bool {
+        self.end <= self.start
+    }
+
+    pub fn duration(&self) -> Duration {
+        match self.crosses_midnight() {
+            true => self.end - self.start + Duration::days(1),
+            false => self.end - self.start
+        }
+    }
+
+    // Start and end of the shift worked on `date`
+    pub fn starts_at(&self, date: NaiveDate) -> NaiveDateTime {
+        date.and_time(self.start)
+    }
+
+    pub fn ends_at(&self, date: NaiveDate) -> NaiveDateTime {
+        self.starts_at(date) + self.duration()
+    }
+
+    /*
+        Real start and end of the shift worked on `date`, the times being wall-clock times of `tz`.
+        On DST changeover nights a shift from 22:00 to 06:00 lasts 7 or 9 hours
+     */
+    pub fn instants(&self, date: NaiveDate, tz: Tz) -> (DateTime<Utc>, DateTime<Utc>) {
+        let end_date = if self.crosses_midnight() { date + Duration::days(1) } else { date };
+        (local_instant(tz, date.and_time(self.start)), local_instant(tz, end_date.and_time(self.end)))
+    }
+
+    // Hours actually worked on `date`: the real length of the shift without its unpaid breaks
+    pub fn net_duration(&self, date: NaiveDate, tz: Tz) -> Duration {
+        let (start, end) = self.instants(date, tz);
+        end - start - Duration::minutes(self.unpaid_break_minutes)
+    }
+}
+
+/*
+    A wall-clock time skipped when clocks go forward is read with the offset from before the change,
+    one repeated when they go back is taken the first time
+ */
+fn local_instant(tz: Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    match tz.from_local_datetime(&local) {
+        LocalResult::Single(at) | LocalResult::Ambiguous(at, _) => at.with_timezone(&Utc),
+        LocalResult::None => local_instant(tz, local - Duration::hours(1)) + Duration::hours(1)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ShiftFilter {
+    #[serde(default)]
+    pub include_archived: bool
+}
+
+impl ShiftDTO {
+    /*
+        The name is the code shown in schedules and exports, so it can't be "N" or a leave code.
+        Times are given to the minute ("06:00"), an end at or before the start is on the next day.
+        A shift can't start and end at the same time
+     */
+    fn validate(self) -> Result<ShiftDTO, Error> {
+        let name = self.name.trim().to_string();
+        if name.is_empty() {
+            return Err("Shift name is required".into())
+        }
+        if name == "N" || LEAVE_TYPES.iter().any(|(_, code)| *code == name) {
+            return Err(format!("'{}' is reserved and can't be used as a shift name", name).into())
+        }
+        if [self.start_time, self.end_time].iter().any(|time| time.second() != 0 || time.nanosecond() != 0) {
+            return Err("Start and end time are given to the minute".into())
+        }
+        if self.end_time == self.start_time {
+            return Err("End time must differ from start time".into())
+        }
+        if self.minium_attendences.is_some_and(|min| min < 0) {
+            return Err("Minimum attendance must not be negative".into())
+        }
+        if ![SHIFT_WORK, SHIFT_ON_CALL].contains(&self.category.as_str()) {
+            return Err(format!("Unknown shift category '{}', expected {} or {}", self.category, SHIFT_WORK, SHIFT_ON_CALL).into())
+        }
+        Ok(ShiftDTO { name, ..self })
+    }
 }
 
 impl Shift {
-    pub fn new(shift_dto: ShiftDTO, conn: &mut PgConnection) -> Result<String, String> {
+    // Times of the shift, breaks are added by the callers that count hours
+    pub fn window(&self) -> ShiftWindow {
+        ShiftWindow::new(self.start_time, self.end_time)
+    }
+
+    pub fn new(shift_dto: ShiftDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<Shift, Error> {
+        use crate::schema::shifts::dsl::*;
+        let shift_dto = shift_dto.validate()?;
+        Self::check_unique(&shift_dto.name, None, _organization_id, conn)?;
+        let rs = diesel::insert_into(shifts)
+            .values((&shift_dto, organization_id.eq(_organization_id)))
+            .get_result::<Shift>(conn);
+        Self::map_unique_violation(rs, &shift_dto.name)
+    }
+
+    pub fn update(_id: i32, shift_dto: ShiftDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<Shift, Error> {
+        use crate::schema::shifts::dsl::*;
+        let shift = Self::find(_id, _organization_id, conn)?;
+        if shift.archived_at.is_some() {
+            return Err("Archived shifts can't be changed".into())
+        }
+        let shift_dto = shift_dto.validate()?;
+        Self::check_unique(&shift_dto.name, Some(_id), _organization_id, conn)?;
+        // the times and hours of past schedules stay as they were worked
+        let scheduled = Self::scheduled_count(_id, conn)?;
+        if scheduled > 0 && (shift_dto.start_time != shift.start_time || shift_dto.end_time != shift.end_time) {
+            return Err(format!("Shift '{}' is used by {} schedule(s) and its times can't change, archive it and create a new one", shift.name, scheduled).into())
+        }
+        if scheduled > 0 && shift_dto.category != shift.category {
+            return Err(format!("Shift '{}' is used by {} schedule(s) and its category can't change, archive it and create a new one", shift.name, scheduled).into())
+        }
+        if shift_dto.category != SHIFT_WORK {
+            use crate::schema::team_shifts;
+            if team_shifts::table.filter(team_shifts::shift_id.eq(_id)).count().get_result::<i64>(conn)? > 0 {
+                return Err("Shifts in a team's catalogue have to stay work shifts".into())
+            }
+        }
+        let duration = ShiftWindow::new(shift_dto.start_time, shift_dto.end_time).duration().num_minutes();
+        if ShiftBreak::latest_end(_id, conn)? as i64 > duration {
+            return Err("The breaks of the shift don't fit in the new times, change them first".into())
+        }
+        let rs = diesel::update(shifts.find(_id)).set(&shift_dto).get_result::<Shift>(conn);
+        Self::map_unique_violation(rs, &shift_dto.name)
+    }
+
+    fn scheduled_count(_id: i32, conn: &mut PgConnection) -> Result<i64, Error> {
+        use crate::schema::schedules;
+        Ok(schedules::table.filter(schedules::shift_id.eq(_id)).count().get_result::<i64>(conn)?)
+    }
+
+    fn check_unique(_name: &str, except: Option<i32>, _organization_id: i32, conn: &mut PgConnection) -> Result<(), Error> {
+        use crate::schema::shifts::dsl::*;
+        let mut query = shifts
+            .filter(organization_id.eq(_organization_id))
+            .filter(name.eq(_name))
+            .filter(archived_at.is_null())
+            .into_boxed();
+        if let Some(except) = except {
+            query = query.filter(id.ne(except));
+        }
+        if query.count().get_result::<i64>(conn)? > 0 {
+            return Err(format!("Shift '{}' already exists", _name).into())
+        }
+        Ok(())
+    }
+
+    // lost a race against another request creating the same code
+    fn map_unique_violation(rs: QueryResult<Shift>, _name: &str) -> Result<Shift, Error> {
+        match rs {
+            Ok(shift) => Ok(shift),
+            Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => Err(format!("Shift '{}' already exists", _name).into()),
+            Err(err) => Err(err.into())
+        }
+    }
+
+    /*
+        Shifts referenced by schedules are archived so the history stays readable,
+        the others are deleted. A shift in a team's catalogue has to be replaced there first
+     */
+    pub fn delete(_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<String, Error> {
+        use crate::schema::team_shifts;
         use crate::schema::shifts::dsl::*;
-        let new_shift = shift_dto;
-        let rs = diesel::insert_into(shifts).values(new_shift).execute(conn);
-        if rs.is_err() {
-            return Err(constants::DATABASE_INSERT_ERROR.to_string())
-        } else {
-            Ok(constants::DATABASE_INSERT_SUCCESS.to_string())
+        let shift = Self::find(_id, _organization_id, conn)?;
+        if shift.archived_at.is_some() {
+            return Err("Shift is already archived".into())
+        }
+        let in_catalogues = team_shifts::table.filter(team_shifts::shift_id.eq(_id)).count().get_result::<i64>(conn)?;
+        if in_catalogues > 0 {
+            return Err(format!("Shift '{}' is used by {} team catalogue(s), replace it there first", shift.name, in_catalogues).into())
+        }
+        let scheduled = Self::scheduled_count(_id, conn)?;
+        if scheduled > 0 {
+            diesel::update(shifts.find(_id)).set(archived_at.eq(Utc::now().naive_utc())).execute(conn)?;
+            return Ok(format!("Shift '{}' is used by {} schedule(s) and was archived", shift.name, scheduled))
+        }
+        diesel::delete(shifts.find(_id)).execute(conn)?;
+        Ok(format!("Shift '{}' deleted", shift.name))
+    }
+
+    // Archived shifts are still found, past schedules refer to them
+    pub fn find_by_id(_id: &i32, _organization_id: i32, conn: &mut PgConnection) -> QueryResult<Shift> {
+        use crate::schema::shifts::dsl::*;
+        shifts.find(_id).filter(organization_id.eq(_organization_id)).get_result::<Shift>(conn)
+    }
+
+    pub fn find(_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<Shift, Error> {
+        Self::find_by_id(&_id, _organization_id, conn)
+            .optional()?
+            .ok_or_else(|| "Shift not found".into())
+    }
+
+    // A shift that can still be put on new schedules
+    pub fn find_active(_id: &i32, _organization_id: i32, conn: &mut PgConnection) -> Result<Shift, Error> {
+        match Self::find_by_id(_id, _organization_id, conn).optional()? {
+            Some(shift) if shift.archived_at.is_none() => Ok(shift),
+            Some(shift) => Err(format!("Shift '{}' is archived", shift.name).into()),
+            None => Err(format!("Shift {} not found", _id).into())
         }
     }
 
-    pub fn find_by_id(_id: &i32, conn: &mut PgConnection) -> QueryResult<Shift> {
+    pub fn find_all(filter: ShiftFilter, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<Shift>, Error> {
         use crate::schema::shifts::dsl::*;
-        shifts.find(_id).get_result::<Shift>(conn)
+        let mut query = shifts.filter(organization_id.eq(_organization_id)).into_boxed();
+        if !filter.include_archived {
+            query = query.filter(archived_at.is_null());
+        }
+        Ok(query.order_by(id).load::<Shift>(conn)?)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveTime};
+    use chrono_tz::Tz;
+    use crate::models::shifts::{SHIFT_ON_CALL, SHIFT_WORK, ShiftDTO, ShiftWindow};
+
+    fn time(value: &str) -> NaiveTime {
+        NaiveTime::parse_from_str(value, "%H:%M:%S").unwrap()
+    }
+
+    fn shift(name: &str, start_time: &str, end_time: &str) -> ShiftDTO {
+        ShiftDTO { name: name.to_string(), start_time: time(start_time), end_time: time(end_time), minium_attendences: Some(1), category: SHIFT_WORK.to_string() }
+    }
+
+    #[test]
+    fn test_name_is_trimmed() {
+        let shift = shift(" S ", "06:00:00", "14:00:00").validate().unwrap();
+        assert_eq!(shift.name, "S");
+    }
+
+    #[test]
+    fn test_invalid_windows_are_rejected() {
+        assert!(shift("S", "08:00:00", "08:00:00").validate().is_err());
+        assert!(shift("S", "06:00:30", "14:00:00").validate().is_err());
+        assert!(shift("S", "06:15:00", "14:45:00").validate().is_ok());
+        assert!(shift("D", "22:00:00", "06:00:00").validate().is_ok());
+    }
+
+    #[test]
+    fn test_reserved_codes_are_rejected() {
+        assert!(shift("N", "06:00:00", "14:00:00").validate().is_err());
+        assert!(shift("SL", "06:00:00", "14:00:00").validate().is_err());
+        assert!(shift("", "06:00:00", "14:00:00").validate().is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_shift_categories() {
+        let standby = ShiftDTO { category: SHIFT_ON_CALL.to_string(), ..shift("OC", "18:00:00", "08:00:00") };
+        assert!(standby.validate().is_ok());
+        let unknown = ShiftDTO { category: "overtime".to_string(), ..shift("OT", "18:00:00", "22:00:00") };
+        assert!(unknown.validate().is_err());
+    }
+
+    #[test]
+    fn test_night_shift_ends_next_day() {
+        let night = ShiftWindow::new(time("22:00:00"), time("06:00:00"));
+        let date = NaiveDate::from_ymd_opt(2024, 1, 31).unwrap();
+        assert!(night.crosses_midnight());
+        assert_eq!(night.duration().num_minutes(), 480);
+        assert_eq!(night.ends_at(date), NaiveDate::from_ymd_opt(2024, 2, 1).unwrap().and_time(time("06:00:00")));
+
+        let evening = ShiftWindow::new(time("14:00:00"), time("00:00:00"));
+        assert_eq!(evening.duration().num_minutes(), 600);
+    }
+
+    #[test]
+    fn test_night_shifts_on_dst_changeover() {
+        let night = ShiftWindow::new(time("22:00:00"), time("06:00:00"));
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        // clocks go forward on the night of 30 to 31 March 2024 and back on 26 to 27 October
+        let (start, end) = night.instants(NaiveDate::from_ymd_opt(2024, 3, 30).unwrap(), berlin);
+        assert_eq!((end - start).num_hours(), 7);
+        let (start, end) = night.instants(NaiveDate::from_ymd_opt(2024, 10, 26).unwrap(), berlin);
+        assert_eq!((end - start).num_hours(), 9);
+        let (start, end) = night.instants(NaiveDate::from_ymd_opt(2024, 6, 1).unwrap(), berlin);
+        assert_eq!((end - start).num_hours(), 8);
+        assert_eq!(start.to_rfc3339(), "2024-06-01T20:00:00+00:00");
+    }
+
+    #[test]
+    fn test_skipped_time_is_moved_past_the_gap() {
+        let early = ShiftWindow::new(time("02:30:00"), time("10:30:00"));
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let (start, end) = early.instants(NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(), berlin);
+        assert_eq!(start.to_rfc3339(), "2024-03-31T01:30:00+00:00");
+        assert_eq!((end - start).num_hours(), 7);
+    }
+
+    #[test]
+    fn test_net_duration_leaves_out_unpaid_breaks() {
+        let night = ShiftWindow::new(time("22:00:00"), time("06:00:00")).with_unpaid_breaks(45);
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        assert_eq!(night.net_duration(NaiveDate::from_ymd_opt(2024, 6, 1).unwrap(), berlin).num_minutes(), 435);
+        assert_eq!(night.net_duration(NaiveDate::from_ymd_opt(2024, 10, 26).unwrap(), berlin).num_minutes(), 495);
+    }
+}
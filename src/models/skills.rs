@@ -0,0 +1,391 @@
+use std::collections::{BTreeMap, HashMap};
+use chrono::{Datelike, NaiveDate};
+use diesel::{Insertable, PgConnection, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use crate::error::Error;
+use crate::models::employee::Employee;
+use crate::models::schedule::Schedule;
+use crate::models::shifts::Shift;
+use crate::models::teams::{SHIFT_SLOTS, ShiftCatalogue};
+use crate::schema::{employee_skills, skill_requirements, skills};
+use diesel::prelude::*;
+
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Clone)]
+#[diesel(table_name = skills)]
+pub struct Skill {
+    pub id: i32,
+    pub organization_id: i32,
+    pub name: String
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SkillDTO {
+    pub name: String
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmployeeSkillsDTO {
+    pub skills: Vec<i32>
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Clone)]
+#[diesel(table_name = skill_requirements)]
+pub struct SkillRequirement {
+    pub id: i32,
+    pub shift_id: i32,
+    pub skill_id: i32,
+    pub min_count: i32,
+    // only on that date, where it replaces the requirement of every day
+    pub date: Option<NaiveDate>
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SkillRequirementDTO {
+    pub skill_id: i32,
+    pub min_count: i32,
+    #[serde(default)]
+    pub date: Option<NaiveDate>
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = skill_requirements)]
+struct NewSkillRequirement {
+    shift_id: i32,
+    skill_id: i32,
+    min_count: i32,
+    date: Option<NaiveDate>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SkillRequirementsDTO {
+    pub requirements: Vec<SkillRequirementDTO>
+}
+
+// A shift worked by fewer holders of a skill than it requires
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SkillViolation {
+    pub date: NaiveDate,
+    pub shift_id: i32,
+    pub team_id: Option<i32>,
+    pub skill_id: i32,
+    pub skill: String,
+    pub required: i32,
+    pub assigned: i32
+}
+
+/*
+    Skills of the employees and what each slot of the generator needs, see utils::create_sample_schedule.
+    A requirement of a day replaces the one of every day for the same skill
+ */
+#[derive(Debug, Default)]
+pub struct SkillRules {
+    pub employee_skills: HashMap<i32, Vec<i32>>,
+    // slot -> skill -> minimum count
+    pub every_day: HashMap<String, BTreeMap<i32, i32>>,
+    // (day of month, slot) -> skill -> minimum count
+    pub on_day: HashMap<(i32, String), BTreeMap<i32, i32>>
+}
+
+impl SkillRules {
+    pub fn has_skill(&self, employee: i32, skill: i32) -> bool {
+        self.employee_skills.get(&employee).is_some_and(|skills| skills.contains(&skill))
+    }
+
+    // Skill -> minimum count of a slot on a day, by skill id
+    pub fn required(&self, day: i32, slot: &str) -> Vec<(i32, i32)> {
+        let mut required = self.every_day.get(slot).cloned().unwrap_or_default();
+        if let Some(on_day) = self.on_day.get(&(day, slot.to_string())) {
+            required.extend(on_day);
+        }
+        required.into_iter().filter(|(_, count)| *count > 0).collect()
+    }
+
+    // (skill, required, assigned) for each skill `employees` lack to work the slot on a day
+    pub fn shortfalls(&self, day: i32, slot: &str, employees: &[i32]) -> Vec<(i32, i32, i32)> {
+        self.required(day, slot).into_iter()
+            .map(|(skill, count)| (skill, count, employees.iter().filter(|e| self.has_skill(**e, skill)).count() as i32))
+            .filter(|(_, count, assigned)| assigned < count)
+            .collect()
+    }
+
+    // Whether a skill of the employee is required by one of `slots` that day
+    pub fn needed_in(&self, employee: i32, day: i32, slots: &[&str]) -> bool {
+        slots.iter().any(|slot| self.required(day, slot).iter().any(|(skill, _)| self.has_skill(employee, *skill)))
+    }
+
+    // Rules of the slots of a catalogue in a month, for `employees`
+    pub fn load(catalogue: &ShiftCatalogue, employees: &[i32], month: i32, year: i32, conn: &mut PgConnection) -> Result<SkillRules, Error> {
+        let mut rules = SkillRules::default();
+        for (employee, skill) in employee_skills::table
+            .filter(employee_skills::employee_id.eq_any(employees))
+            .select((employee_skills::employee_id, employee_skills::skill_id))
+            .load::<(i32, i32)>(conn)? {
+            rules.employee_skills.entry(employee).or_default().push(skill);
+        }
+        for slot in SHIFT_SLOTS {
+            let Some(_shift_id) = catalogue.shift_id(slot) else { continue };
+            let requirements = skill_requirements::table
+                .filter(skill_requirements::shift_id.eq(_shift_id))
+                .load::<SkillRequirement>(conn)?;
+            for requirement in requirements {
+                match requirement.date {
+                    None => {
+                        rules.every_day.entry(slot.to_string()).or_default().insert(requirement.skill_id, requirement.min_count);
+                    }
+                    Some(_date) if _date.month() as i32 == month && _date.year() == year => {
+                        rules.on_day.entry((_date.day() as i32, slot.to_string())).or_default().insert(requirement.skill_id, requirement.min_count);
+                    }
+                    Some(_) => {}
+                }
+            }
+        }
+        Ok(rules)
+    }
+}
+
+impl Skill {
+    pub fn new(skill_dto: SkillDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<Skill, Error> {
+        let name = Self::check_name(&skill_dto, None, _organization_id, conn)?;
+        Ok(diesel::insert_into(skills::table)
+            .values((skills::name.eq(name), skills::organization_id.eq(_organization_id)))
+            .get_result::<Skill>(conn)?)
+    }
+
+    pub fn update(_id: i32, skill_dto: SkillDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<Skill, Error> {
+        Self::find_by_id(_id, _organization_id, conn)?;
+        let name = Self::check_name(&skill_dto, Some(_id), _organization_id, conn)?;
+        Ok(diesel::update(skills::table.find(_id)).set(skills::name.eq(name)).get_result::<Skill>(conn)?)
+    }
+
+    // Employees lose the skill and shifts stop requiring it
+    pub fn delete(_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<String, Error> {
+        let skill = Self::find_by_id(_id, _organization_id, conn)?;
+        diesel::delete(skills::table.find(_id)).execute(conn)?;
+        Ok(format!("Skill '{}' deleted", skill.name))
+    }
+
+    fn check_name(skill_dto: &SkillDTO, except: Option<i32>, _organization_id: i32, conn: &mut PgConnection) -> Result<String, Error> {
+        let name = skill_dto.name.trim();
+        if name.is_empty() {
+            return Err("Skill name is required".into())
+        }
+        let taken = skills::table
+            .filter(skills::organization_id.eq(_organization_id))
+            .filter(skills::name.eq(name))
+            .filter(skills::id.ne(except.unwrap_or(0)))
+            .count()
+            .get_result::<i64>(conn)?;
+        if taken > 0 {
+            return Err(format!("Skill '{}' already exists", name).into())
+        }
+        Ok(name.to_string())
+    }
+
+    pub fn find_by_id(_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<Skill, Error> {
+        skills::table.find(_id).filter(skills::organization_id.eq(_organization_id)).first::<Skill>(conn)
+            .optional()?
+            .ok_or_else(|| "Skill not found".into())
+    }
+
+    pub fn find_all(_organization_id: i32, conn: &mut PgConnection) -> Result<Vec<Skill>, Error> {
+        Ok(skills::table.filter(skills::organization_id.eq(_organization_id)).order_by(skills::name).load::<Skill>(conn)?)
+    }
+
+    pub fn find_by_employee(_employee_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<Skill>, Error> {
+        Employee::find_by_id(_employee_id, _organization_id, conn).map_err(|_| "Employee not found")?;
+        Ok(skills::table
+            .inner_join(employee_skills::table)
+            .filter(employee_skills::employee_id.eq(_employee_id))
+            .select((skills::id, skills::organization_id, skills::name))
+            .order_by(skills::name)
+            .load::<Skill>(conn)?)
+    }
+
+    // Replaces the skills of an employee
+    pub fn set_for_employee(_employee_id: i32, skills_dto: EmployeeSkillsDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<Skill>, Error> {
+        Employee::find_by_id(_employee_id, _organization_id, conn).map_err(|_| "Employee not found")?;
+        let mut held = skills_dto.skills;
+        held.sort();
+        held.dedup();
+        Self::check_exist(&held, _organization_id, conn)?;
+
+        conn.transaction::<(), Error, _>(|conn| {
+            diesel::delete(employee_skills::table.filter(employee_skills::employee_id.eq(_employee_id))).execute(conn)?;
+            let rows: Vec<_> = held.iter()
+                .map(|skill| (employee_skills::employee_id.eq(_employee_id), employee_skills::skill_id.eq(*skill)))
+                .collect();
+            if !rows.is_empty() {
+                diesel::insert_into(employee_skills::table).values(&rows).execute(conn)?;
+            }
+            Ok(())
+        })?;
+        Self::find_by_employee(_employee_id, _organization_id, conn)
+    }
+
+    fn check_exist(ids: &[i32], _organization_id: i32, conn: &mut PgConnection) -> Result<(), Error> {
+        let mut ids = ids.to_vec();
+        ids.sort();
+        ids.dedup();
+        let found = skills::table
+            .filter(skills::id.eq_any(&ids))
+            .filter(skills::organization_id.eq(_organization_id))
+            .count()
+            .get_result::<i64>(conn)?;
+        if found != ids.len() as i64 {
+            return Err("Unknown skill".into())
+        }
+        Ok(())
+    }
+
+    /*
+        Shortfalls of the shifts worked in `schedules`, each day of a shift of a team on its own.
+        Only shifts that are worked that day are checked
+     */
+    pub fn violations(schedules_in_range: &[Schedule], _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<SkillViolation>, Error> {
+        let mut worked: BTreeMap<(NaiveDate, i32, Option<i32>), Vec<i32>> = BTreeMap::new();
+        for schedule in schedules_in_range {
+            worked.entry((schedule.data, schedule.shift_id, schedule.team_id)).or_default().push(schedule.employee_id);
+        }
+        let shift_ids: Vec<i32> = worked.keys().map(|(_, shift, _)| *shift).collect();
+        let requirements = skill_requirements::table
+            .filter(skill_requirements::shift_id.eq_any(&shift_ids))
+            .load::<SkillRequirement>(conn)?;
+        if requirements.is_empty() {
+            return Ok(vec![])
+        }
+        let employee_ids: Vec<i32> = schedules_in_range.iter().map(|s| s.employee_id).collect();
+        let held = employee_skills::table
+            .filter(employee_skills::employee_id.eq_any(&employee_ids))
+            .select((employee_skills::employee_id, employee_skills::skill_id))
+            .load::<(i32, i32)>(conn)?;
+        let names: HashMap<i32, String> = Self::find_all(_organization_id, conn)?.into_iter().map(|skill| (skill.id, skill.name)).collect();
+
+        let mut violations = Vec::new();
+        for ((_date, _shift_id, _team_id), employees) in worked {
+            let mut required: BTreeMap<i32, i32> = BTreeMap::new();
+            for requirement in requirements.iter().filter(|r| r.shift_id == _shift_id && r.date.is_none()) {
+                required.insert(requirement.skill_id, requirement.min_count);
+            }
+            for requirement in requirements.iter().filter(|r| r.shift_id == _shift_id && r.date == Some(_date)) {
+                required.insert(requirement.skill_id, requirement.min_count);
+            }
+            for (skill, count) in required {
+                let assigned = employees.iter().filter(|e| held.contains(&(**e, skill))).count() as i32;
+                if assigned < count {
+                    violations.push(SkillViolation {
+                        date: _date,
+                        shift_id: _shift_id,
+                        team_id: _team_id,
+                        skill_id: skill,
+                        skill: names.get(&skill).cloned().unwrap_or_default(),
+                        required: count,
+                        assigned,
+                    });
+                }
+            }
+        }
+        Ok(violations)
+    }
+}
+
+impl SkillRequirement {
+    pub fn find_by_shift(_shift_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<SkillRequirement>, Error> {
+        Shift::find(_shift_id, _organization_id, conn)?;
+        Ok(skill_requirements::table
+            .filter(skill_requirements::shift_id.eq(_shift_id))
+            .order_by((skill_requirements::date.asc().nulls_first(), skill_requirements::skill_id))
+            .load::<SkillRequirement>(conn)?)
+    }
+
+    // Replaces the skills a shift requires
+    pub fn set_for_shift(_shift_id: i32, requirements_dto: SkillRequirementsDTO, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<SkillRequirement>, Error> {
+        let shift = Shift::find(_shift_id, _organization_id, conn)?;
+        if shift.archived_at.is_some() {
+            return Err("Archived shifts can't be changed".into())
+        }
+        validate_requirements(&requirements_dto.requirements)?;
+        let skill_ids: Vec<i32> = requirements_dto.requirements.iter().map(|r| r.skill_id).collect();
+        Skill::check_exist(&skill_ids, _organization_id, conn)?;
+
+        conn.transaction::<(), Error, _>(|conn| {
+            diesel::delete(skill_requirements::table.filter(skill_requirements::shift_id.eq(_shift_id))).execute(conn)?;
+            let rows: Vec<NewSkillRequirement> = requirements_dto.requirements.iter()
+                .map(|r| NewSkillRequirement {
+                    shift_id: _shift_id,
+                    skill_id: r.skill_id,
+                    min_count: r.min_count,
+                    date: r.date,
+                })
+                .collect();
+            if !rows.is_empty() {
+                diesel::insert_into(skill_requirements::table).values(&rows).execute(conn)?;
+            }
+            Ok(())
+        })?;
+        Self::find_by_shift(_shift_id, _organization_id, conn)
+    }
+}
+
+// One requirement per skill and date, a count of 0 only makes sense to waive a skill on a date
+fn validate_requirements(requirements: &[SkillRequirementDTO]) -> Result<(), Error> {
+    for (i, requirement) in requirements.iter().enumerate() {
+        if requirement.min_count < 0 || (requirement.min_count == 0 && requirement.date.is_none()) {
+            return Err("Skills are required by at least one employee, or 0 on a date to waive them".into())
+        }
+        if requirements[..i].iter().any(|r| r.skill_id == requirement.skill_id && r.date == requirement.date) {
+            return Err(format!("Skill {} is required twice on the same days", requirement.skill_id).into())
+        }
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+    use chrono::NaiveDate;
+    use crate::models::skills::{SkillRequirementDTO, SkillRules, validate_requirements};
+
+    const L2: i32 = 1;
+    const TOOL: i32 = 2;
+
+    fn rules() -> SkillRules {
+        SkillRules {
+            employee_skills: HashMap::from([(1, vec![L2]), (2, vec![L2, TOOL]), (3, vec![TOOL])]),
+            every_day: HashMap::from([("D".to_string(), BTreeMap::from([(L2, 1)]))]),
+            on_day: HashMap::from([
+                ((24, "D".to_string()), BTreeMap::from([(L2, 2), (TOOL, 1)])),
+                ((25, "D".to_string()), BTreeMap::from([(L2, 0)])),
+            ]),
+        }
+    }
+
+    #[test]
+    fn test_requirements_of_a_day_replace_every_day() {
+        let rules = rules();
+        assert_eq!(rules.required(1, "D"), vec![(L2, 1)]);
+        assert_eq!(rules.required(24, "D"), vec![(L2, 2), (TOOL, 1)]);
+        assert_eq!(rules.required(25, "D"), vec![]);
+        assert_eq!(rules.required(1, "S"), vec![]);
+    }
+
+    #[test]
+    fn test_shortfalls() {
+        let rules = rules();
+        assert_eq!(rules.shortfalls(1, "D", &[3, 4]), vec![(L2, 1, 0)]);
+        assert!(rules.shortfalls(1, "D", &[1]).is_empty());
+        assert_eq!(rules.shortfalls(24, "D", &[2, 3]), vec![(L2, 2, 1)]);
+        assert!(rules.needed_in(1, 1, &["C", "D"]));
+        assert!(!rules.needed_in(3, 1, &["C", "D"]));
+    }
+
+    #[test]
+    fn test_validate_requirements() {
+        let requirement = |skill_id, min_count, date| SkillRequirementDTO { skill_id, min_count, date };
+        let christmas = NaiveDate::from_ymd_opt(2024, 12, 25);
+        assert!(validate_requirements(&[requirement(L2, 1, None), requirement(L2, 0, christmas)]).is_ok());
+        assert!(validate_requirements(&[requirement(L2, 0, None)]).is_err());
+        assert!(validate_requirements(&[requirement(L2, 1, christmas), requirement(L2, 2, christmas)]).is_err());
+    }
+}
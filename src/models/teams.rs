@@ -0,0 +1,361 @@
+use std::collections::HashMap;
+use chrono::NaiveDateTime;
+use chrono_tz::Tz;
+use diesel::{Identifiable, Insertable, PgConnection, Queryable, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use crate::error::Error;
+use crate::models::employee::Employee;
+use crate::models::organizations::Organization;
+use crate::models::shifts::{SHIFT_WORK, Shift};
+use crate::permissions::Permission;
+use crate::schema::{team_members, team_shifts, teams};
+use crate::utils::{parse_time_zone, TokenClaims};
+use diesel::prelude::*;
+
+/*
+    Slots of the generator. When a team doesn't say otherwise, a slot is filled
+    by the shift of the organization named after it:
+        - S: Morning shift from 6.00 to 14.00
+        - C: Afternoon shift from 14.00 to 22.00
+        - D: Night shift from 22.00 to 6.00 next day ***
+        - H: Office hours from 8.00 to 18.00
+ */
+pub const SHIFT_SLOTS: [&str; 4] = ["S", "C", "D", "H"];
+// Key of the on-call duty of a day in generated schedules, it has no slot in the catalogue
+pub const ON_CALL_SLOT: &str = "O";
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Identifiable, Clone)]
+#[diesel(table_name = teams)]
+pub struct Team {
+    pub id: i32,
+    pub name: String,
+    pub manager_id: Option<i32>,
+    pub created_at: NaiveDateTime,
+    pub organization_id: i32,
+    // zone of the team's shift times, the organization's when not set
+    pub time_zone: Option<String>,
+    // holidays of this region apply on top of the organization's, see models::holidays
+    pub region: Option<String>
+}
+
+#[derive(Debug, Serialize, Deserialize, Insertable)]
+#[diesel(table_name = teams)]
+pub struct TeamDTO {
+    pub name: String,
+    pub manager_id: Option<i32>,
+    #[serde(default)]
+    pub time_zone: Option<String>,
+    #[serde(default)]
+    pub region: Option<String>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TeamMembersDTO {
+    pub employees: Vec<i32>
+}
+
+// Slot code (S, C, D, H) -> shift id
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TeamShiftsDTO {
+    pub shifts: HashMap<String, i32>
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TeamDetail {
+    #[serde(flatten)]
+    pub team: Team,
+    pub members: Vec<i32>,
+    pub shifts: HashMap<String, i32>
+}
+
+/*
+    Slot code <-> shift id of a team.
+    A shift id maps back to its code for every team, so schedules show up
+    with the right letter even when listed across teams
+ */
+#[derive(Debug, Clone)]
+pub struct ShiftCatalogue {
+    by_code: HashMap<String, i32>,
+    by_shift: HashMap<i32, String>
+}
+
+impl ShiftCatalogue {
+    // `team_shifts` overrides `defaults`, `other_shifts` only adds reverse lookups
+    pub fn new(defaults: &[(String, i32)], team_shifts: &[(String, i32)], other_shifts: &[(String, i32)]) -> Self {
+        let mut by_code: HashMap<String, i32> = defaults.iter().cloned().collect();
+        let mut by_shift: HashMap<i32, String> = defaults.iter().map(|(code, id)| (*id, code.clone())).collect();
+        for (code, id) in other_shifts {
+            by_shift.insert(*id, code.clone());
+        }
+        for (code, id) in team_shifts {
+            by_code.insert(code.clone(), *id);
+            by_shift.insert(*id, code.clone());
+        }
+        ShiftCatalogue { by_code, by_shift }
+    }
+
+    pub fn shift_id(&self, code: &str) -> Option<i32> {
+        self.by_code.get(code).copied()
+    }
+
+    // "N" for shifts outside of the catalogue
+    pub fn code(&self, shift_id: i32) -> &str {
+        self.by_shift.get(&shift_id).map(|code| code.as_str()).unwrap_or("N")
+    }
+}
+
+impl Team {
+    // Only AssignRoles can give the new team a manager, the same as when updating it
+    pub fn new(team_dto: TeamDTO, claims: &TokenClaims, conn: &mut PgConnection) -> Result<Team, Error> {
+        use crate::schema::teams::dsl::*;
+        let _organization_id = claims.org;
+        Self::check_manager_assignment(None, team_dto.manager_id, claims)?;
+        Self::check_manager(team_dto.manager_id, _organization_id, conn)?;
+        if let Some(zone) = &team_dto.time_zone {
+            parse_time_zone(zone)?;
+        }
+        if teams.filter(organization_id.eq(_organization_id)).filter(name.eq(&team_dto.name)).count().get_result::<i64>(conn)? > 0 {
+            return Err(format!("Team '{}' already exists", team_dto.name).into())
+        }
+        Ok(diesel::insert_into(teams).values((&team_dto, organization_id.eq(_organization_id))).get_result::<Team>(conn)?)
+    }
+
+    // Only whoever manages the team can change it, and only AssignRoles can hand it to another manager
+    pub fn update(_id: i32, team_dto: TeamDTO, claims: &TokenClaims, conn: &mut PgConnection) -> Result<Team, Error> {
+        use crate::schema::teams::dsl::*;
+        let _organization_id = claims.org;
+        let team = Self::find_by_id(_id, _organization_id, conn)?;
+        team.check_manages(claims)?;
+        Self::check_manager_assignment(team.manager_id, team_dto.manager_id, claims)?;
+        Self::check_manager(team_dto.manager_id, _organization_id, conn)?;
+        if let Some(zone) = &team_dto.time_zone {
+            parse_time_zone(zone)?;
+        }
+        if teams.filter(organization_id.eq(_organization_id)).filter(name.eq(&team_dto.name)).filter(id.ne(_id)).count().get_result::<i64>(conn)? > 0 {
+            return Err(format!("Team '{}' already exists", team_dto.name).into())
+        }
+        Ok(diesel::update(teams.find(_id))
+            .set((name.eq(team_dto.name), manager_id.eq(team_dto.manager_id), time_zone.eq(team_dto.time_zone), region.eq(team_dto.region)))
+            .get_result::<Team>(conn)?)
+    }
+
+    fn check_manager_assignment(current: Option<i32>, requested: Option<i32>, claims: &TokenClaims) -> Result<(), Error> {
+        if requested != current && !claims.can(Permission::AssignRoles) {
+            return Err("You are not allowed to change the manager of a team".into())
+        }
+        Ok(())
+    }
+
+    fn check_manager(_manager_id: Option<i32>, _organization_id: i32, conn: &mut PgConnection) -> Result<(), Error> {
+        if let Some(_manager_id) = _manager_id {
+            let manager = Employee::find_by_id(_manager_id, _organization_id, conn).map_err(|_| "Team manager not found")?;
+            if !manager.active {
+                return Err("Team manager is deactivated".into())
+            }
+        }
+        Ok(())
+    }
+
+    pub fn find_by_id(_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<Team, Error> {
+        use crate::schema::teams::dsl::*;
+        teams.find(_id).filter(organization_id.eq(_organization_id)).first::<Team>(conn)
+            .optional()?
+            .ok_or_else(|| "Team not found".into())
+    }
+
+    pub fn find_all(_organization_id: i32, conn: &mut PgConnection) -> Result<Vec<Team>, Error> {
+        use crate::schema::teams::dsl::*;
+        Ok(teams.filter(organization_id.eq(_organization_id)).order_by(name).load::<Team>(conn)?)
+    }
+
+    pub fn detail(_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<TeamDetail, Error> {
+        let team = Self::find_by_id(_id, _organization_id, conn)?;
+        let shifts = team_shifts::table
+            .filter(team_shifts::team_id.eq(_id))
+            .select((team_shifts::code, team_shifts::shift_id))
+            .load::<(String, i32)>(conn)?
+            .into_iter()
+            .collect();
+        Ok(TeamDetail {
+            members: Self::member_ids(_id, conn)?,
+            shifts,
+            team,
+        })
+    }
+
+    // `_team_id` must already be known to belong to the caller's organization
+    pub fn member_ids(_team_id: i32, conn: &mut PgConnection) -> Result<Vec<i32>, Error> {
+        Ok(team_members::table
+            .filter(team_members::team_id.eq(_team_id))
+            .select(team_members::employee_id)
+            .order_by(team_members::employee_id)
+            .load::<i32>(conn)?)
+    }
+
+    // Members of every team the employee manages
+    pub fn managed_member_ids(_manager_id: i32, _organization_id: i32, conn: &mut PgConnection) -> Result<Vec<i32>, Error> {
+        Ok(team_members::table
+            .inner_join(teams::table)
+            .filter(teams::manager_id.eq(_manager_id))
+            .filter(teams::organization_id.eq(_organization_id))
+            .select(team_members::employee_id)
+            .distinct()
+            .order_by(team_members::employee_id)
+            .load::<i32>(conn)?)
+    }
+
+    // Admins manage every team, others only the teams they manage or that have no manager
+    pub fn check_manages(&self, claims: &TokenClaims) -> Result<(), Error> {
+        match self.manager_id {
+            Some(manager) if manager != claims.sub && !claims.can(Permission::ViewAllDepartments) =>
+                Err(format!("You don't manage team '{}'", self.name).into()),
+            _ => Ok(())
+        }
+    }
+
+    // Reviewers with ViewAllDepartments reach everyone, others only the members of the teams they manage
+    pub fn check_manages_employee(_employee_id: i32, claims: &TokenClaims, conn: &mut PgConnection) -> Result<(), Error> {
+        if claims.can(Permission::ViewAllDepartments) || Self::managed_member_ids(claims.sub, claims.org, conn)?.contains(&_employee_id) {
+            return Ok(())
+        }
+        Err("You don't manage this employee".into())
+    }
+
+    // Replaces the members of a team
+    pub fn set_members(_id: i32, members_dto: TeamMembersDTO, claims: &TokenClaims, conn: &mut PgConnection) -> Result<TeamDetail, Error> {
+        use crate::schema::employees;
+        let team = Self::find_by_id(_id, claims.org, conn)?;
+        team.check_manages(claims)?;
+        let mut members = members_dto.employees;
+        members.sort();
+        members.dedup();
+        let found = employees::table
+            .filter(employees::id.eq_any(&members))
+            .filter(employees::organization_id.eq(claims.org))
+            .filter(employees::active.eq(true))
+            .count()
+            .get_result::<i64>(conn)?;
+        if found != members.len() as i64 {
+            return Err("Unknown or deactivated employee in team members".into())
+        }
+
+        conn.transaction::<(), Error, _>(|conn| {
+            diesel::delete(team_members::table.filter(team_members::team_id.eq(_id))).execute(conn)?;
+            let rows: Vec<_> = members.iter()
+                .map(|member| (team_members::team_id.eq(_id), team_members::employee_id.eq(*member)))
+                .collect();
+            if !rows.is_empty() {
+                diesel::insert_into(team_members::table).values(&rows).execute(conn)?;
+            }
+            Ok(())
+        })?;
+        Self::detail(_id, claims.org, conn)
+    }
+
+    // Replaces the shift catalogue of a team
+    pub fn set_shifts(_id: i32, shifts_dto: TeamShiftsDTO, claims: &TokenClaims, conn: &mut PgConnection) -> Result<TeamDetail, Error> {
+        let team = Self::find_by_id(_id, claims.org, conn)?;
+        team.check_manages(claims)?;
+        for (code, shift) in &shifts_dto.shifts {
+            if !SHIFT_SLOTS.contains(&code.as_str()) {
+                return Err(format!("Unknown shift slot '{}', expected S, C, D or H", code).into())
+            }
+            if Shift::find_active(shift, claims.org, conn)?.category != SHIFT_WORK {
+                return Err(format!("Slot {} needs a work shift, on-call shifts are rostered on top of them", code).into())
+            }
+        }
+
+        conn.transaction::<(), Error, _>(|conn| {
+            diesel::delete(team_shifts::table.filter(team_shifts::team_id.eq(_id))).execute(conn)?;
+            let rows: Vec<_> = shifts_dto.shifts.iter()
+                .map(|(code, shift)| (team_shifts::team_id.eq(_id), team_shifts::code.eq(code), team_shifts::shift_id.eq(*shift)))
+                .collect();
+            if !rows.is_empty() {
+                diesel::insert_into(team_shifts::table).values(&rows).execute(conn)?;
+            }
+            Ok(())
+        })?;
+        Self::detail(_id, claims.org, conn)
+    }
+
+    // Zone of the shift times of a team, or of the organization when no team is given
+    pub fn time_zone(_team_id: Option<i32>, _organization_id: i32, conn: &mut PgConnection) -> Result<Tz, Error> {
+        let zone = match _team_id {
+            Some(_team_id) => Self::find_by_id(_team_id, _organization_id, conn)?.time_zone,
+            None => None
+        };
+        let zone = match zone {
+            Some(zone) => zone,
+            None => Organization::find_by_id(_organization_id, conn)?.time_zone
+        };
+        Ok(parse_time_zone(&zone)?)
+    }
+
+    // Region of a team for its holidays, none without a team (only holidays of the whole organization apply)
+    pub fn region(_team_id: Option<i32>, _organization_id: i32, conn: &mut PgConnection) -> Result<Option<String>, Error> {
+        match _team_id {
+            Some(_team_id) => Ok(Self::find_by_id(_team_id, _organization_id, conn)?.region),
+            None => Ok(None)
+        }
+    }
+
+    // The catalogue of a team, or the defaults of the organization when no team is given
+    pub fn shift_catalogue(_team_id: Option<i32>, _organization_id: i32, conn: &mut PgConnection) -> Result<ShiftCatalogue, Error> {
+        use crate::schema::shifts;
+        let defaults = shifts::table
+            .filter(shifts::organization_id.eq(_organization_id))
+            .filter(shifts::name.eq_any(SHIFT_SLOTS))
+            .filter(shifts::category.eq(SHIFT_WORK))
+            .filter(shifts::archived_at.is_null())
+            .order_by(shifts::id)
+            .select((shifts::name, shifts::id))
+            .load::<(String, i32)>(conn)?;
+        let rows = team_shifts::table
+            .inner_join(teams::table)
+            .filter(teams::organization_id.eq(_organization_id))
+            .select((team_shifts::team_id, team_shifts::code, team_shifts::shift_id))
+            .load::<(i32, String, i32)>(conn)?;
+        let (own, other): (Vec<_>, Vec<_>) = rows.into_iter().partition(|(team, _, _)| Some(*team) == _team_id);
+        let strip = |rows: Vec<(i32, String, i32)>| rows.into_iter().map(|(_, code, shift)| (code, shift)).collect::<Vec<_>>();
+        Ok(ShiftCatalogue::new(&defaults, &strip(own), &strip(other)))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::models::teams::{ShiftCatalogue, Team};
+    use crate::utils::TokenClaims;
+
+    fn defaults() -> Vec<(String, i32)> {
+        vec![("S".to_string(), 4), ("C".to_string(), 3), ("D".to_string(), 2), ("H".to_string(), 1)]
+    }
+
+    #[test]
+    fn test_default_catalogue() {
+        let catalogue = ShiftCatalogue::new(&defaults(), &[], &[]);
+        assert_eq!(catalogue.shift_id("S"), Some(4));
+        assert_eq!(catalogue.code(1), "H");
+        assert_eq!(catalogue.code(99), "N");
+    }
+
+    #[test]
+    fn test_team_catalogue_overrides_defaults() {
+        let catalogue = ShiftCatalogue::new(&defaults(), &[("S".to_string(), 7)], &[("D".to_string(), 9)]);
+        assert_eq!(catalogue.shift_id("S"), Some(7));
+        assert_eq!(catalogue.shift_id("D"), Some(2));
+        assert_eq!(catalogue.code(7), "S");
+        // shifts of other teams are still recognised
+        assert_eq!(catalogue.code(9), "D");
+    }
+
+    #[test]
+    fn test_only_role_assigners_pick_team_managers() {
+        let claims = |role: &str| TokenClaims { sub: 2, role: role.to_string(), org: 1, sid: 1, iat: 0, exp: 0 };
+        assert!(Team::check_manager_assignment(None, Some(2), &claims("Manager")).is_err());
+        assert!(Team::check_manager_assignment(Some(3), Some(2), &claims("Manager")).is_err());
+        assert!(Team::check_manager_assignment(Some(2), Some(2), &claims("Manager")).is_ok());
+        assert!(Team::check_manager_assignment(None, None, &claims("Manager")).is_ok());
+        assert!(Team::check_manager_assignment(None, Some(2), &claims("Admin")).is_ok());
+    }
+}
@@ -0,0 +1,147 @@
+use diesel::prelude::*;
+use crate::error::Error;
+use crate::utils::TokenClaims;
+
+/*
+    What a role allows. Routes require permissions with `middleware::permission::RequirePermission`,
+    never role names, so changing what a role can do only touches `Role::permissions`
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    ViewSchedules,
+    ManageSchedules,
+    // file shift changes and leave on behalf of someone else
+    FileForOthers,
+    ReviewShiftChanges,
+    ManageApprovalPolicies,
+    ReviewLeave,
+    ManageLeaveBalances,
+    // edit, deactivate and reset the password of other employees
+    ManageEmployees,
+    DeleteEmployees,
+    AssignRoles,
+    // see the requests and balances of their own department
+    ViewTeam,
+    // see every department, not only their own
+    ViewAllDepartments,
+    // settings of the organization itself, such as its time zone
+    ManageOrganization,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Admin,
+    Manager,
+    TeamLead,
+    Employee,
+    Auditor,
+}
+
+pub const ROLES: [Role; 5] = [Role::Admin, Role::Manager, Role::TeamLead, Role::Employee, Role::Auditor];
+
+impl Role {
+    // The value stored in `employees.role`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Admin => "Admin",
+            Role::Manager => "Manager",
+            Role::TeamLead => "TeamLead",
+            Role::Employee => "Employee",
+            Role::Auditor => "Auditor",
+        }
+    }
+
+    pub fn parse(role: &str) -> Option<Role> {
+        ROLES.iter().copied().find(|r| r.name().eq_ignore_ascii_case(role))
+    }
+
+    pub fn permissions(&self) -> &'static [Permission] {
+        use Permission::*;
+        match self {
+            Role::Admin => &[
+                ViewSchedules, ManageSchedules, FileForOthers, ReviewShiftChanges, ManageApprovalPolicies,
+                ReviewLeave, ManageLeaveBalances, ManageEmployees, DeleteEmployees, AssignRoles,
+                ViewTeam, ViewAllDepartments, ManageOrganization,
+            ],
+            Role::Manager => &[
+                ViewSchedules, ManageSchedules, FileForOthers, ReviewShiftChanges, ManageApprovalPolicies,
+                ReviewLeave, ManageLeaveBalances, ManageEmployees, DeleteEmployees, ViewTeam,
+            ],
+            Role::TeamLead => &[ViewSchedules, FileForOthers, ReviewShiftChanges, ReviewLeave, ViewTeam],
+            Role::Employee => &[ViewSchedules],
+            Role::Auditor => &[ViewSchedules, ViewTeam, ViewAllDepartments],
+        }
+    }
+}
+
+// Unknown roles get no permission at all
+pub fn has_permission(role: &str, permission: Permission) -> bool {
+    Role::parse(role).is_some_and(|r| r.permissions().contains(&permission))
+}
+
+impl TokenClaims {
+    pub fn can(&self, permission: Permission) -> bool {
+        has_permission(&self.role, permission)
+    }
+
+    /*
+        ManageEmployees and DeleteEmployees stop at accounts holding AssignRoles unless the caller holds it too,
+        otherwise a Manager could take over or remove an Admin
+     */
+    pub fn outranked_by(&self, role: &str) -> bool {
+        has_permission(role, Permission::AssignRoles) && !self.can(Permission::AssignRoles)
+    }
+}
+
+/*
+    Whose data the caller may list:
+        None             only their own
+        Some(Some(dep))  everyone in department `dep`
+        Some(None)       everyone (ViewAllDepartments, or ViewTeam without a department)
+ */
+pub fn department_scope(claims: &TokenClaims, conn: &mut PgConnection) -> Result<Option<Option<String>>, Error> {
+    use crate::schema::employees;
+    if claims.can(Permission::ViewAllDepartments) {
+        return Ok(Some(None))
+    }
+    if !claims.can(Permission::ViewTeam) {
+        return Ok(None)
+    }
+    Ok(Some(employees::table.find(claims.sub).select(employees::department).first::<Option<String>>(conn)?))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::permissions::{has_permission, Permission, Role};
+    use crate::utils::TokenClaims;
+
+    #[test]
+    fn test_parse_role() {
+        assert_eq!(Role::parse("Manager"), Some(Role::Manager));
+        assert_eq!(Role::parse("teamlead"), Some(Role::TeamLead));
+        assert_eq!(Role::parse("Janitor"), None);
+    }
+
+    #[test]
+    fn test_role_permissions() {
+        assert!(has_permission("Admin", Permission::AssignRoles));
+        assert!(!has_permission("Manager", Permission::AssignRoles));
+        assert!(!has_permission("Manager", Permission::ManageOrganization));
+        assert!(has_permission("TeamLead", Permission::ReviewLeave));
+        assert!(!has_permission("TeamLead", Permission::ManageSchedules));
+        assert!(has_permission("Auditor", Permission::ViewAllDepartments));
+        assert!(!has_permission("Auditor", Permission::ReviewLeave));
+        assert!(!has_permission("Employee", Permission::ViewTeam));
+        assert!(!has_permission("Janitor", Permission::ViewSchedules));
+    }
+
+    #[test]
+    fn test_role_assigners_are_out_of_reach_of_managers() {
+        let claims = |role: &str| TokenClaims { sub: 1, role: role.to_string(), org: 1, sid: 1, iat: 0, exp: 0 };
+        assert!(claims("Manager").outranked_by("Admin"));
+        assert!(!claims("Manager").outranked_by("Manager"));
+        assert!(!claims("Manager").outranked_by("Employee"));
+        assert!(!claims("Admin").outranked_by("Admin"));
+    }
+}
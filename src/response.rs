@@ -17,6 +17,22 @@ impl<T> ResponseBody<T> {
     }
 }
 
+// Body of authentication and authorization failures, `code` is one of the error::AUTH_* values
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+}
+
+impl ErrorBody {
+    pub fn new(code: &str, message: &str) -> ErrorBody {
+        ErrorBody {
+            code: code.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
 #[allow(unused)]
 #[derive(Serialize)]
 pub struct Page<T> {
@@ -46,6 +62,7 @@ impl<T> Page<T> {
     }
 }
 
+#[allow(clippy::needless_return)]
 pub fn match_err_response<T: serde::Serialize>(result: Result<T, Error>) ->  Result<HttpResponse, Error>{
     match result {
         Ok(rs) => Ok(HttpResponse::Ok().json(rs)),
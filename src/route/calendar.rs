@@ -0,0 +1,87 @@
+use actix_web::{Error, HttpResponse, web};
+use crate::config::postgres::DbPool;
+use crate::constants;
+use crate::middleware;
+use crate::models::calendar_feeds::CalendarFeed;
+use crate::models::schedule::Schedule;
+use crate::permissions::Permission;
+use crate::response::match_err_response;
+use crate::utils::TokenClaims;
+
+// The token is only shown here, issuing a new one revokes the previous one
+pub async fn issue_token(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>) -> Result<HttpResponse, Error> {
+    let (uid, org) = (claims.sub, claims.org);
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        CalendarFeed::issue(uid, org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn revoke_token(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>) -> Result<HttpResponse, Error> {
+    let uid = claims.sub;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        CalendarFeed::revoke(uid, &mut conn).map(|_| constants::MESSAGE_OK)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+async fn feed(token: String, team_id: Option<i32>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        let employee = CalendarFeed::authenticate(&token, &mut conn)?;
+        Schedule::ical_feed(&employee, team_id, &mut conn)
+    }).await?;
+
+    match result {
+        Ok(ical) => Ok(HttpResponse::Ok()
+            .content_type("text/calendar; charset=utf-8")
+            .body(ical)),
+        Err(err) => Ok(HttpResponse::BadRequest().body(err.to_string()))
+    }
+}
+
+pub async fn employee_feed(token: web::Path<String>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    feed(token.into_inner(), None, pool).await
+}
+
+pub async fn team_feed(path: web::Path<(String, i32)>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let (token, team_id) = path.into_inner();
+    feed(token, Some(team_id), pool).await
+}
+
+// Request path with the secret of a feed masked, for the access log
+pub fn redact_feed_token(path: &str) -> String {
+    match path.split_once("/calendar/") {
+        Some((prefix, rest)) => match rest.split_once('/') {
+            Some((_token, feed)) => format!("{}/calendar/***/{}", prefix, feed),
+            None => path.to_string()
+        },
+        None => path.to_string()
+    }
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/calendar")
+        .route("/token", web::post().to(issue_token).wrap(middleware::permission::RequirePermission(Permission::ViewSchedules)).wrap(middleware::jwt::JWTAuth))
+        .route("/token", web::delete().to(revoke_token).wrap(middleware::jwt::JWTAuth))
+        // calendar apps can't send a JWT, the secret token in the path says whose feed it is
+        .route("/{token}/schedule.ics", web::get().to(employee_feed))
+        .route("/{token}/team/{team_id}.ics", web::get().to(team_feed));
+    conf.service(scope);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::route::calendar::redact_feed_token;
+
+    #[test]
+    fn test_feed_tokens_are_kept_out_of_the_log() {
+        assert_eq!(redact_feed_token("/v1/calendar/12.s3cret/schedule.ics"), "/v1/calendar/***/schedule.ics");
+        assert_eq!(redact_feed_token("/v1/calendar/12.s3cret/team/4.ics"), "/v1/calendar/***/team/4.ics");
+        assert_eq!(redact_feed_token("/v1/calendar/token"), "/v1/calendar/token");
+        assert_eq!(redact_feed_token("/v1/schedule/export?month=5&year=2024"), "/v1/schedule/export?month=5&year=2024");
+    }
+}
@@ -1,14 +1,23 @@
-use actix_web::{Error, HttpResponse, web};
-use serde_json::json;
+use actix_web::{Error, HttpRequest, HttpResponse, web};
+use crate::config::login::LoginPolicy;
 use crate::config::postgres::DbPool;
-use crate::models::employee::{Employee, EmployeeDTO, LoginDTO};
+use crate::constants;
+use crate::middleware;
+use crate::permissions::{Permission, Role};
+use crate::models::employee::{ChangePasswordDTO, Employee, EmployeeDTO, LoginDTO, UpdateEmployeeDTO};
+use crate::error::ServiceError;
+use crate::models::login_attempts::{LockoutEventFilter, LoginAttempt};
+use crate::models::password_reset_tokens::{PasswordResetToken, ResetPasswordDTO};
+use crate::models::sessions::{RefreshDTO, Session};
 use crate::response::match_err_response;
+use crate::utils::TokenClaims;
 
-pub async fn create(pool: web::Data<DbPool>, payload: web::Json<EmployeeDTO>) -> Result<HttpResponse, Error> {
+pub async fn create(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<EmployeeDTO>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
     let result = web::block(move || {
         let mut conn = pool.get()?;
         //add service module later
-        Employee::new(payload.into_inner(), &mut conn)
+        Employee::new(payload.into_inner(), &claims, &mut conn)
     }).await?.map_err(actix_web::error::ErrorInternalServerError);
     match_err_response(result)
 }
@@ -16,18 +25,19 @@ pub async fn create(pool: web::Data<DbPool>, payload: web::Json<EmployeeDTO>) ->
 pub async fn seed(pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
     let pool1 = pool.clone();
     let payload = EmployeeDTO {
-        name: "Manager".to_string(),
+        name: Role::Manager.name().to_string(),
         email: "manager@vsec.com.vn".to_string(),
         password: "123".to_string(),
         phone_number: None,
         department: None,
-        role: "Manager".to_string(),
+        role: Role::Manager.name().to_string(),
         availability: None,
+        time_zone: None,
     };
     let _result = web::block(move || {
         let mut conn = pool1.clone().get()?;
         //add service module later
-        Employee::new(payload, &mut conn)
+        Employee::register(payload, constants::DEFAULT_ORGANIZATION, &mut conn)
     }).await?.map_err(actix_web::error::ErrorInternalServerError);
     for i in 0..20 {
         let payload = EmployeeDTO {
@@ -36,60 +46,194 @@ pub async fn seed(pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
             password: "123".to_string(),
             phone_number: None,
             department: None,
-            role: "SOC".to_string(),
+            role: Role::Employee.name().to_string(),
             availability: None,
+            time_zone: None,
         };
         let pool_shared = pool.clone();
         let _result = web::block(move || {
             let mut conn = pool_shared.get()?;
             //add service module later
-            Employee::new(payload, &mut conn)
+            Employee::register(payload, constants::DEFAULT_ORGANIZATION, &mut conn)
         }).await?.map_err(actix_web::error::ErrorInternalServerError);
     }
 
     match_err_response(Ok("ok"))
 }
 
-pub async fn login(login_dto: web::Json<LoginDTO>, pool: web::Data<DbPool>) ->   Result<HttpResponse, Error> {
+#[allow(clippy::needless_return)]
+pub async fn login(req: HttpRequest, login_dto: web::Json<LoginDTO>, pool: web::Data<DbPool>) ->   Result<HttpResponse, Error> {
+    let policy = LoginPolicy::from_env();
+    let client_ip = policy.client_ip(&req);
+
+    // answers to accounts with recent failures are held back, longer after every failure
+    let (email, delay_pool) = (login_dto.email.clone(), pool.clone());
+    let delay = web::block(move || {
+        let mut conn = delay_pool.get()?;
+        LoginAttempt::delay(&email, &policy, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError)?;
+    actix_web::rt::time::sleep(delay).await;
+
     let rs = web::block(move || {
         let mut conn = pool.get()?;
-        Employee::login(login_dto.into_inner(), &mut conn)
+        Employee::login(login_dto.into_inner(), client_ip.as_deref(), &mut conn)
     })
-    .await?
-    .map_err(actix_web::error::ErrorInternalServerError);
+    .await?;
 
     // response OK if user added , FAIL if some server, database or validation error occur
     match rs {
-        Ok(token) => Ok(HttpResponse::Ok().json(json!({"access_token" : token}))),
-        Err(_) => {
-            return Ok(HttpResponse::BadRequest().body("FAIL"))
+        Ok(tokens) => Ok(HttpResponse::Ok().json(tokens)),
+        Err(err) => match err.downcast::<ServiceError>() {
+            Ok(err) => Err((*err).into()),
+            Err(_) => return Ok(HttpResponse::BadRequest().body("FAIL"))
         }
     }
 }
 
-pub async fn get_by_id(uid: web::Path<i32> ,pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
-    let uid = uid.into_inner();
+pub async fn unlock(uid: web::Path<i32>, claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let (uid, claims) = (uid.into_inner(), claims.into_inner());
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        LoginAttempt::unlock(uid, &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+pub async fn get_lockout_events(filter: web::Query<LockoutEventFilter>, claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        LoginAttempt::find_events(filter.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+pub async fn refresh(refresh_dto: web::Json<RefreshDTO>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        Session::refresh(refresh_dto.into_inner(), &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+pub async fn logout(claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        Session::revoke(claims.sid, claims.sub, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+pub async fn logout_all(claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let uid = claims.sub;
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        Session::revoke_all(uid, None, &mut conn)?;
+        Ok::<_, crate::error::Error>(crate::constants::MESSAGE_LOGOUT_SUCCESS)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+pub async fn get_by_id(uid: web::Path<i32>, claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let (uid, org) = (uid.into_inner(), claims.org);
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        Employee::find_by_id(uid, org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+pub async fn get_managers(claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        Employee::find_by_role(Role::Manager, org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+pub async fn get_employees(claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        Employee::find_by_role(Role::Employee, org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+pub async fn update(uid: web::Path<i32>, claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>, payload: web::Json<UpdateEmployeeDTO>) -> Result<HttpResponse, Error> {
+    let (uid, claims) = (uid.into_inner(), claims.into_inner());
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        Employee::update(uid, payload.into_inner(), &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+pub async fn deactivate(uid: web::Path<i32>, claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let (uid, claims) = (uid.into_inner(), claims.into_inner());
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        Employee::set_active(uid, false, &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+pub async fn activate(uid: web::Path<i32>, claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let (uid, claims) = (uid.into_inner(), claims.into_inner());
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        Employee::set_active(uid, true, &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+pub async fn delete(uid: web::Path<i32>, claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let (uid, claims) = (uid.into_inner(), claims.into_inner());
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        Employee::anonymize(uid, &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+pub async fn change_password(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<ChangePasswordDTO>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
     let rs = web::block(move || {
         let mut conn = pool.get()?;
-        Employee::find_by_id(uid, &mut conn)
+        Employee::change_password(&claims, payload.into_inner(), &mut conn)
     }).await?.map_err(actix_web::error::ErrorInternalServerError);
 
     match_err_response(rs)
 }
 
-pub async fn get_managers(pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+pub async fn issue_reset_token(uid: web::Path<i32>, claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let (uid, claims) = (uid.into_inner(), claims.into_inner());
     let rs = web::block(move || {
         let mut conn = pool.get()?;
-        Employee::find_by_role("Manager", &mut conn)
+        PasswordResetToken::issue(uid, &claims, &mut conn)
     }).await?.map_err(actix_web::error::ErrorInternalServerError);
 
     match_err_response(rs)
 }
 
-pub async fn get_employees(pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+pub async fn reset_password(pool: web::Data<DbPool>, payload: web::Json<ResetPasswordDTO>) -> Result<HttpResponse, Error> {
     let rs = web::block(move || {
         let mut conn = pool.get()?;
-        Employee::find_by_role("SOC", &mut conn)
+        PasswordResetToken::redeem(payload.into_inner(), &mut conn)
     }).await?.map_err(actix_web::error::ErrorInternalServerError);
 
     match_err_response(rs)
@@ -97,12 +241,24 @@ pub async fn get_employees(pool: web::Data<DbPool>) -> Result<HttpResponse, Erro
 
 pub fn config(conf: &mut web::ServiceConfig) {
     let scope = web::scope("/user")
-        .route("/", web::post().to(create))
+        .route("/", web::post().to(create).wrap(middleware::permission::RequirePermission(Permission::ManageEmployees)).wrap(middleware::jwt::JWTAuth))
         .route("/login", web::post().to(login))
+        .route("/refresh", web::post().to(refresh))
+        .route("/logout", web::post().to(logout).wrap(middleware::jwt::JWTAuth))
+        .route("/logout_all", web::post().to(logout_all).wrap(middleware::jwt::JWTAuth))
         .route("/seed", web::get().to(seed))
-        .route("/employees", web::get().to(get_employees))
-        .route("/managers", web::get().to(get_managers))
-        .route("/{id}" , web::get().to(get_by_id));
+        .route("/employees", web::get().to(get_employees).wrap(middleware::jwt::JWTAuth))
+        .route("/managers", web::get().to(get_managers).wrap(middleware::jwt::JWTAuth))
+        .route("/lockout_events", web::get().to(get_lockout_events).wrap(middleware::permission::RequirePermission(Permission::ManageEmployees)).wrap(middleware::jwt::JWTAuth))
+        .route("/password", web::put().to(change_password).wrap(middleware::jwt::JWTAuth))
+        .route("/password/reset", web::post().to(reset_password))
+        .route("/{id}/unlock", web::post().to(unlock).wrap(middleware::permission::RequirePermission(Permission::ManageEmployees)).wrap(middleware::jwt::JWTAuth))
+        .route("/{id}/reset_token", web::post().to(issue_reset_token).wrap(middleware::permission::RequirePermission(Permission::ManageEmployees)).wrap(middleware::jwt::JWTAuth))
+        .route("/{id}" , web::get().to(get_by_id).wrap(middleware::jwt::JWTAuth))
+        .route("/{id}", web::patch().to(update).wrap(middleware::jwt::JWTAuth))
+        .route("/{id}", web::delete().to(delete).wrap(middleware::permission::RequirePermission(Permission::DeleteEmployees)).wrap(middleware::jwt::JWTAuth))
+        .route("/{id}/deactivate", web::post().to(deactivate).wrap(middleware::permission::RequirePermission(Permission::ManageEmployees)).wrap(middleware::jwt::JWTAuth))
+        .route("/{id}/activate", web::post().to(activate).wrap(middleware::permission::RequirePermission(Permission::ManageEmployees)).wrap(middleware::jwt::JWTAuth));
     conf.service(scope);
 }
 
@@ -0,0 +1,63 @@
+use actix_web::{Error, HttpResponse, web};
+use crate::config::postgres::DbPool;
+use crate::middleware;
+use crate::models::holidays::{Holiday, HolidayDTO, HolidayFilter, HolidayImportFilter};
+use crate::permissions::Permission;
+use crate::response::match_err_response;
+use crate::utils::TokenClaims;
+
+pub async fn find_all(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, filter: web::Query<HolidayFilter>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Holiday::find_all(filter.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn create(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<HolidayDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Holiday::new(payload.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn update(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, holiday_id: web::Path<i32>, payload: web::Json<HolidayDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Holiday::update(holiday_id.into_inner(), payload.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn delete(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, holiday_id: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Holiday::delete(holiday_id.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+// Body is the text of an iCalendar (.ics) file
+pub async fn import(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, filter: web::Query<HolidayImportFilter>, calendar: String) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Holiday::import_ical(&calendar, filter.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/holiday").wrap(middleware::jwt::JWTAuth)
+        .route("/", web::get().to(find_all).wrap(middleware::permission::RequirePermission(Permission::ViewSchedules)))
+        .route("/", web::post().to(create).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)))
+        .route("/import", web::post().to(import).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)))
+        .route("/{id}", web::put().to(update).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)))
+        .route("/{id}", web::delete().to(delete).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)));
+    conf.service(scope);
+}
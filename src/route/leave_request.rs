@@ -0,0 +1,124 @@
+use actix_web::{Error, HttpResponse, web};
+use crate::config::postgres::DbPool;
+use crate::middleware;
+use crate::permissions::Permission;
+use crate::models::leave_balances::{AdjustmentDTO, LeaveEntitlement, LeaveEntitlementDTO, PeriodDTO};
+use crate::models::leave_requests::{LeaveFilter, LeaveRequest, LeaveRequestDTO, ReviewDTO};
+use crate::response::match_err_response;
+use crate::utils::TokenClaims;
+
+pub async fn create(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<LeaveRequestDTO>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        LeaveRequest::new(payload.into_inner(), &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn find_all(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, filter: web::Query<LeaveFilter>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        LeaveRequest::find_all(filter.into_inner(), &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn approve(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, leave_id: web::Path<i32>, payload: web::Json<ReviewDTO>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        LeaveRequest::review(leave_id.into_inner(), true, payload.into_inner(), &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn reject(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, leave_id: web::Path<i32>, payload: web::Json<ReviewDTO>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        LeaveRequest::review(leave_id.into_inner(), false, payload.into_inner(), &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn cancel(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, leave_id: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        LeaveRequest::cancel(leave_id.into_inner(), &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn get_balances(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        LeaveEntitlement::report(&claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn get_balance_history(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, employee_id: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        LeaveEntitlement::history(employee_id.into_inner(), &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn set_entitlement(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<LeaveEntitlementDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        LeaveEntitlement::upsert(payload.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn adjust_balance(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<AdjustmentDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        LeaveEntitlement::adjust(payload.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn accrue(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<PeriodDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        let month = payload.month.ok_or("month is required")?;
+        LeaveEntitlement::accrue(month, payload.year, org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn carry_over(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<PeriodDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        LeaveEntitlement::carry_over(payload.year, org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/leave").wrap(middleware::jwt::JWTAuth)
+        .route("/", web::get().to(find_all))
+        .route("/", web::post().to(create))
+        .route("/{id}/approve", web::post().to(approve).wrap(middleware::permission::RequirePermission(Permission::ReviewLeave)))
+        .route("/{id}/reject", web::post().to(reject).wrap(middleware::permission::RequirePermission(Permission::ReviewLeave)))
+        .route("/{id}/cancel", web::post().to(cancel))
+        .route("/balances", web::get().to(get_balances))
+        .route("/balances/{employee_id}/history", web::get().to(get_balance_history))
+        .route("/balances/entitlement", web::put().to(set_entitlement).wrap(middleware::permission::RequirePermission(Permission::ManageLeaveBalances)))
+        .route("/balances/adjust", web::post().to(adjust_balance).wrap(middleware::permission::RequirePermission(Permission::ManageLeaveBalances)))
+        .route("/balances/accrue", web::post().to(accrue).wrap(middleware::permission::RequirePermission(Permission::ManageLeaveBalances)))
+        .route("/balances/carry_over", web::post().to(carry_over).wrap(middleware::permission::RequirePermission(Permission::ManageLeaveBalances)));
+    conf.service(scope);
+}
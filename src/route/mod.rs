@@ -1,4 +1,10 @@
+pub mod calendar;
 pub mod employee;
+pub mod holiday;
+pub mod leave_request;
+pub mod organization;
 pub mod shift;
 pub mod schedule;
-pub mod shift_change;
\ No newline at end of file
+pub mod shift_change;
+pub mod skill;
+pub mod team;
\ No newline at end of file
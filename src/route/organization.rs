@@ -0,0 +1,63 @@
+use actix_web::{Error, HttpRequest, HttpResponse, web};
+use crate::config::login::LoginPolicy;
+use crate::config::postgres::DbPool;
+use crate::error::ServiceError;
+use crate::middleware;
+use crate::models::organizations::{NewOrganizationDTO, OnCallRulesDTO, Organization, OrganizationTimeZoneDTO};
+use crate::permissions::Permission;
+use crate::response::match_err_response;
+use crate::utils::TokenClaims;
+
+// Sign-up of a new organization, its admin then logs in through /user/login. Off by default, see config::signup
+pub async fn create(req: HttpRequest, pool: web::Data<DbPool>, payload: web::Json<NewOrganizationDTO>) -> Result<HttpResponse, Error> {
+    let client_ip = LoginPolicy::from_env().client_ip(&req);
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Organization::new(payload.into_inner(), client_ip.as_deref(), &mut conn)
+    }).await?;
+
+    match result {
+        Ok(organization) => Ok(HttpResponse::Ok().json(organization)),
+        Err(err) => match err.downcast::<ServiceError>() {
+            Ok(err) => Err((*err).into()),
+            Err(err) => Ok(HttpResponse::BadRequest().body(err.to_string()))
+        }
+    }
+}
+
+// The organization of the caller
+pub async fn get_own(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Organization::find_by_id(org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn set_time_zone(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<OrganizationTimeZoneDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Organization::set_time_zone(org, payload.into_inner(), &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn set_on_call_rules(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<OnCallRulesDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Organization::set_on_call_rules(org, payload.into_inner(), &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/organization")
+        .route("/", web::post().to(create))
+        .route("/", web::get().to(get_own).wrap(middleware::jwt::JWTAuth))
+        .route("/time_zone", web::put().to(set_time_zone).wrap(middleware::permission::RequirePermission(Permission::ManageOrganization)).wrap(middleware::jwt::JWTAuth))
+        .route("/on_call", web::put().to(set_on_call_rules).wrap(middleware::permission::RequirePermission(Permission::ManageOrganization)).wrap(middleware::jwt::JWTAuth));
+    conf.service(scope);
+}
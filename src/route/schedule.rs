@@ -1,27 +1,29 @@
 use actix_web::{Error, HttpResponse, web};
-use actix_files::NamedFile;
 use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
 use serde::Deserialize;
 use web::Json;
 use crate::config::postgres::DbPool;
 use crate::middleware;
+use crate::permissions::Permission;
 use crate::models::schedule::{AutoScheduleDTO, Schedule, ScheduleDTO};
 use crate::response::match_err_response;
-use mime::Mime;
+use crate::utils::TokenClaims;
 
-pub async fn create(pool: web::Data<DbPool>, payload: Json<ScheduleDTO>) -> Result<HttpResponse, Error> {
+pub async fn create(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: Json<ScheduleDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
     let result = web::block(move || {
         let mut conn = pool.get()?;
         //add service module later
-        Schedule::new(payload.into_inner(), &mut conn)
+        Schedule::new(payload.into_inner(), org, &mut conn)
     }).await?.map_err(actix_web::error::ErrorInternalServerError);
     match_err_response(result)
 }
 
-pub async fn generate_schedules(pool: web::Data<DbPool>, payload: Json<AutoScheduleDTO>) -> Result<HttpResponse, Error>{
+pub async fn generate_schedules(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: Json<AutoScheduleDTO>) -> Result<HttpResponse, Error>{
+    let claims = claims.into_inner();
     let rs = web::block(move || {
         let mut conn = pool.get()?;
-        Schedule::from_sample_to_db(payload.into_inner(),&mut conn)
+        Schedule::from_sample_to_db(payload.into_inner(), &claims, &mut conn)
     }).await?.map_err(actix_web::error::ErrorInternalServerError);
     match_err_response(rs)
 }
@@ -29,30 +31,31 @@ pub async fn generate_schedules(pool: web::Data<DbPool>, payload: Json<AutoSched
 #[derive(Deserialize)]
 pub struct Info {
     pub month: i32,
-    pub year: i32
+    pub year: i32,
+    pub team_id: Option<i32>
 }
 
-pub async fn export_csv(param : web::Query<Info>, pool: web::Data<DbPool>) -> Result<NamedFile, Error> {
+#[allow(clippy::needless_return)]
+pub async fn export_csv(param : web::Query<Info>, claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
     let rs = web::block(move || {
         let mut conn = pool.get()?;
-        Schedule::export_csv(param.month, param.year, &mut conn)
+        Schedule::export_csv(param.month, param.year, param.team_id, &claims, &mut conn)
     }).await?.map_err(actix_web::error::ErrorInternalServerError);
 
     match rs {
-        Ok(filename) => {
-            let file = NamedFile::open(&filename)?;
-
+        Ok(export) => {
             let content_disposition = ContentDisposition {
                 disposition: DispositionType::Attachment,
                 parameters: vec![
-                    DispositionParam::Filename(String::from(filename))
+                    DispositionParam::Filename(export.file_name)
                 ],
             };
 
-            let content_type: Mime = "text/csv".parse().unwrap();
-            Ok(file
-                .set_content_disposition(content_disposition)
-                .set_content_type(content_type))
+            Ok(HttpResponse::Ok()
+                .insert_header(content_disposition)
+                .content_type(mime::TEXT_CSV)
+                .body(export.content))
         },
         Err(err) => {
             return Err(err)
@@ -60,10 +63,33 @@ pub async fn export_csv(param : web::Query<Info>, pool: web::Data<DbPool>) -> Re
     }
 }
 
-pub async fn get_by_month(param : web::Query<Info>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+pub async fn get_by_month(param : web::Query<Info>, claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        Schedule::get_by_month_year(param.month, param.year, param.team_id, &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+// Gross and net hours worked per employee in a month
+pub async fn get_hours(param : web::Query<Info>, claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let rs = web::block(move || {
+        let mut conn = pool.get()?;
+        Schedule::hours(param.month, param.year, param.team_id, &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+
+    match_err_response(rs)
+}
+
+// Shifts worked without the skills they require
+pub async fn get_skill_violations(param : web::Query<Info>, claims: web::ReqData<TokenClaims>, pool: web::Data<DbPool>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
     let rs = web::block(move || {
         let mut conn = pool.get()?;
-        Schedule::get_by_month_year(param.month, param.year, &mut conn)
+        Schedule::skill_violations(param.month, param.year, param.team_id, org, &mut conn)
     }).await?.map_err(actix_web::error::ErrorInternalServerError);
 
     match_err_response(rs)
@@ -71,10 +97,13 @@ pub async fn get_by_month(param : web::Query<Info>, pool: web::Data<DbPool>) ->
 
 pub fn config(conf: &mut web::ServiceConfig) {
     let scope = web::scope("/schedule")
-        .route("/", web::get().to(get_by_month).wrap(middleware::jwt::JWTAuth))
-        .route("/", web::post().to(create).wrap(middleware::is_manager::IsManager).wrap(middleware::jwt::JWTAuth))
-        .route("/gen", web::post().to(generate_schedules).wrap(middleware::is_manager::IsManager).wrap(middleware::jwt::JWTAuth))
-        .route("/export", web::get().to(export_csv))
+        .route("/", web::get().to(get_by_month).wrap(middleware::permission::RequirePermission(Permission::ViewSchedules)).wrap(middleware::jwt::JWTAuth))
+        .route("/", web::post().to(create).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)).wrap(middleware::jwt::JWTAuth))
+        .route("/gen", web::post().to(generate_schedules).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)).wrap(middleware::jwt::JWTAuth))
+        // the organization and the rows to export come from the token
+        .route("/export", web::get().to(export_csv).wrap(middleware::permission::RequirePermission(Permission::ViewSchedules)).wrap(middleware::jwt::JWTAuth))
+        .route("/hours", web::get().to(get_hours).wrap(middleware::permission::RequirePermission(Permission::ViewTeam)).wrap(middleware::jwt::JWTAuth))
+        .route("/skill_violations", web::get().to(get_skill_violations).wrap(middleware::permission::RequirePermission(Permission::ViewSchedules)).wrap(middleware::jwt::JWTAuth))
       ;
     conf.service(scope);
 }
\ No newline at end of file
@@ -0,0 +1,104 @@
+use actix_web::{Error, HttpResponse, web};
+use crate::config::postgres::DbPool;
+use crate::middleware;
+use crate::models::shift_breaks::{ShiftBreak, ShiftBreaksDTO};
+use crate::models::shifts::{Shift, ShiftDTO, ShiftFilter};
+use crate::models::skills::{SkillRequirement, SkillRequirementsDTO};
+use crate::permissions::Permission;
+use crate::response::match_err_response;
+use crate::utils::TokenClaims;
+
+pub async fn find_all(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, filter: web::Query<ShiftFilter>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Shift::find_all(filter.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn get_by_id(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, shift_id: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Shift::find(shift_id.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn create(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<ShiftDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Shift::new(payload.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn update(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, shift_id: web::Path<i32>, payload: web::Json<ShiftDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Shift::update(shift_id.into_inner(), payload.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn delete(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, shift_id: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Shift::delete(shift_id.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn get_breaks(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, shift_id: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        ShiftBreak::find_by_shift(shift_id.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn set_breaks(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, shift_id: web::Path<i32>, payload: web::Json<ShiftBreaksDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        ShiftBreak::set_for_shift(shift_id.into_inner(), payload.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn get_skills(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, shift_id: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        SkillRequirement::find_by_shift(shift_id.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn set_skills(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, shift_id: web::Path<i32>, payload: web::Json<SkillRequirementsDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        SkillRequirement::set_for_shift(shift_id.into_inner(), payload.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/shift").wrap(middleware::jwt::JWTAuth)
+        .route("/", web::get().to(find_all).wrap(middleware::permission::RequirePermission(Permission::ViewSchedules)))
+        .route("/", web::post().to(create).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)))
+        .route("/{id}", web::get().to(get_by_id).wrap(middleware::permission::RequirePermission(Permission::ViewSchedules)))
+        .route("/{id}", web::put().to(update).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)))
+        .route("/{id}", web::delete().to(delete).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)))
+        .route("/{id}/breaks", web::get().to(get_breaks).wrap(middleware::permission::RequirePermission(Permission::ViewSchedules)))
+        .route("/{id}/breaks", web::put().to(set_breaks).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)))
+        .route("/{id}/skills", web::get().to(get_skills).wrap(middleware::permission::RequirePermission(Permission::ViewSchedules)))
+        .route("/{id}/skills", web::put().to(set_skills).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)));
+    conf.service(scope);
+}
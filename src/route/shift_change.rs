@@ -1,30 +1,84 @@
 use actix_web::{Error, HttpResponse, web};
 use crate::config::postgres::DbPool;
 use crate::middleware;
-use crate::models::shift_changes::{ShiftChange, ShiftChangeDTO};
+use crate::permissions::Permission;
+use crate::models::approval_policies::{ApprovalPolicy, ApprovalPolicyDTO};
+use crate::models::shift_changes::{ShiftChange, ShiftChangeDTO, ShiftChangeFilter};
 use crate::response::match_err_response;
+use crate::utils::TokenClaims;
 
-pub async fn create(pool: web::Data<DbPool>, payload: web::Json<ShiftChangeDTO>) -> Result<HttpResponse, Error> {
+pub async fn create(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<ShiftChangeDTO>) -> Result<HttpResponse, Error> {
     let result = web::block(move || {
         let mut conn = pool.get()?;
         //add service module later
-        ShiftChange::new(payload.into_inner(), &mut conn)
+        ShiftChange::new(payload.into_inner(), &claims, &mut conn)
     }).await?.map_err(actix_web::error::ErrorInternalServerError);
     match_err_response(result)
 }
 
-pub async fn verify(pool: web::Data<DbPool>, shift_change_id: web::Path<i32>) ->  Result<HttpResponse, Error> {
+pub async fn verify(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, shift_change_id: web::Path<i32>) ->  Result<HttpResponse, Error> {
+    let org = claims.org;
     let result = web::block(move || {
         let mut conn = pool.get()?;
         //add service module later
-        ShiftChange::verify_change(shift_change_id.into_inner(), &mut conn)
+        ShiftChange::verify_change(shift_change_id.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn find_all(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, filter: web::Query<ShiftChangeFilter>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        ShiftChange::find_all(filter.into_inner(), &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn consent(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, shift_change_id: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        ShiftChange::consent(shift_change_id.into_inner(), &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn get_policies(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        ApprovalPolicy::find_all(org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn create_policy(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<ApprovalPolicyDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        ApprovalPolicy::new(payload.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn update_policy(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, policy_id: web::Path<i32>, payload: web::Json<ApprovalPolicyDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        ApprovalPolicy::update(policy_id.into_inner(), payload.into_inner(), org, &mut conn)
     }).await?.map_err(actix_web::error::ErrorInternalServerError);
     match_err_response(result)
 }
 
 pub fn config(conf: &mut web::ServiceConfig) {
     let scope = web::scope("/shift_change").wrap(middleware::jwt::JWTAuth)
+        .route("/", web::get().to(find_all))
         .route("/", web::post().to(create))
-        .route("/verify/{id}", web::get().to(verify).wrap(middleware::is_manager::IsManager));
+        .route("/verify/{id}", web::get().to(verify).wrap(middleware::permission::RequirePermission(Permission::ReviewShiftChanges)))
+        .route("/consent/{id}", web::post().to(consent))
+        .route("/policies", web::get().to(get_policies).wrap(middleware::permission::RequirePermission(Permission::ManageApprovalPolicies)))
+        .route("/policies", web::post().to(create_policy).wrap(middleware::permission::RequirePermission(Permission::ManageApprovalPolicies)))
+        .route("/policies/{id}", web::put().to(update_policy).wrap(middleware::permission::RequirePermission(Permission::ManageApprovalPolicies)));
     conf.service(scope);
 }
@@ -0,0 +1,72 @@
+use actix_web::{Error, HttpResponse, web};
+use crate::config::postgres::DbPool;
+use crate::middleware;
+use crate::models::skills::{EmployeeSkillsDTO, Skill, SkillDTO};
+use crate::permissions::Permission;
+use crate::response::match_err_response;
+use crate::utils::TokenClaims;
+
+pub async fn find_all(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Skill::find_all(org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn create(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<SkillDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Skill::new(payload.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn update(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, skill_id: web::Path<i32>, payload: web::Json<SkillDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Skill::update(skill_id.into_inner(), payload.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn delete(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, skill_id: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Skill::delete(skill_id.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn get_employee_skills(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, employee_id: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Skill::find_by_employee(employee_id.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn set_employee_skills(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, employee_id: web::Path<i32>, payload: web::Json<EmployeeSkillsDTO>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Skill::set_for_employee(employee_id.into_inner(), payload.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/skill").wrap(middleware::jwt::JWTAuth)
+        .route("/", web::get().to(find_all).wrap(middleware::permission::RequirePermission(Permission::ViewSchedules)))
+        .route("/", web::post().to(create).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)))
+        .route("/employee/{id}", web::get().to(get_employee_skills).wrap(middleware::permission::RequirePermission(Permission::ViewSchedules)))
+        .route("/employee/{id}", web::put().to(set_employee_skills).wrap(middleware::permission::RequirePermission(Permission::ManageEmployees)))
+        .route("/{id}", web::put().to(update).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)))
+        .route("/{id}", web::delete().to(delete).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)));
+    conf.service(scope);
+}
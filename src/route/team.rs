@@ -0,0 +1,72 @@
+use actix_web::{Error, HttpResponse, web};
+use crate::config::postgres::DbPool;
+use crate::middleware;
+use crate::models::teams::{Team, TeamDTO, TeamMembersDTO, TeamShiftsDTO};
+use crate::permissions::Permission;
+use crate::response::match_err_response;
+use crate::utils::TokenClaims;
+
+pub async fn find_all(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Team::find_all(org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn get_by_id(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, team_id: web::Path<i32>) -> Result<HttpResponse, Error> {
+    let org = claims.org;
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Team::detail(team_id.into_inner(), org, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn create(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, payload: web::Json<TeamDTO>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Team::new(payload.into_inner(), &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn update(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, team_id: web::Path<i32>, payload: web::Json<TeamDTO>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Team::update(team_id.into_inner(), payload.into_inner(), &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn set_members(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, team_id: web::Path<i32>, payload: web::Json<TeamMembersDTO>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Team::set_members(team_id.into_inner(), payload.into_inner(), &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub async fn set_shifts(pool: web::Data<DbPool>, claims: web::ReqData<TokenClaims>, team_id: web::Path<i32>, payload: web::Json<TeamShiftsDTO>) -> Result<HttpResponse, Error> {
+    let claims = claims.into_inner();
+    let result = web::block(move || {
+        let mut conn = pool.get()?;
+        Team::set_shifts(team_id.into_inner(), payload.into_inner(), &claims, &mut conn)
+    }).await?.map_err(actix_web::error::ErrorInternalServerError);
+    match_err_response(result)
+}
+
+pub fn config(conf: &mut web::ServiceConfig) {
+    let scope = web::scope("/team").wrap(middleware::jwt::JWTAuth)
+        .route("/", web::get().to(find_all).wrap(middleware::permission::RequirePermission(Permission::ViewSchedules)))
+        .route("/", web::post().to(create).wrap(middleware::permission::RequirePermission(Permission::ManageEmployees)))
+        .route("/{id}", web::get().to(get_by_id).wrap(middleware::permission::RequirePermission(Permission::ViewSchedules)))
+        .route("/{id}", web::put().to(update).wrap(middleware::permission::RequirePermission(Permission::ManageEmployees)))
+        .route("/{id}/members", web::put().to(set_members).wrap(middleware::permission::RequirePermission(Permission::ManageEmployees)))
+        .route("/{id}/shifts", web::put().to(set_shifts).wrap(middleware::permission::RequirePermission(Permission::ManageSchedules)));
+    conf.service(scope);
+}
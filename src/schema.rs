@@ -1,5 +1,35 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    approval_policies (id) {
+        id -> Int4,
+        name -> Text,
+        same_shift_type -> Bool,
+        require_consent -> Bool,
+        require_no_violations -> Bool,
+        min_hours_ahead -> Nullable<Int4>,
+        active -> Bool,
+        organization_id -> Int4,
+    }
+}
+
+diesel::table! {
+    calendar_feeds (id) {
+        id -> Int4,
+        employee_id -> Int4,
+        token_hash -> Text,
+        created_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+    }
+}
+
+diesel::table! {
+    employee_skills (employee_id, skill_id) {
+        employee_id -> Int4,
+        skill_id -> Int4,
+    }
+}
+
 diesel::table! {
     employees (id) {
         id -> Int4,
@@ -10,6 +40,117 @@ diesel::table! {
         department -> Nullable<Text>,
         role -> Text,
         availability -> Nullable<Json>,
+        active -> Bool,
+        deactivated_at -> Nullable<Timestamp>,
+        anonymized_at -> Nullable<Timestamp>,
+        organization_id -> Int4,
+        time_zone -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    holidays (id) {
+        id -> Int4,
+        organization_id -> Int4,
+        region -> Nullable<Text>,
+        date -> Date,
+        name -> Text,
+        staffing -> Nullable<Json>,
+        pay_multiplier -> Float8,
+    }
+}
+
+diesel::table! {
+    leave_balance_history (id) {
+        id -> Int4,
+        entitlement_id -> Int4,
+        kind -> Text,
+        change_days -> Float8,
+        balance_after -> Float8,
+        period -> Nullable<Date>,
+        note -> Nullable<Text>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    leave_entitlements (id) {
+        id -> Int4,
+        employee_id -> Int4,
+        leave_type -> Text,
+        accrual_per_month -> Float8,
+        carry_over_limit -> Nullable<Float8>,
+        balance -> Float8,
+    }
+}
+
+diesel::table! {
+    leave_requests (id) {
+        id -> Int4,
+        employee_id -> Int4,
+        leave_type -> Text,
+        start_date -> Date,
+        end_date -> Date,
+        reason -> Nullable<Text>,
+        status -> Text,
+        manager_comment -> Nullable<Text>,
+        reviewed_by -> Nullable<Int4>,
+        created_at -> Timestamp,
+        organization_id -> Int4,
+    }
+}
+
+diesel::table! {
+    lockout_events (id) {
+        id -> Int4,
+        kind -> Text,
+        email -> Nullable<Text>,
+        employee_id -> Nullable<Int4>,
+        ip -> Nullable<Text>,
+        actor_id -> Nullable<Int4>,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    login_attempts (id) {
+        id -> Int4,
+        email -> Text,
+        ip -> Nullable<Text>,
+        success -> Bool,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    organization_signups (id) {
+        id -> Int4,
+        ip -> Text,
+        created_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    organizations (id) {
+        id -> Int4,
+        name -> Text,
+        created_at -> Timestamp,
+        time_zone -> Text,
+        on_call_with_shift -> Bool,
+        on_call_max_per_month -> Nullable<Int4>,
+        on_call_pay_factor -> Float8,
+    }
+}
+
+diesel::table! {
+    password_reset_tokens (id) {
+        id -> Int4,
+        employee_id -> Int4,
+        token_hash -> Text,
+        expires_at -> Timestamp,
+        used_at -> Nullable<Timestamp>,
+        issued_by -> Nullable<Int4>,
+        created_at -> Timestamp,
     }
 }
 
@@ -20,6 +161,31 @@ diesel::table! {
         data -> Date,
         shift_id -> Int4,
         note -> Nullable<Text>,
+        team_id -> Nullable<Int4>,
+        organization_id -> Int4,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Int4,
+        employee_id -> Int4,
+        refresh_token_hash -> Text,
+        previous_token_hash -> Nullable<Text>,
+        expires_at -> Timestamp,
+        revoked_at -> Nullable<Timestamp>,
+        created_at -> Timestamp,
+        last_used_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    shift_breaks (id) {
+        id -> Int4,
+        shift_id -> Int4,
+        start_offset_minutes -> Int4,
+        length_minutes -> Int4,
+        paid -> Bool,
     }
 }
 
@@ -29,6 +195,12 @@ diesel::table! {
         scheduler_id -> Int4,
         reason -> Nullable<Text>,
         status -> Nullable<Text>,
+        requested_by -> Nullable<Int4>,
+        created_at -> Timestamp,
+        swap_scheduler_id -> Nullable<Int4>,
+        counterpart_consent -> Bool,
+        approved_by_policy -> Nullable<Int4>,
+        organization_id -> Int4,
     }
 }
 
@@ -36,20 +208,117 @@ diesel::table! {
     shifts (id) {
         id -> Int4,
         name -> Text,
-        start_time -> Int4,
-        end_time -> Int4,
-        duration -> Nullable<Int4>,
+        start_time -> Time,
+        end_time -> Time,
         minium_attendences -> Nullable<Int4>,
+        organization_id -> Int4,
+        archived_at -> Nullable<Timestamp>,
+        duration_minutes -> Int4,
+        crosses_midnight -> Bool,
+        category -> Text,
+    }
+}
+
+diesel::table! {
+    skill_requirements (id) {
+        id -> Int4,
+        shift_id -> Int4,
+        skill_id -> Int4,
+        min_count -> Int4,
+        date -> Nullable<Date>,
+    }
+}
+
+diesel::table! {
+    skills (id) {
+        id -> Int4,
+        organization_id -> Int4,
+        name -> Text,
+    }
+}
+
+diesel::table! {
+    team_members (team_id, employee_id) {
+        team_id -> Int4,
+        employee_id -> Int4,
+    }
+}
+
+diesel::table! {
+    team_shifts (team_id, code) {
+        team_id -> Int4,
+        code -> Text,
+        shift_id -> Int4,
+    }
+}
+
+diesel::table! {
+    teams (id) {
+        id -> Int4,
+        name -> Text,
+        manager_id -> Nullable<Int4>,
+        created_at -> Timestamp,
+        organization_id -> Int4,
+        time_zone -> Nullable<Text>,
+        region -> Nullable<Text>,
     }
 }
 
+diesel::joinable!(approval_policies -> organizations (organization_id));
+diesel::joinable!(calendar_feeds -> employees (employee_id));
+diesel::joinable!(employee_skills -> employees (employee_id));
+diesel::joinable!(employee_skills -> skills (skill_id));
+diesel::joinable!(employees -> organizations (organization_id));
+diesel::joinable!(holidays -> organizations (organization_id));
+diesel::joinable!(leave_balance_history -> leave_entitlements (entitlement_id));
+diesel::joinable!(leave_entitlements -> employees (employee_id));
+diesel::joinable!(leave_requests -> employees (employee_id));
+diesel::joinable!(leave_requests -> organizations (organization_id));
+diesel::joinable!(lockout_events -> employees (employee_id));
+diesel::joinable!(password_reset_tokens -> employees (employee_id));
 diesel::joinable!(schedules -> employees (employee_id));
+diesel::joinable!(sessions -> employees (employee_id));
+diesel::joinable!(schedules -> organizations (organization_id));
 diesel::joinable!(schedules -> shifts (shift_id));
+diesel::joinable!(schedules -> teams (team_id));
+diesel::joinable!(shift_breaks -> shifts (shift_id));
+diesel::joinable!(shift_changes -> approval_policies (approved_by_policy));
+diesel::joinable!(shift_changes -> employees (requested_by));
+diesel::joinable!(shift_changes -> organizations (organization_id));
 diesel::joinable!(shift_changes -> schedules (scheduler_id));
+diesel::joinable!(shifts -> organizations (organization_id));
+diesel::joinable!(skill_requirements -> shifts (shift_id));
+diesel::joinable!(skill_requirements -> skills (skill_id));
+diesel::joinable!(skills -> organizations (organization_id));
+diesel::joinable!(team_members -> employees (employee_id));
+diesel::joinable!(team_members -> teams (team_id));
+diesel::joinable!(team_shifts -> shifts (shift_id));
+diesel::joinable!(team_shifts -> teams (team_id));
+diesel::joinable!(teams -> employees (manager_id));
+diesel::joinable!(teams -> organizations (organization_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    approval_policies,
+    calendar_feeds,
+    employee_skills,
     employees,
+    holidays,
+    leave_balance_history,
+    leave_entitlements,
+    leave_requests,
+    lockout_events,
+    login_attempts,
+    organization_signups,
+    organizations,
+    password_reset_tokens,
     schedules,
+    sessions,
+    shift_breaks,
     shift_changes,
     shifts,
+    skill_requirements,
+    skills,
+    team_members,
+    team_shifts,
+    teams,
 );
@@ -1,128 +1,158 @@
+use std::cmp::Reverse;
 use std::collections::HashMap;
-use chrono::{DateTime, Duration, Utc};
-use jsonwebtoken::{encode, Header};
+use chrono::{DateTime, Datelike, Duration, Months, NaiveDate, Utc};
+use chrono_tz::Tz;
 use rand::prelude::SliceRandom;
 use serde::{Deserialize, Serialize};
-use crate::models::schedule::{AutoScheduleDTO, DayDetail, ShiftDetail};
+use crate::config::jwt::jwt_config;
+use crate::constants;
+use crate::models::schedule::{AutoScheduleDTO, DayDetail, OnCallPlan, ShiftDetail};
+use crate::models::shifts::ShiftWindow;
+use crate::models::skills::SkillRules;
+use crate::models::teams::{ON_CALL_SLOT, SHIFT_SLOTS};
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct TokenClaims {
     pub sub: i32,
     pub role: String,
+    // organization of the employee, every query is scoped to it
+    pub org: i32,
+    // login session the token belongs to, see models::sessions
+    pub sid: i32,
     pub iat: usize,
     pub exp: usize,
 }
 
-pub fn generate_token(id: i32, role: String, _now : DateTime<Utc>) ->  Result<String, jsonwebtoken::errors::Error> {
+pub fn generate_token(id: i32, role: String, org: i32, sid: i32, _now : DateTime<Utc>) ->  Result<String, jsonwebtoken::errors::Error> {
     let iat = _now.timestamp() as usize;
-    let exp = (_now + Duration::minutes(2000)).timestamp() as usize;
+    let exp = (_now + Duration::minutes(jwt_config().expiry_minutes)).timestamp() as usize;
     let claims = TokenClaims {
         sub: id,
         role,
+        org,
+        sid,
         iat,
         exp
     };
-    let token = encode(
-        &Header::new(jsonwebtoken::Algorithm::RS256),
-        &claims,
-        &jsonwebtoken::EncodingKey::from_rsa_pem(include_bytes!("private_key.pem"))?
-    );
-    token
+    jwt_config().encode(&claims)
 }
 
 pub fn verify_jwt_token(
     token: String
 ) -> Result<TokenClaims, jsonwebtoken::errors::Error> {
-    let validation = jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::RS256);
-
-    let decoded = jsonwebtoken::decode::<TokenClaims>(
-        token.as_str(),
-        &jsonwebtoken::DecodingKey::from_rsa_pem(include_bytes!("public_key.pem"))?,
-        &validation,
-    );
-
-    match decoded {
-        Ok(c) => Ok(c.claims),
-        Err(e) => return Err(e.into())
-    }
+    jwt_config().decode(token.as_str())
 }
 
-pub fn create_sample_schedule(auto_schedule_dto: &AutoScheduleDTO) -> Result<Vec<DayDetail>, String> {
+/*
+    `on_leave` holds the (day of month, employee id) pairs of approved leave,
+    those employees are not rostered on those days.
+    `windows` gives the times of the shift behind each slot, in time zone `tz`. Employees who haven't rested
+    constants::MIN_REST_HOURS since their last shift, or whose net hours would go over `max_hours`, are picked last.
+    `holidays` maps the days of month that are holidays to the staffing of the slots they override,
+    holiday shifts go first to whoever has worked the fewest of them.
+    Each slot first gets the holders of the skills it requires (see `skills`), employees whose skills
+    a later slot of the day requires are kept for it.
+    With an `on_call` plan every day ends with its on-call duty (ON_CALL_SLOT), given to whoever has had
+    the fewest so far. On-call time doesn't count towards the hours or the rest between shifts
+ */
+pub fn create_sample_schedule(auto_schedule_dto: &AutoScheduleDTO, on_leave: &HashMap<(i32, i32), &str>,
+                              windows: &HashMap<String, ShiftWindow>, tz: Tz,
+                              holidays: &HashMap<i32, HashMap<String, i32>>, skills: &SkillRules,
+                              on_call: Option<&OnCallPlan>) -> Result<Vec<DayDetail>, String> {
     let month = &auto_schedule_dto.month;
     let _year = &auto_schedule_dto.year;
     let employees: Vec<i32> = auto_schedule_dto.employees.clone();
-    let days_in_month = match month {
-        1|3|5|7|8|10|12 => 31,
-        4|6|9|11 => 30,
-        2 => 28,
-        _ => return Err("Invalid Month".to_string())
-    };
-    let mut map : HashMap<i32, i32> = HashMap::new();
-    for i in &employees {
-        map.insert(*i, 0);
-    }
+    let days_in_month = last_day_of_month(*month, *_year).ok_or("Invalid Month")?.day() as i32;
     let mut rs: Vec<DayDetail> = Vec::new();
-    let mut recent_night_employees: Vec<i32> = Vec::new();
+    let mut last_shift_end: HashMap<i32, DateTime<Utc>> = HashMap::new();
+    let mut worked: HashMap<i32, Duration> = HashMap::new();
+    let mut holiday_shifts: HashMap<i32, i32> = HashMap::new();
+    let mut on_call_duties: HashMap<i32, i32> = HashMap::new();
     for i in 1..=days_in_month {
+        let date = NaiveDate::from_ymd_opt(*_year, *month as u32, i as u32).ok_or("Invalid Year")?;
         let mut shift_detail_in_day : Vec<ShiftDetail> = Vec::new();
-        let mut employees_temp = employees.clone();
+        let mut employees_temp: Vec<i32> = employees.iter().filter(|e| !on_leave.contains_key(&(i, **e))).cloned().collect();
         let mut rng = rand::thread_rng();
         employees_temp.shuffle(&mut rng);
-        let mut number_of_employees_in_day = vec![1,1,1];
-        let office_hour_employees_count = &auto_schedule_dto.nums_h;
-        number_of_employees_in_day.push(*office_hour_employees_count);
-        let mut shifts_indx = 0;
-        for shifts in vec!["S", "C", "D", "H"] {
+        let number_of_employees_in_day = staffing_of_day(auto_schedule_dto, holidays.get(&i));
+        let is_holiday = holidays.contains_key(&i);
+        if employees_temp.len() < number_of_employees_in_day.iter().sum::<i32>() as usize {
+            return Err(format!("Not enough available employees on day {}", i))
+        }
+        for (shifts_indx, shifts) in SHIFT_SLOTS.into_iter().enumerate() {
+            let window = windows.get(shifts).ok_or(format!("No shift for slot {}", shifts))?;
+            let (starts_at, ends_at) = window.instants(date, tz);
+            let net = window.net_duration(date, tz);
+            let later_slots = &SHIFT_SLOTS[shifts_indx + 1..];
+            // employees who can take the shift go to the end, where they are popped from,
+            // those with the fewest holiday shifts (on holidays) and the least worked last
+            employees_temp.sort_by_key(|e| {
+                let can_take = has_rested(last_shift_end.get(e), starts_at) && within_cap(worked.get(e), net, auto_schedule_dto.max_hours);
+                let holidays_worked = if is_holiday { holiday_shifts.get(e).copied().unwrap_or(0) } else { 0 };
+                (can_take, !skills.needed_in(*e, i, later_slots), Reverse(holidays_worked), Reverse(worked.get(e).copied().unwrap_or(Duration::zero())))
+            });
             let mut employees_in_this_shift: Vec<i32> = Vec::new() ;
-            for _ in 0..number_of_employees_in_day[shifts_indx] {
-                employees_in_this_shift.push(employees_temp.pop().unwrap());
-            }
-            if shifts == "S" {
-                let mut index_remove : Vec<usize> = Vec::new() ;
-                for i in 0..employees_in_this_shift.len() {
-                    let e  = employees_in_this_shift[i];
-                    if recent_night_employees.contains(&e) {
-                        index_remove.push(i);
-                        let mut x = employees_temp.len() - 1;
-                        let mut precheck ;
-                        while x > 0 {
-                            precheck = employees_temp[x];
-                            match recent_night_employees.contains(&precheck) {
-                                true => {
-                                    x -= 1;
-                                }
-                                false => {
-                                    employees_in_this_shift.push(precheck);
-                                    employees_temp.remove(x);
-                                    employees_temp.push(e);
-                                    break;
-                                }
-                            }
-                        }
+            // a slot closed that day (e.g. by a holiday) requires no skills
+            let required_skills = match number_of_employees_in_day[shifts_indx] {
+                0 => vec![],
+                _ => skills.required(i, shifts)
+            };
+            for (skill, count) in required_skills {
+                let mut holders = employees_in_this_shift.iter().filter(|e| skills.has_skill(**e, skill)).count() as i32;
+                // the best placed holder is the last one, a slot left short fails verify_valid_schedule
+                while holders < count {
+                    match employees_temp.iter().rposition(|e| skills.has_skill(*e, skill)) {
+                        Some(holder) => employees_in_this_shift.push(employees_temp.remove(holder)),
+                        None => break
                     }
+                    holders += 1;
                 }
-                index_remove.sort();
-                index_remove.reverse();
-                for x in index_remove {
-                    employees_in_this_shift.remove(x);
+            }
+            while (employees_in_this_shift.len() as i32) < number_of_employees_in_day[shifts_indx] {
+                match employees_temp.pop() {
+                    Some(employee) => employees_in_this_shift.push(employee),
+                    None => break
                 }
             }
             for x in &employees_in_this_shift {
-                if let Some(count) = map.get(&x) {
-                    map.insert(*x, count + 1);
-                } else {
-                    map.insert(*x, 0);
-                };
+                last_shift_end.insert(*x, ends_at);
+                let total = worked.get(x).copied().unwrap_or(Duration::zero()) + net;
+                worked.insert(*x, total);
+                if is_holiday {
+                    *holiday_shifts.entry(*x).or_insert(0) += 1;
+                }
             }
             let detail_shift = ShiftDetail {
                 key: shifts.to_string(),
                 value: employees_in_this_shift.clone(),
             };
             shift_detail_in_day.push(detail_shift);
-            if shifts == "D" {
-                recent_night_employees = employees_in_this_shift.clone();
+        }
+        if let Some(on_call) = on_call {
+            let working_today: Vec<i32> = shift_detail_in_day.iter().flat_map(|shift| shift.value.clone()).collect();
+            let mut candidates: Vec<i32> = employees.iter()
+                .filter(|e| !on_leave.contains_key(&(i, **e)))
+                .filter(|e| on_call.with_shift || !working_today.contains(e))
+                .filter(|e| on_call.max_per_month.is_none_or(|max| on_call_duties.get(e).copied().unwrap_or(0) < max))
+                .cloned()
+                .collect();
+            candidates.shuffle(&mut rng);
+            // the fewest duties last, where they are popped from, those off work that day first
+            candidates.sort_by_key(|e| (Reverse(on_call_duties.get(e).copied().unwrap_or(0)), !working_today.contains(e)));
+            let mut on_duty: Vec<i32> = Vec::new();
+            while (on_duty.len() as i32) < on_call.per_day {
+                match candidates.pop() {
+                    Some(employee) => on_duty.push(employee),
+                    None => break
+                }
+            }
+            for e in &on_duty {
+                *on_call_duties.entry(*e).or_insert(0) += 1;
             }
-            shifts_indx+=1;
+            shift_detail_in_day.push(ShiftDetail {
+                key: ON_CALL_SLOT.to_string(),
+                value: on_duty,
+            });
         }
         let day_detail = DayDetail {
             day: i,
@@ -131,41 +161,176 @@ pub fn create_sample_schedule(auto_schedule_dto: &AutoScheduleDTO) -> Result<Vec
 
         rs.push(day_detail);
     }
-    println!("{:?}", map);
-    println!("{:?}", rs);
-    println!("{:?}", verify_valid_schedule(&rs));
     Ok(rs)
 }
 
-pub fn verify_valid_schedule(input : &Vec<DayDetail>) -> bool {
-    let mut recent_night_employees: Vec<i32> = Vec::new();
-    for day in input {
-        let day_value = day.value.clone();
-        //Because there is only 1 shift in the DayDetail have the ShiftDetail name is "S"
-        let shift_filter = day_value.clone().into_iter().filter(|a| a.key == "S").collect::<Vec<ShiftDetail>>();
-        let morning_shift = &shift_filter[0];
-        let employees_morning: &[i32] = &morning_shift.value.clone();
-        /*
-            This is a O n^2 time complexity
-            Although it is a bad algorithm on avarage,
-            But since size of employees in each shifts is quite small (<10),
-            this is the best algo to check 2 vector's elements
-            Refs: https://stackoverflow.com/questions/64226562/check-if-vec-contains-all-elements-from-another-vec
-        */
-        if employees_morning.iter().any(|item| recent_night_employees.contains(item)) {
-            return false;
-        }
-        let night_shift = day_value.clone().into_iter().filter(|a| a.key == "D").collect::<Vec<ShiftDetail>>();
-        let night_shift = &night_shift[0];
-        recent_night_employees = night_shift.value.clone();
-    }
+// Employees per slot (in SHIFT_SLOTS order) on a day, a holiday may override some slots
+pub fn staffing_of_day(auto_schedule_dto: &AutoScheduleDTO, holiday: Option<&HashMap<String, i32>>) -> Vec<i32> {
+    let defaults = [1, 1, 1, auto_schedule_dto.nums_h];
+    SHIFT_SLOTS.iter().zip(defaults)
+        .map(|(slot, default)| holiday.and_then(|staffing| staffing.get(*slot).copied()).unwrap_or(default))
+        .collect()
+}
+
+// Whether net hours already `worked` plus a shift of `net` stay within `max_hours`
+fn within_cap(worked: Option<&Duration>, net: Duration, max_hours: Option<i64>) -> bool {
+    max_hours.is_none_or(|max_hours| worked.copied().unwrap_or(Duration::zero()) + net <= Duration::hours(max_hours))
+}
+
+// Whether the rest since a shift ending at `last_end` is long enough to start one at `starts_at`
+fn has_rested(last_end: Option<&DateTime<Utc>>, starts_at: DateTime<Utc>) -> bool {
+    last_end.is_none_or(|last_end| starts_at - *last_end >= Duration::hours(constants::MIN_REST_HOURS))
+}
 
+/*
+    Every slot is staffed (as a holiday asks, if any) with the skills it requires, nobody works twice a day,
+    everyone rests constants::MIN_REST_HOURS between the real end and start of their shifts
+    and stays within the cap of net hours, if any.
+    On-call duties follow the rules of the `on_call` plan
+ */
+#[allow(clippy::needless_return)]
+pub fn verify_valid_schedule(input : &Vec<DayDetail>, auto_schedule_dto: &AutoScheduleDTO, windows: &HashMap<String, ShiftWindow>, tz: Tz,
+                             holidays: &HashMap<i32, HashMap<String, i32>>, skills: &SkillRules, on_call: Option<&OnCallPlan>) -> bool {
+    let mut last_shift_end: HashMap<i32, DateTime<Utc>> = HashMap::new();
+    let mut worked: HashMap<i32, Duration> = HashMap::new();
+    let mut on_call_duties: HashMap<i32, i32> = HashMap::new();
     for day in input {
-        for shift in &day.value {
-            if shift.value.is_empty() {
+        let date = match NaiveDate::from_ymd_opt(auto_schedule_dto.year, auto_schedule_dto.month as u32, day.day as u32) {
+            Some(date) => date,
+            None => return false
+        };
+        let mut working_today: Vec<i32> = Vec::new();
+        let staffing = staffing_of_day(auto_schedule_dto, holidays.get(&day.day));
+        for shift in day.value.iter().filter(|shift| shift.key != ON_CALL_SLOT) {
+            let window = match windows.get(&shift.key) {
+                Some(window) => window,
+                None => return false
+            };
+            let required = SHIFT_SLOTS.iter().position(|slot| *slot == shift.key).map_or(1, |slot| staffing[slot]);
+            // nobody works a slot closed that day, and it needs no skills
+            if required == 0 {
+                if !shift.value.is_empty() {
+                    return false;
+                }
+                continue;
+            }
+            if (shift.value.len() as i32) < required || !skills.shortfalls(day.day, &shift.key, &shift.value).is_empty() {
+                return false;
+            }
+            let net = window.net_duration(date, tz);
+            for employee in &shift.value {
+                if working_today.contains(employee) || !has_rested(last_shift_end.get(employee), window.instants(date, tz).0)
+                    || !within_cap(worked.get(employee), net, auto_schedule_dto.max_hours) {
+                    return false;
+                }
+                working_today.push(*employee);
+                let total = worked.get(employee).copied().unwrap_or(Duration::zero()) + net;
+                worked.insert(*employee, total);
+            }
+        }
+        if let Some(on_call) = on_call {
+            let on_duty: &[i32] = day.value.iter().find(|shift| shift.key == ON_CALL_SLOT).map_or(&[], |shift| &shift.value);
+            if (on_duty.len() as i32) < on_call.per_day {
                 return false;
             }
+            for (i, employee) in on_duty.iter().enumerate() {
+                let duties = on_call_duties.get(employee).copied().unwrap_or(0) + 1;
+                if on_duty[..i].contains(employee) || (!on_call.with_shift && working_today.contains(employee))
+                    || on_call.max_per_month.is_some_and(|max| duties > max) {
+                    return false;
+                }
+                on_call_duties.insert(*employee, duties);
+            }
+        }
+        for shift in day.value.iter().filter(|shift| shift.key != ON_CALL_SLOT) {
+            let (_, ends_at) = windows[&shift.key].instants(date, tz);
+            for employee in &shift.value {
+                last_shift_end.insert(*employee, ends_at);
+            }
         }
     }
     return true;
-}
\ No newline at end of file
+}
+/*
+    Same rest rule as verify_valid_schedule, for a single assignment of `window` on `date`:
+    at most one shift a day and constants::MIN_REST_HOURS between the end of a shift and the start of the next.
+    `neighbours` are the other assignments of the employee around `date`, with the time zone of their team
+ */
+pub fn breaks_rest_rule(date: NaiveDate, window: ShiftWindow, tz: Tz, neighbours: &[(NaiveDate, ShiftWindow, Tz)]) -> bool {
+    let min_rest = Duration::hours(constants::MIN_REST_HOURS);
+    let (starts_at, ends_at) = window.instants(date, tz);
+    neighbours.iter().any(|(day, other, other_tz)| {
+        let (other_starts_at, other_ends_at) = other.instants(*day, *other_tz);
+        *day == date
+            || (starts_at < other_ends_at + min_rest && other_starts_at < ends_at + min_rest)
+    })
+}
+
+// Last day of a month, February 29 in leap years
+pub fn last_day_of_month(month: i32, year: i32) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(year, u32::try_from(month).ok()?, 1)?
+        .checked_add_months(Months::new(1))
+        .and_then(|date| date.pred_opt())
+}
+
+// An IANA time zone name such as "Europe/Berlin"
+pub fn parse_time_zone(name: &str) -> Result<Tz, String> {
+    name.parse::<Tz>().map_err(|_| format!("Unknown time zone '{}'", name))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{NaiveDate, NaiveTime};
+    use chrono_tz::Tz;
+    use crate::models::shifts::ShiftWindow;
+    use crate::utils::{breaks_rest_rule, last_day_of_month, parse_time_zone};
+
+    fn window(start: u32, end: u32) -> ShiftWindow {
+        ShiftWindow::new(NaiveTime::from_hms_opt(start, 0, 0).unwrap(), NaiveTime::from_hms_opt(end, 0, 0).unwrap())
+    }
+
+    const UTC: Tz = Tz::UTC;
+
+    #[test]
+    fn test_rest_after_night_shift() {
+        let night = window(22, 6);
+        let day = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        let next = NaiveDate::from_ymd_opt(2024, 1, 11).unwrap();
+        // 06:00 to 06:00, then 06:00 to 08:00
+        assert!(breaks_rest_rule(next, window(6, 14), UTC, &[(day, night, UTC)]));
+        assert!(breaks_rest_rule(next, window(8, 18), UTC, &[(day, night, UTC)]));
+        // exactly the minimum rest
+        assert!(!breaks_rest_rule(next, window(14, 22), UTC, &[(day, night, UTC)]));
+        // looking back from the night shift
+        assert!(breaks_rest_rule(day, night, UTC, &[(next, window(6, 14), UTC)]));
+    }
+
+    #[test]
+    fn test_one_shift_a_day() {
+        let day = NaiveDate::from_ymd_opt(2024, 1, 10).unwrap();
+        assert!(breaks_rest_rule(day, window(22, 6), UTC, &[(day, window(6, 14), UTC)]));
+    }
+
+    #[test]
+    fn test_rest_across_dst_change() {
+        let berlin = parse_time_zone("Europe/Berlin").unwrap();
+        let night = window(22, 6);
+        // the 9 hour night shift still ends at 06:00 on the wall clock
+        let day = NaiveDate::from_ymd_opt(2024, 10, 26).unwrap();
+        let next = NaiveDate::from_ymd_opt(2024, 10, 27).unwrap();
+        assert!(!breaks_rest_rule(next, window(14, 22), berlin, &[(day, night, berlin)]));
+        // 06:00 in Berlin is 05:00 UTC that morning, a shift of a team on UTC at 12:00 comes 7 hours later
+        assert!(breaks_rest_rule(next, window(12, 20), UTC, &[(day, night, berlin)]));
+        assert!(!breaks_rest_rule(next, window(13, 21), UTC, &[(day, night, berlin)]));
+        assert!(parse_time_zone("Mars/Olympus").is_err());
+    }
+
+    #[test]
+    fn test_last_day_of_month() {
+        assert_eq!(last_day_of_month(2, 2024), NaiveDate::from_ymd_opt(2024, 2, 29));
+        assert_eq!(last_day_of_month(2, 2025), NaiveDate::from_ymd_opt(2025, 2, 28));
+        assert_eq!(last_day_of_month(12, 2024), NaiveDate::from_ymd_opt(2024, 12, 31));
+        assert_eq!(last_day_of_month(13, 2024), None);
+    }
+}